@@ -8,7 +8,6 @@ use uuid::Uuid;
 
 use liberte_shared::crypto::generate_symmetric_key;
 use liberte_shared::invite::InviteToken;
-use liberte_shared::types::ChannelId;
 use liberte_store::Channel;
 
 use crate::state::AppState;
@@ -53,12 +52,9 @@ pub async fn create_channel(
         guard.swarm_cmd_tx.clone()
     };
 
-    // Subscribe to GossipSub topic
+    // Subscribe to the channel's GossipSub topics
     if let Some(tx) = cmd_tx {
-        let topic = ChannelId(channel_id).to_topic();
-        let _ = tx
-            .send(liberte_net::SwarmCommand::SubscribeTopic(topic))
-            .await;
+        crate::swarm_bridge::sync_channel_topics(state.inner(), &tx).await;
     }
 
     info!(channel_id = %channel_id, name = %name, "Channel created");
@@ -142,12 +138,9 @@ pub async fn accept_invite(
         guard.swarm_cmd_tx.clone()
     };
 
-    // Subscribe to GossipSub topic
+    // Subscribe to the channel's GossipSub topics
     if let Some(tx) = cmd_tx {
-        let topic = ChannelId(channel_id).to_topic();
-        let _ = tx
-            .send(liberte_net::SwarmCommand::SubscribeTopic(topic))
-            .await;
+        crate::swarm_bridge::sync_channel_topics(state.inner(), &tx).await;
     }
 
     info!(channel_id = %channel_id, name = %channel_name, "Joined channel via invite");
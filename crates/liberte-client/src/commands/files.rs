@@ -56,7 +56,7 @@ pub async fn send_file(
     let file_id = Uuid::new_v4();
     let timestamp = Utc::now();
 
-    let (sender_pubkey, cmd_tx) = {
+    let (sender_pubkey, cmd_tx, channel_key) = {
         let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
         let identity = guard
             .identity
@@ -66,7 +66,17 @@ pub async fn send_file(
             .swarm_cmd_tx
             .clone()
             .ok_or_else(|| "Swarm not started".to_string())?;
-        (identity.public_key_bytes(), tx)
+        let key_hex = guard
+            .database
+            .as_ref()
+            .ok_or_else(|| "Database not opened".to_string())?
+            .get_channel_key(channel_uuid)
+            .map_err(|e| format!("No key for channel: {e}"))?;
+        let channel_key: [u8; 32] = hex::decode(key_hex)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| "Invalid stored channel key".to_string())?;
+        (identity.public_key_bytes(), tx, channel_key)
     };
 
     let offer = WireMessage::FileOffer(FileOffer {
@@ -80,14 +90,14 @@ pub async fn send_file(
     });
 
     // Encrypt the file offer before publishing (metadata is sensitive)
-    let topic = ChannelId(channel_uuid).to_topic();
+    let topic = liberte_net::current_topic(&channel_key);
     let wire_bytes = offer
         .to_bytes()
         .map_err(|e| format!("Serialization failed: {e}"))?;
 
     // NOTE: File offer is published as plaintext wire message on the
     // channel topic. In a future iteration, this should be encrypted
-    // with the channel key (requires passing channel_key_hex to send_file).
+    // with the channel key as well.
     cmd_tx
         .send(SwarmCommand::PublishMessage {
             topic,
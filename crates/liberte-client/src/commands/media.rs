@@ -23,14 +23,13 @@ pub struct CallState {
     pub mode: String,
 }
 
-/// Publish a WireMessage on the given channel topic, encrypted with the channel key.
+/// Publish a WireMessage on the channel's current topic, encrypted with the channel key.
 fn publish_wire_message(
     cmd_tx: &tokio::sync::mpsc::Sender<SwarmCommand>,
-    channel_id: &str,
     channel_key: &[u8; 32],
     msg: &WireMessage,
 ) {
-    let topic = format!("channel:{channel_id}");
+    let topic = liberte_net::current_topic(channel_key);
     let Ok(plaintext) = msg.to_bytes() else {
         return;
     };
@@ -118,7 +117,7 @@ pub async fn start_call(
         event_type: VoiceEventType::Join,
         timestamp: chrono::Utc::now(),
     });
-    publish_wire_message(&cmd_tx, &channel_id, &channel_key, &join_msg);
+    publish_wire_message(&cmd_tx, &channel_key, &join_msg);
 
     // Spawn voice sender task
     let sender_active = active_flag;
@@ -130,7 +129,6 @@ pub async fn start_call(
 
     tokio::spawn(async move {
         let mut sequence: u32 = 0;
-        let topic = format!("channel:{sender_channel_id}");
         let ch_uuid = uuid::Uuid::parse_str(&sender_channel_id).unwrap();
 
         info!("Voice sender task started");
@@ -178,7 +176,8 @@ pub async fn start_call(
                 if let Ok(ciphertext) = crypto::encrypt(&sender_channel_key, &plaintext) {
                     let _ = sender_cmd_tx
                         .send(SwarmCommand::PublishMessage {
-                            topic: topic.clone(),
+                            // Re-derived per frame so long calls follow topic rotation
+                            topic: liberte_net::current_media_topic(&sender_channel_key),
                             data: ciphertext,
                         })
                         .await;
@@ -271,7 +270,7 @@ pub async fn end_call(state: State<'_, Arc<Mutex<AppState>>>) -> Result<CallStat
             event_type: VoiceEventType::Leave,
             timestamp: chrono::Utc::now(),
         });
-        publish_wire_message(&cmd_tx, channel_id, &channel_key, &leave_msg);
+        publish_wire_message(&cmd_tx, &channel_key, &leave_msg);
     }
 
     // Emit event
@@ -357,7 +356,7 @@ pub async fn toggle_mute(state: State<'_, Arc<Mutex<AppState>>>) -> Result<bool,
             event_type,
             timestamp: chrono::Utc::now(),
         });
-        publish_wire_message(&cmd_tx, channel_id, &channel_key, &mute_msg);
+        publish_wire_message(&cmd_tx, &channel_key, &mute_msg);
     }
 
     info!(muted = new_muted, "Mute toggled");
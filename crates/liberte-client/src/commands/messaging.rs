@@ -174,7 +174,7 @@ pub async fn send_message(
         message_id,
     });
 
-    let topic = ChannelId(channel_uuid).to_topic();
+    let topic = liberte_net::current_topic(&channel_key);
     let wire_bytes = wire_msg
         .to_bytes()
         .map_err(|e| format!("Serialization failed: {e}"))?;
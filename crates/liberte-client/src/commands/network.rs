@@ -1,10 +1,13 @@
 use std::sync::{Arc, Mutex};
 
+use serde::Serialize;
 use tauri::State;
 use tracing::info;
 
-use liberte_net::SwarmCommand;
-use liberte_shared::types::ConnectionMode;
+use liberte_net::{ConnectionInfo, PresenceInfo, QueueStats, SwarmCommand};
+use liberte_shared::types::{ConnectionMode, UserId};
+use libp2p::multiaddr::Protocol;
+use libp2p::PeerId;
 
 use crate::state::AppState;
 
@@ -59,6 +62,313 @@ pub async fn list_peers(state: State<'_, Arc<Mutex<AppState>>>) -> Result<Vec<St
     Ok(peers.iter().map(|p| p.to_string()).collect())
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerStatsDto {
+    pub peer_id: String,
+    pub address: String,
+    pub mode: String,
+    pub connected_at: u64,
+    pub rtt_ms: Option<f64>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl From<ConnectionInfo> for PeerStatsDto {
+    fn from(info: ConnectionInfo) -> Self {
+        let mode = match info.mode {
+            ConnectionMode::Direct => "direct",
+            ConnectionMode::Relayed => "relayed",
+            ConnectionMode::Disconnected => "disconnected",
+        };
+        Self {
+            peer_id: info.peer_id.to_string(),
+            address: info.address.to_string(),
+            mode: mode.to_string(),
+            connected_at: info.connected_at,
+            rtt_ms: info.rtt.map(|d| d.as_secs_f64() * 1000.0),
+            bytes_in: info.bytes_in,
+            bytes_out: info.bytes_out,
+        }
+    }
+}
+
+/// Per-peer latency and traffic, for diagnosing choppy calls or slow relays.
+#[tauri::command]
+pub async fn get_network_diagnostics(
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<PeerStatsDto>, String> {
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        guard
+            .swarm_cmd_tx
+            .clone()
+            .ok_or_else(|| "Swarm not started".to_string())?
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    cmd_tx
+        .send(SwarmCommand::GetStats(reply_tx))
+        .await
+        .map_err(|e| format!("Failed to send GetStats command: {e}"))?;
+
+    let stats = reply_rx
+        .await
+        .map_err(|e| format!("Swarm did not reply: {e}"))?;
+
+    Ok(stats.into_iter().map(PeerStatsDto::from).collect())
+}
+
+fn parse_peer_id(peer_id: &str) -> Result<PeerId, String> {
+    peer_id
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid peer ID: {e}"))
+}
+
+/// Close every connection to a peer without banning it; it may reconnect.
+#[tauri::command]
+pub async fn disconnect_peer(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    peer_id: String,
+) -> Result<bool, String> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        guard
+            .swarm_cmd_tx
+            .clone()
+            .ok_or_else(|| "Swarm not started".to_string())?
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    cmd_tx
+        .send(SwarmCommand::DisconnectPeer {
+            peer_id,
+            reply: reply_tx,
+        })
+        .await
+        .map_err(|e| format!("Failed to send DisconnectPeer command: {e}"))?;
+
+    reply_rx
+        .await
+        .map_err(|e| format!("Swarm did not reply: {e}"))
+}
+
+/// Persist a ban and, if the swarm is running, drop the peer immediately.
+#[tauri::command]
+pub async fn ban_peer(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    peer_id: String,
+    reason: Option<String>,
+) -> Result<(), String> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        let db = guard.database.as_ref().ok_or("No database")?;
+        db.ban_peer(&peer_id.to_string(), reason.as_deref())
+            .map_err(|e| format!("Failed to store ban: {e}"))?;
+        guard.swarm_cmd_tx.clone()
+    };
+
+    info!(peer = %peer_id, "Peer banned");
+
+    if let Some(cmd_tx) = cmd_tx {
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        cmd_tx
+            .send(SwarmCommand::BanPeer {
+                peer_id,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|e| format!("Failed to send BanPeer command: {e}"))?;
+        reply_rx
+            .await
+            .map_err(|e| format!("Swarm did not reply: {e}"))?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unban_peer(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    peer_id: String,
+) -> Result<bool, String> {
+    let peer_id = parse_peer_id(&peer_id)?;
+
+    let (was_banned, cmd_tx) = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        let db = guard.database.as_ref().ok_or("No database")?;
+        let was_banned = db
+            .unban_peer(&peer_id.to_string())
+            .map_err(|e| format!("Failed to remove ban: {e}"))?;
+        (was_banned, guard.swarm_cmd_tx.clone())
+    };
+
+    if let Some(cmd_tx) = cmd_tx {
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        cmd_tx
+            .send(SwarmCommand::UnbanPeer {
+                peer_id,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|e| format!("Failed to send UnbanPeer command: {e}"))?;
+        reply_rx
+            .await
+            .map_err(|e| format!("Swarm did not reply: {e}"))?;
+    }
+
+    Ok(was_banned)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BannedPeerDto {
+    pub peer_id: String,
+    pub reason: Option<String>,
+    pub banned_at: String,
+}
+
+#[tauri::command]
+pub fn list_banned_peers(
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<BannedPeerDto>, String> {
+    let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+    let db = guard.database.as_ref().ok_or("No database")?;
+
+    let bans = db
+        .list_banned_peers()
+        .map_err(|e| format!("Failed to list banned peers: {e}"))?;
+
+    Ok(bans
+        .into_iter()
+        .map(|ban| BannedPeerDto {
+            peer_id: ban.peer_id,
+            reason: ban.reason,
+            banned_at: ban.banned_at.to_rfc3339(),
+        })
+        .collect())
+}
+
+/// Addresses the local node is currently listening on.
+#[tauri::command]
+pub async fn get_listen_addrs(
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<String>, String> {
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        guard
+            .swarm_cmd_tx
+            .clone()
+            .ok_or_else(|| "Swarm not started".to_string())?
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    cmd_tx
+        .send(SwarmCommand::GetListenAddrs(reply_tx))
+        .await
+        .map_err(|e| format!("Failed to send GetListenAddrs command: {e}"))?;
+
+    let addrs = reply_rx
+        .await
+        .map_err(|e| format!("Swarm did not reply: {e}"))?;
+
+    Ok(addrs.iter().map(|a| a.to_string()).collect())
+}
+
+/// Gossipsub topics the local node is subscribed to, for diagnostics.
+#[tauri::command]
+pub async fn list_subscriptions(
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<String>, String> {
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        guard
+            .swarm_cmd_tx
+            .clone()
+            .ok_or_else(|| "Swarm not started".to_string())?
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    cmd_tx
+        .send(SwarmCommand::ListSubscriptions(reply_tx))
+        .await
+        .map_err(|e| format!("Failed to send ListSubscriptions command: {e}"))?;
+
+    reply_rx
+        .await
+        .map_err(|e| format!("Swarm did not reply: {e}"))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatsDto {
+    pub queued: usize,
+    pub held: usize,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub deferred: u64,
+}
+
+impl From<QueueStats> for QueueStatsDto {
+    fn from(stats: QueueStats) -> Self {
+        Self {
+            queued: stats.queued,
+            held: stats.held,
+            delivered: stats.delivered,
+            dropped: stats.dropped,
+            deferred: stats.deferred,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationStatsDto {
+    pub control: QueueStatsDto,
+    pub chat: QueueStatsDto,
+    pub realtime: QueueStatsDto,
+}
+
+/// Swarm → app queue depths and drop counters, to spot a lagging consumer.
+#[tauri::command]
+pub async fn get_notification_stats(
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<NotificationStatsDto, String> {
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        guard
+            .swarm_cmd_tx
+            .clone()
+            .ok_or_else(|| "Swarm not started".to_string())?
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    cmd_tx
+        .send(SwarmCommand::GetNotificationStats(reply_tx))
+        .await
+        .map_err(|e| format!("Failed to send GetNotificationStats command: {e}"))?;
+
+    let stats = reply_rx
+        .await
+        .map_err(|e| format!("Swarm did not reply: {e}"))?;
+
+    Ok(NotificationStatsDto {
+        control: stats.control.into(),
+        chat: stats.chat.into(),
+        realtime: stats.realtime.into(),
+    })
+}
+
 #[tauri::command]
 pub fn get_connection_mode(state: State<'_, Arc<Mutex<AppState>>>) -> Result<String, String> {
     let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
@@ -71,3 +381,110 @@ pub fn get_connection_mode(state: State<'_, Arc<Mutex<AppState>>>) -> Result<Str
 
     Ok(mode.to_string())
 }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactPresenceDto {
+    pub user_id: String,
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    pub expires_at: String,
+}
+
+impl From<PresenceInfo> for ContactPresenceDto {
+    fn from(info: PresenceInfo) -> Self {
+        Self {
+            user_id: info.user_id.to_hex(),
+            peer_id: info.peer_id.to_string(),
+            addresses: info.addresses.iter().map(|a| a.to_string()).collect(),
+            expires_at: info.expires_at.to_rfc3339(),
+        }
+    }
+}
+
+async fn lookup_presence(
+    state: &State<'_, Arc<Mutex<AppState>>>,
+    user_id_hex: &str,
+) -> Result<Option<PresenceInfo>, String> {
+    let user_id_hex = user_id_hex.trim();
+    if user_id_hex.len() != 64 {
+        return Err("Invalid user ID (expected 64 hex chars)".into());
+    }
+    let user_id = UserId::from_hex(user_id_hex).map_err(|e| format!("Invalid user ID: {e}"))?;
+
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        guard
+            .swarm_cmd_tx
+            .clone()
+            .ok_or_else(|| "Swarm not started".to_string())?
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
+    cmd_tx
+        .send(SwarmCommand::LookupPresence {
+            user_id,
+            reply: reply_tx,
+        })
+        .await
+        .map_err(|e| format!("Failed to send LookupPresence command: {e}"))?;
+
+    reply_rx
+        .await
+        .map_err(|e| format!("Swarm did not reply: {e}"))
+}
+
+/// Resolve a contact's signed presence record from the DHT.
+#[tauri::command]
+pub async fn lookup_contact(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    user_id: String,
+) -> Result<Option<ContactPresenceDto>, String> {
+    Ok(lookup_presence(&state, &user_id)
+        .await?
+        .map(ContactPresenceDto::from))
+}
+
+/// Resolve a contact by user ID and dial every address they published.
+#[tauri::command]
+pub async fn dial_contact(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    user_id: String,
+) -> Result<ContactPresenceDto, String> {
+    let info = lookup_presence(&state, &user_id)
+        .await?
+        .ok_or_else(|| "Contact is not reachable (no presence record)".to_string())?;
+
+    let cmd_tx = {
+        let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
+        guard
+            .swarm_cmd_tx
+            .clone()
+            .ok_or_else(|| "Swarm not started".to_string())?
+    };
+
+    let addrs: Vec<libp2p::Multiaddr> = info
+        .addresses
+        .iter()
+        .map(|addr| {
+            let mut addr = addr.clone();
+            if !matches!(addr.iter().last(), Some(Protocol::P2p(_))) {
+                addr.push(Protocol::P2p(info.peer_id));
+            }
+            addr
+        })
+        .collect();
+
+    // One dial over all addresses, so the swarm can try QUIC before TCP/WebSocket
+    info!(user = %info.user_id.short(), count = addrs.len(), "Dialing contact");
+    cmd_tx
+        .send(SwarmCommand::DialPeer {
+            peer_id: info.peer_id,
+            addrs,
+        })
+        .await
+        .map_err(|e| format!("Failed to send dial command: {e}"))?;
+
+    Ok(info.into())
+}
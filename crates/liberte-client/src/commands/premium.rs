@@ -51,6 +51,7 @@ pub fn activate_premium(
 
     let mut guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
     guard.is_premium = true;
+    guard.premium_token = Some(token.clone());
 
     info!(
         valid_until = %token.valid_until.to_rfc3339(),
@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 use tauri::State;
 use tracing::info;
 
+use liberte_store::Database;
+
 use crate::state::AppState;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +19,18 @@ pub struct AppSettings {
     pub auto_connect: bool,
     pub theme: String,
     pub server_url: String,
+    /// Resolve through the OS instead of encrypted DNS (for networks blocking DoH)
+    #[serde(default)]
+    pub use_system_dns: bool,
+    /// DoH/DoT upstreams as scheme://tls-name@ip[:port]; empty means the defaults
+    #[serde(default)]
+    pub dns_upstreams: Vec<String>,
+    /// Pre-shared swarm key file for a private network; only peers with the same key can connect
+    #[serde(default)]
+    pub swarm_key_path: Option<String>,
+    /// Rendezvous node multiaddr ending in /p2p/<peer id>, used to find channel members
+    #[serde(default)]
+    pub rendezvous_point: Option<String>,
 }
 
 impl Default for AppSettings {
@@ -30,6 +44,10 @@ impl Default for AppSettings {
             auto_connect: true,
             theme: "dark".into(),
             server_url: String::new(),
+            use_system_dns: false,
+            dns_upstreams: Vec::new(),
+            swarm_key_path: None,
+            rendezvous_point: None,
         }
     }
 }
@@ -42,6 +60,11 @@ pub fn get_settings(state: State<'_, Arc<Mutex<AppState>>>) -> Result<AppSetting
         return Ok(AppSettings::default());
     };
 
+    load_settings(db)
+}
+
+/// Read persisted settings, falling back to defaults when none are saved yet.
+pub fn load_settings(db: &Database) -> Result<AppSettings, String> {
     let _ = db.conn().execute_batch(
         "CREATE TABLE IF NOT EXISTS app_settings (
             id INTEGER PRIMARY KEY CHECK (id = 1),
@@ -70,6 +93,23 @@ pub fn update_settings(
     state: State<'_, Arc<Mutex<AppState>>>,
     settings: AppSettings,
 ) -> Result<(), String> {
+    for upstream in &settings.dns_upstreams {
+        upstream
+            .parse::<liberte_net::DnsUpstream>()
+            .map_err(|e| format!("{e}"))?;
+    }
+
+    if let Some(ref path) = settings.swarm_key_path {
+        liberte_net::load_swarm_key(std::path::Path::new(path)).map_err(|e| format!("{e}"))?;
+    }
+
+    if let Some(ref addr) = settings.rendezvous_point {
+        let addr: libp2p::Multiaddr = addr
+            .parse()
+            .map_err(|e| format!("Invalid rendezvous point: {e}"))?;
+        liberte_net::rendezvous::RendezvousState::new(addr).map_err(|e| format!("{e}"))?;
+    }
+
     let guard = state.lock().map_err(|e| format!("Lock poisoned: {e}"))?;
 
     let db = guard
@@ -112,6 +152,11 @@ pub struct ServerInfo {
     pub premium_required: bool,
     pub registration_open: bool,
     pub max_peers: usize,
+    /// Relay peer id and dialable multiaddrs; absent on older servers
+    #[serde(default)]
+    pub peer_id: Option<String>,
+    #[serde(default)]
+    pub multiaddrs: Vec<String>,
 }
 
 #[tauri::command]
@@ -54,6 +54,16 @@ pub fn run() {
             commands::network::connect_peer,
             commands::network::list_peers,
             commands::network::get_connection_mode,
+            commands::network::lookup_contact,
+            commands::network::dial_contact,
+            commands::network::get_network_diagnostics,
+            commands::network::get_notification_stats,
+            commands::network::disconnect_peer,
+            commands::network::ban_peer,
+            commands::network::unban_peer,
+            commands::network::list_banned_peers,
+            commands::network::get_listen_addrs,
+            commands::network::list_subscriptions,
             commands::messaging::send_message,
             commands::messaging::get_messages,
             commands::messaging::list_channels,
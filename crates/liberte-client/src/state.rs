@@ -2,17 +2,20 @@ use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
 use liberte_shared::identity::Identity;
+use liberte_shared::premium::PremiumToken;
 use liberte_shared::types::ConnectionMode;
 use liberte_store::Database;
 use tauri::AppHandle;
 use tokio::sync::mpsc;
 
-use liberte_net::SwarmCommand;
+use liberte_net::{SwarmCommand, TopicTable};
 
 pub struct AppState {
     pub identity: Option<Identity>,
     pub database: Option<Database>,
     pub swarm_cmd_tx: Option<mpsc::Sender<SwarmCommand>>,
+    /// Maps opaque gossipsub topic names back to channels
+    pub topic_table: TopicTable,
     pub app_handle: Option<AppHandle>,
     pub connection_mode: ConnectionMode,
     // Voice call state
@@ -20,6 +23,8 @@ pub struct AppState {
     pub is_muted: bool,
     pub is_video_enabled: bool,
     pub is_premium: bool,
+    /// Presented to the relay server when binding our peer id
+    pub premium_token: Option<PremiumToken>,
     pub server_url: String,
     pub call_mode: String,
     /// Channel UUID string of the active voice call
@@ -38,12 +43,14 @@ impl AppState {
             identity: None,
             database: None,
             swarm_cmd_tx: None,
+            topic_table: TopicTable::new(),
             app_handle: None,
             connection_mode: ConnectionMode::Disconnected,
             is_in_call: false,
             is_muted: false,
             is_video_enabled: true,
             is_premium: false,
+            premium_token: None,
             server_url: String::new(),
             call_mode: "mesh".to_string(),
             call_channel_id: None,
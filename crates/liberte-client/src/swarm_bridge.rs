@@ -1,19 +1,36 @@
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use tauri::AppHandle;
 use tokio::sync::mpsc;
 use tracing::{debug, info, warn};
 
-use liberte_net::{SwarmCommand, SwarmNotification};
+use liberte_net::{
+    DnsResolverConfig, DnsUpstream, MessageAcceptance, MessageRateLimiter, NotificationReceiver,
+    SwarmCommand, SwarmNotification, TopicKind,
+};
+use liberte_shared::constants::{
+    GOSSIP_RATE_LIMIT, GOSSIP_RATE_WINDOW_SECS, PEER_BOOK_LOAD_LIMIT, PEER_BOOK_MAX_AGE_DAYS,
+    PRESENCE_REPUBLISH_SECS, PRESENCE_TTL_SECS,
+};
 use liberte_shared::crypto;
+use liberte_shared::identity::Identity;
 use liberte_shared::protocol::WireMessage;
 use liberte_shared::types::ChannelId;
 use liberte_store::Message;
+use libp2p::{Multiaddr, PeerId};
 
 use crate::events::*;
 use crate::state::AppState;
 
+/// How often to check whether channel topics crossed an epoch or grace boundary.
+const TOPIC_ROTATION_CHECK_SECS: u64 = 60;
+
+/// How long to wait for the configured server's `/info` and relay binding.
+const SERVER_INFO_TIMEOUT_SECS: u64 = 5;
+
 /// Start the libp2p swarm, store `cmd_tx` in AppState, and spawn the
 /// notification processing loop that forwards events to the Tauri frontend.
 pub async fn start_swarm_and_bridge(
@@ -33,11 +50,22 @@ pub async fn start_swarm_and_bridge(
     let libp2p_keypair = libp2p::identity::Keypair::ed25519_from_bytes(keypair_bytes)
         .map_err(|e| format!("Failed to create libp2p keypair: {e}"))?;
 
-    let config = liberte_net::swarm::SwarmConfig::default();
+    let server_url = load_server_url(&state);
+    let rendezvous_point = load_rendezvous_point(&state);
+    let config = liberte_net::swarm::SwarmConfig {
+        known_peers: load_peer_book(&state),
+        banned_peers: load_banned_peers(&state),
+        dns: load_dns_config(&state),
+        swarm_key_path: load_swarm_key_path(&state),
+        rendezvous_point: rendezvous_point.clone(),
+        presence_identity: Some(Identity::from_secret_bytes(&identity_secret)),
+        ..Default::default()
+    };
 
-    let (cmd_tx, notif_rx, local_peer_id) = liberte_net::spawn_swarm(libp2p_keypair, config)
-        .await
-        .map_err(|e| format!("Failed to spawn swarm: {e}"))?;
+    let (cmd_tx, notif_rx, local_peer_id) =
+        liberte_net::spawn_swarm(libp2p_keypair.clone(), config)
+            .await
+            .map_err(|e| format!("Failed to spawn swarm: {e}"))?;
 
     info!(peer_id = %local_peer_id, "Swarm started");
 
@@ -47,48 +75,324 @@ pub async fn start_swarm_and_bridge(
         guard.swarm_cmd_tx = Some(cmd_tx.clone());
     }
 
-    // Subscribe to all existing channels
-    subscribe_all_channels(&state, &cmd_tx).await;
+    // Subscribe to all existing channels, then keep topics rotating
+    sync_channel_topics(&state, &cmd_tx).await;
+
+    let rotation_state = state.clone();
+    let rotation_tx = cmd_tx.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(TOPIC_ROTATION_CHECK_SECS));
+        ticker.tick().await;
+        while !rotation_tx.is_closed() {
+            ticker.tick().await;
+            sync_channel_topics(&rotation_state, &rotation_tx).await;
+        }
+    });
+
+    // The configured server's relay is dialed, and doubles as rendezvous
+    // point unless another one is set. Binding our peer id to the identity
+    // first lets a premium-only relay accept the reservation. Neither holds
+    // up startup, and the binding is renewed before the presence record it
+    // rests on expires.
+    if let Some(url) = server_url {
+        let bind_state = state.clone();
+        let bind_tx = cmd_tx.clone();
+        tokio::spawn(async move {
+            bind_relay_identity(&bind_state, &url, &identity_secret, &libp2p_keypair).await;
+            let relay_addrs = load_relay_addrs(&url).await;
+            if rendezvous_point.is_none() {
+                if let Some(addr) = relay_addrs.first() {
+                    let _ = bind_tx
+                        .send(SwarmCommand::SetRendezvousPoint(addr.clone()))
+                        .await;
+                }
+            }
+            for addr in relay_addrs {
+                let _ = bind_tx.send(SwarmCommand::Dial(addr)).await;
+            }
+
+            let mut ticker = tokio::time::interval(Duration::from_secs(PRESENCE_REPUBLISH_SECS));
+            ticker.tick().await;
+            while !bind_tx.is_closed() {
+                ticker.tick().await;
+                bind_relay_identity(&bind_state, &url, &identity_secret, &libp2p_keypair).await;
+            }
+        });
+    }
 
     // Spawn notification processing loop
     let state_clone = state.clone();
     tokio::spawn(async move {
-        notification_loop(app, state_clone, notif_rx).await;
+        notification_loop(app, state_clone, cmd_tx, notif_rx).await;
     });
 
     Ok(())
 }
 
-/// Subscribe to gossipsub topics for all channels the user has keys for.
-async fn subscribe_all_channels(state: &Arc<Mutex<AppState>>, cmd_tx: &mpsc::Sender<SwarmCommand>) {
-    let channel_keys: HashMap<String, String> = {
-        let guard = match state.lock() {
+/// Swarm key file for private network mode, if the user configured one.
+fn load_swarm_key_path(state: &Arc<Mutex<AppState>>) -> Option<PathBuf> {
+    let guard = state.lock().ok()?;
+    let db = guard.database.as_ref()?;
+    crate::commands::settings::load_settings(db)
+        .ok()?
+        .swarm_key_path
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+fn load_server_url(state: &Arc<Mutex<AppState>>) -> Option<String> {
+    let guard = state.lock().ok()?;
+    let db = guard.database.as_ref()?;
+    let url = crate::commands::settings::load_settings(db)
+        .ok()?
+        .server_url;
+    (!url.is_empty()).then_some(url)
+}
+
+/// Prove to the server's relay that our peer id belongs to our identity,
+/// with the premium token if we hold one. Failures are only logged: a
+/// relay that does not require premium admits us without a binding.
+async fn bind_relay_identity(
+    state: &Arc<Mutex<AppState>>,
+    server_url: &str,
+    identity_secret: &[u8; 32],
+    keypair: &libp2p::identity::Keypair,
+) {
+    let identity = Identity::from_secret_bytes(identity_secret);
+    let ttl = chrono::Duration::seconds(PRESENCE_TTL_SECS);
+    let presence = match liberte_net::sign_presence(&identity, keypair, &[], ttl) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!(error = %e, "Failed to sign relay binding");
+            return;
+        }
+    };
+    let premium_token = state
+        .lock()
+        .ok()
+        .and_then(|guard| guard.premium_token.clone());
+
+    let url = format!("{}/relay/bind", server_url.trim_end_matches('/'));
+    let request = reqwest::Client::new()
+        .post(&url)
+        .timeout(Duration::from_secs(SERVER_INFO_TIMEOUT_SECS))
+        .json(&serde_json::json!({
+            "presence": presence,
+            "premium_token": premium_token,
+        }))
+        .send()
+        .await;
+    match request {
+        Ok(resp) if resp.status().is_success() => debug!("Relay binding renewed"),
+        Ok(resp) => warn!(status = %resp.status(), "Server refused relay binding"),
+        Err(e) => warn!(error = %e, "Could not reach server for relay binding"),
+    }
+}
+
+/// Relay multiaddrs advertised by the server's `/info`.
+async fn load_relay_addrs(server_url: &str) -> Vec<Multiaddr> {
+    let info = tokio::time::timeout(
+        Duration::from_secs(SERVER_INFO_TIMEOUT_SECS),
+        crate::commands::settings::get_server_info(server_url.to_string()),
+    )
+    .await;
+    match info {
+        Ok(Ok(info)) => info
+            .multiaddrs
+            .iter()
+            .filter_map(|addr| addr.parse().ok())
+            .collect(),
+        Ok(Err(e)) => {
+            warn!(error = %e, "Could not fetch relay addresses from server");
+            Vec::new()
+        }
+        Err(_) => {
+            warn!("Timed out fetching relay addresses from server");
+            Vec::new()
+        }
+    }
+}
+
+/// Rendezvous node used to find channel members, if the user configured one.
+fn load_rendezvous_point(state: &Arc<Mutex<AppState>>) -> Option<Multiaddr> {
+    let guard = state.lock().ok()?;
+    let db = guard.database.as_ref()?;
+    let addr = crate::commands::settings::load_settings(db)
+        .ok()?
+        .rendezvous_point
+        .filter(|addr| !addr.is_empty())?;
+    match addr.parse() {
+        Ok(addr) => Some(addr),
+        Err(e) => {
+            warn!(error = %e, "Ignoring invalid rendezvous point");
+            None
+        }
+    }
+}
+
+/// DNS resolver choice from the user's settings. Invalid upstreams are
+/// skipped; if none remain, the built-in encrypted defaults are used.
+fn load_dns_config(state: &Arc<Mutex<AppState>>) -> DnsResolverConfig {
+    let settings = match state.lock() {
+        Ok(guard) => guard
+            .database
+            .as_ref()
+            .and_then(|db| crate::commands::settings::load_settings(db).ok())
+            .unwrap_or_default(),
+        Err(_) => return DnsResolverConfig::default(),
+    };
+
+    if settings.use_system_dns {
+        return DnsResolverConfig::System;
+    }
+
+    let upstreams: Vec<DnsUpstream> = settings
+        .dns_upstreams
+        .iter()
+        .filter_map(|s| match s.parse() {
+            Ok(upstream) => Some(upstream),
+            Err(e) => {
+                warn!(error = %e, "Skipping invalid DNS upstream");
+                None
+            }
+        })
+        .collect();
+
+    if upstreams.is_empty() {
+        DnsResolverConfig::default()
+    } else {
+        DnsResolverConfig::Encrypted(upstreams)
+    }
+}
+
+fn load_banned_peers(state: &Arc<Mutex<AppState>>) -> Vec<PeerId> {
+    let guard = match state.lock() {
+        Ok(g) => g,
+        Err(_) => return Vec::new(),
+    };
+    let db = match guard.database.as_ref() {
+        Some(db) => db,
+        None => return Vec::new(),
+    };
+
+    db.list_banned_peers()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|ban| ban.peer_id.parse().ok())
+        .collect()
+}
+
+/// Prune stale entries from the persistent peer book and return the rest
+/// so they can be seeded into Kademlia.
+fn load_peer_book(state: &Arc<Mutex<AppState>>) -> Vec<(PeerId, Vec<Multiaddr>)> {
+    let guard = match state.lock() {
+        Ok(g) => g,
+        Err(_) => return Vec::new(),
+    };
+    let db = match guard.database.as_ref() {
+        Some(db) => db,
+        None => return Vec::new(),
+    };
+
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(PEER_BOOK_MAX_AGE_DAYS);
+    match db.prune_known_peers(cutoff) {
+        Ok(removed) if removed > 0 => debug!(removed, "Pruned stale peers from peer book"),
+        Ok(_) => {}
+        Err(e) => warn!(error = %e, "Failed to prune peer book"),
+    }
+
+    let known = db
+        .list_known_peers(PEER_BOOK_LOAD_LIMIT)
+        .unwrap_or_default();
+
+    let peers: Vec<(PeerId, Vec<Multiaddr>)> = known
+        .into_iter()
+        .filter_map(|peer| {
+            let peer_id = peer.peer_id.parse::<PeerId>().ok()?;
+            let addrs: Vec<Multiaddr> = peer
+                .addresses
+                .iter()
+                .filter_map(|a| a.parse().ok())
+                .collect();
+            (!addrs.is_empty()).then_some((peer_id, addrs))
+        })
+        .collect();
+
+    info!(count = peers.len(), "Loaded peers from peer book");
+    peers
+}
+
+/// Bring gossipsub subscriptions in line with the current topic epoch for
+/// every channel the user has a key for, updating the local topic table.
+/// Rendezvous namespaces rotate on the same epochs and are resent with them.
+pub async fn sync_channel_topics(
+    state: &Arc<Mutex<AppState>>,
+    cmd_tx: &mpsc::Sender<SwarmCommand>,
+) {
+    let now = chrono::Utc::now();
+    let (diff, namespaces) = {
+        let mut guard = match state.lock() {
             Ok(g) => g,
             Err(_) => return,
         };
-        let db = match guard.database.as_ref() {
-            Some(db) => db,
+        let channels: Vec<(ChannelId, crypto::SymmetricKey)> = match guard.database.as_ref() {
+            Some(db) => db
+                .get_all_channel_keys()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(id, key_hex)| {
+                    let key: crypto::SymmetricKey = hex::decode(key_hex).ok()?.try_into().ok()?;
+                    Some((ChannelId(id), key))
+                })
+                .collect(),
             None => return,
         };
-        db.get_all_channel_keys()
-            .unwrap_or_default()
-            .into_iter()
-            .map(|(id, k)| (id.to_string(), k))
-            .collect()
+        let namespaces: Vec<String> = channels
+            .iter()
+            .flat_map(|(_, key)| liberte_net::active_namespaces(key, now))
+            .collect();
+        (guard.topic_table.refresh(&channels, now), namespaces)
     };
 
-    for channel_id_str in channel_keys.keys() {
-        if let Ok(uuid) = uuid::Uuid::parse_str(channel_id_str) {
-            let topic = ChannelId(uuid).to_topic();
-            debug!(topic = %topic, "Auto-subscribing to channel");
-            let _ = cmd_tx.send(SwarmCommand::SubscribeTopic(topic)).await;
-        }
+    if diff.subscribe.is_empty() && diff.unsubscribe.is_empty() {
+        return;
     }
 
-    info!(
-        count = channel_keys.len(),
-        "Subscribed to existing channels"
+    let _ = cmd_tx
+        .send(SwarmCommand::SetRendezvousNamespaces(namespaces))
+        .await;
+
+    debug!(
+        subscribe = diff.subscribe.len(),
+        unsubscribe = diff.unsubscribe.len(),
+        "Rotating channel topics"
     );
+    for (topic, kind) in diff.subscribe {
+        let _ = cmd_tx
+            .send(SwarmCommand::SubscribeTopic { topic, kind })
+            .await;
+    }
+    for topic in diff.unsubscribe {
+        let (reply, reply_rx) = tokio::sync::oneshot::channel();
+        if cmd_tx
+            .send(SwarmCommand::UnsubscribeTopic {
+                topic: topic.clone(),
+                reply,
+            })
+            .await
+            .is_err()
+        {
+            return;
+        }
+        // The topic table is already updated; a failure only leaves us
+        // receiving an expired topic until the next restart
+        match reply_rx.await {
+            Ok(Ok(true)) => {}
+            Ok(Ok(false)) => debug!(topic = %topic, "Expired topic was not subscribed"),
+            Ok(Err(e)) => warn!(topic = %topic, error = %e, "Failed to leave expired topic"),
+            Err(_) => return,
+        }
+    }
 }
 
 /// Main loop that receives swarm notifications and dispatches them to the
@@ -96,10 +400,15 @@ async fn subscribe_all_channels(state: &Arc<Mutex<AppState>>, cmd_tx: &mpsc::Sen
 async fn notification_loop(
     app: AppHandle,
     state: Arc<Mutex<AppState>>,
-    mut notif_rx: mpsc::Receiver<SwarmNotification>,
+    cmd_tx: mpsc::Sender<SwarmCommand>,
+    mut notif_rx: NotificationReceiver,
 ) {
     // Track which channels each peer is associated with (for presence)
     let mut _channel_peers: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut rate_limiter = MessageRateLimiter::new(
+        GOSSIP_RATE_LIMIT,
+        Duration::from_secs(GOSSIP_RATE_WINDOW_SECS),
+    );
 
     info!("Swarm notification bridge started");
 
@@ -107,6 +416,7 @@ async fn notification_loop(
         match notification {
             SwarmNotification::PeerConnected { peer_id, address } => {
                 info!(peer = %peer_id, addr = %address, "Peer connected (bridge)");
+                update_peer_book(&state, |db| db.record_peer_success(&peer_id.to_string()));
                 emit_event(
                     &app,
                     EVENT_PEER_CONNECTED,
@@ -128,6 +438,8 @@ async fn notification_loop(
             }
 
             SwarmNotification::MessageReceived {
+                message_id,
+                propagation_source,
                 source,
                 topic,
                 data,
@@ -138,7 +450,31 @@ async fn notification_loop(
                     len = data.len(),
                     "Message received on bridge"
                 );
-                handle_incoming_message(&app, &state, &topic, &data);
+                let acceptance = match validate_incoming_message(
+                    &state,
+                    &mut rate_limiter,
+                    source.as_ref(),
+                    &topic,
+                    &data,
+                ) {
+                    Ok(validated) => {
+                        handle_incoming_message(&app, &state, validated);
+                        MessageAcceptance::Accept
+                    }
+                    Err(verdict) => verdict,
+                };
+                // try_send: awaiting here while the swarm awaits us could deadlock.
+                // An unreported message simply expires from the gossip cache.
+                if cmd_tx
+                    .try_send(SwarmCommand::ReportValidation {
+                        message_id,
+                        propagation_source,
+                        acceptance,
+                    })
+                    .is_err()
+                {
+                    debug!("Dropped validation report, command channel full");
+                }
             }
 
             SwarmNotification::RelayReservation {
@@ -151,91 +487,155 @@ async fn notification_loop(
                     "Relay reservation received"
                 );
             }
+
+            SwarmNotification::PeerIdentified {
+                peer_id,
+                listen_addrs,
+            } => {
+                let addrs: Vec<String> = listen_addrs.iter().map(|a| a.to_string()).collect();
+                update_peer_book(&state, |db| {
+                    db.upsert_peer_addresses(&peer_id.to_string(), &addrs)
+                });
+            }
+
+            SwarmNotification::DialFailed { peer_id } => {
+                update_peer_book(&state, |db| db.record_peer_failure(&peer_id.to_string()));
+            }
         }
     }
 
     warn!("Swarm notification loop ended");
 }
 
-/// Try to decrypt and store an incoming gossipsub message.
-fn handle_incoming_message(
-    app: &AppHandle,
+/// Run a peer-book write against the database, logging (not propagating) failures.
+fn update_peer_book<F>(state: &Arc<Mutex<AppState>>, f: F)
+where
+    F: FnOnce(&liberte_store::Database) -> Result<(), liberte_store::StoreError>,
+{
+    let guard = match state.lock() {
+        Ok(g) => g,
+        Err(_) => return,
+    };
+    if let Some(ref db) = guard.database {
+        if let Err(e) = f(db) {
+            debug!(error = %e, "Failed to update peer book");
+        }
+    }
+}
+
+/// An incoming gossipsub message that passed application-level validation.
+struct ValidatedMessage {
+    channel_uuid: uuid::Uuid,
+    own_pubkey: Option<[u8; 32]>,
+    wire_msg: WireMessage,
+}
+
+/// Check that an incoming message maps to a known channel, decodes with its
+/// key and is within the author's rate budget. The error is the verdict to
+/// report to gossipsub: `Reject` penalizes the propagating peer, `Ignore` doesn't.
+fn validate_incoming_message(
     state: &Arc<Mutex<AppState>>,
+    rate_limiter: &mut MessageRateLimiter,
+    source: Option<&PeerId>,
     topic: &str,
     data: &[u8],
-) {
-    // The topic format is "channel:<uuid>"
-    let channel_uuid_str = match topic.strip_prefix("channel:") {
-        Some(s) => s,
-        None => {
-            debug!(topic = %topic, "Ignoring message on non-channel topic");
-            return;
+) -> Result<ValidatedMessage, MessageAcceptance> {
+    if let Some(source) = source {
+        if !rate_limiter.check(source) {
+            debug!(source = %source, "Author over gossip rate limit");
+            return Err(MessageAcceptance::Ignore);
         }
-    };
+    }
 
-    let channel_uuid = match uuid::Uuid::parse_str(channel_uuid_str) {
-        Ok(u) => u,
-        Err(e) => {
-            warn!(topic = %topic, error = %e, "Invalid channel UUID in topic");
-            return;
+    let guard = state.lock().map_err(|_| MessageAcceptance::Ignore)?;
+
+    // Topics are opaque hashes; map back to the channel via the local table
+    let (channel_uuid, topic_kind) = match (
+        guard.topic_table.channel_for(topic),
+        guard.topic_table.kind_of(topic),
+    ) {
+        (Some(channel_id), Some(kind)) => (channel_id.0, kind),
+        _ => {
+            debug!(topic = %topic, "Ignoring message on unknown topic");
+            return Err(MessageAcceptance::Ignore);
         }
     };
 
-    // Look up the channel key
-    let (channel_key, own_pubkey) = {
-        let guard = match state.lock() {
-            Ok(g) => g,
-            Err(_) => return,
-        };
-        let db = match guard.database.as_ref() {
-            Some(db) => db,
-            None => return,
-        };
-
-        let key_hex = match db.get_channel_key(channel_uuid) {
-            Ok(k) => k,
-            Err(_) => {
-                debug!(channel = %channel_uuid, "No key for channel, skipping");
-                return;
-            }
-        };
-
-        let key_bytes = match hex::decode(&key_hex) {
-            Ok(b) if b.len() == 32 => {
-                let mut arr = [0u8; 32];
-                arr.copy_from_slice(&b);
-                arr
-            }
-            _ => return,
-        };
-
-        let own_pk = guard.identity.as_ref().map(|id| id.public_key_bytes());
-
-        (key_bytes, own_pk)
+    let db = guard.database.as_ref().ok_or(MessageAcceptance::Ignore)?;
+    let channel_key: [u8; 32] = match db.get_channel_key(channel_uuid) {
+        Ok(key_hex) => hex::decode(key_hex)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or(MessageAcceptance::Ignore)?,
+        Err(_) => {
+            debug!(channel = %channel_uuid, "No key for channel, skipping");
+            return Err(MessageAcceptance::Ignore);
+        }
     };
+    let own_pubkey = guard.identity.as_ref().map(|id| id.public_key_bytes());
+    drop(guard);
 
     // The data on gossipsub is a raw WireMessage (not encrypted at transport level,
     // but the content inside ChatMessage is encrypted with the channel key).
-    let wire_msg = match WireMessage::from_bytes(data) {
-        Ok(m) => m,
+    let (wire_msg, sealed) = match WireMessage::from_bytes(data) {
+        Ok(m) => (m, false),
         Err(e) => {
             // The message might be encrypted at the channel level — try decrypting first
-            match crypto::decrypt(&channel_key, data) {
-                Ok(plaintext) => match WireMessage::from_bytes(&plaintext) {
-                    Ok(m) => m,
-                    Err(e2) => {
-                        debug!(error = %e, error2 = %e2, "Failed to deserialize wire message");
-                        return;
-                    }
-                },
-                Err(_) => {
-                    debug!(error = %e, "Failed to deserialize wire message");
-                    return;
-                }
-            }
+            let plaintext = crypto::decrypt(&channel_key, data).map_err(|_| {
+                debug!(error = %e, "Failed to deserialize wire message");
+                MessageAcceptance::Reject
+            })?;
+            let m = WireMessage::from_bytes(&plaintext).map_err(|e2| {
+                debug!(error = %e, error2 = %e2, "Failed to deserialize wire message");
+                MessageAcceptance::Reject
+            })?;
+            (m, true)
         }
     };
 
+    // Anyone can publish plaintext on a topic, so a plaintext message is only
+    // accepted if it names this channel; a sealed one is bound by the key
+    let bound = match wire_msg.channel_id() {
+        Some(id) => id.0 == channel_uuid,
+        None => sealed,
+    };
+    if !bound {
+        debug!(channel = %channel_uuid, sealed, "Rejecting message not bound to this channel");
+        return Err(MessageAcceptance::Reject);
+    }
+
+    // Media topics are queued as lossy realtime traffic; only voice frames belong there
+    if topic_kind == TopicKind::Media && !matches!(wire_msg, WireMessage::VoiceFrame(_)) {
+        debug!(channel = %channel_uuid, "Rejecting non-media message on media topic");
+        return Err(MessageAcceptance::Reject);
+    }
+
+    if let WireMessage::ChatMessage(ref chat) = wire_msg {
+        if crypto::decrypt(&channel_key, &chat.encrypted_content).is_err() {
+            debug!(channel = %channel_uuid, "Rejecting chat message not sealed for this channel");
+            return Err(MessageAcceptance::Reject);
+        }
+    }
+
+    Ok(ValidatedMessage {
+        channel_uuid,
+        own_pubkey,
+        wire_msg,
+    })
+}
+
+/// Store and forward a validated message to the frontend.
+fn handle_incoming_message(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    validated: ValidatedMessage,
+) {
+    let ValidatedMessage {
+        channel_uuid,
+        own_pubkey,
+        wire_msg,
+    } = validated;
+
     match wire_msg {
         WireMessage::ChatMessage(chat) => {
             // Skip our own messages (already stored locally)
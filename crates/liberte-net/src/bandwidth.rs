@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::muxing::{
+    StreamMuxer, StreamMuxerBox, StreamMuxerEvent, StreamMuxerExt, SubstreamBox,
+};
+use libp2p::PeerId;
+
+/// Bytes moved over all connections to one peer.
+#[derive(Debug, Default)]
+pub struct PeerBandwidth {
+    inbound: AtomicU64,
+    outbound: AtomicU64,
+}
+
+impl PeerBandwidth {
+    pub fn inbound(&self) -> u64 {
+        self.inbound.load(Ordering::Relaxed)
+    }
+
+    pub fn outbound(&self) -> u64 {
+        self.outbound.load(Ordering::Relaxed)
+    }
+}
+
+/// Per-peer byte counters, shared between the transport (which counts) and
+/// the swarm loop (which reads them into `ConnectionInfo`).
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthTracker {
+    peers: Arc<Mutex<HashMap<PeerId, Arc<PeerBandwidth>>>>,
+    /// All peers since start, including ones already removed.
+    total: Arc<PeerBandwidth>,
+}
+
+impl BandwidthTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn counters_for(&self, peer_id: PeerId) -> Arc<PeerBandwidth> {
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        peers.entry(peer_id).or_default().clone()
+    }
+
+    /// (bytes in, bytes out) for a peer, or zeros if unknown.
+    pub fn get(&self, peer_id: &PeerId) -> (u64, u64) {
+        let peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        peers
+            .get(peer_id)
+            .map_or((0, 0), |c| (c.inbound(), c.outbound()))
+    }
+
+    /// (bytes in, bytes out) over every connection since the tracker was created.
+    pub fn totals(&self) -> (u64, u64) {
+        (self.total.inbound(), self.total.outbound())
+    }
+
+    /// Forget a peer once its last connection closed.
+    pub fn remove(&self, peer_id: &PeerId) {
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        peers.remove(peer_id);
+    }
+
+    /// Wrap a freshly upgraded connection so its substreams are counted.
+    pub fn instrument(&self, peer_id: PeerId, muxer: StreamMuxerBox) -> StreamMuxerBox {
+        StreamMuxerBox::new(CountingMuxer {
+            inner: muxer,
+            counters: self.counters_for(peer_id),
+            total: self.total.clone(),
+        })
+    }
+}
+
+struct CountingMuxer {
+    inner: StreamMuxerBox,
+    counters: Arc<PeerBandwidth>,
+    total: Arc<PeerBandwidth>,
+}
+
+impl StreamMuxer for CountingMuxer {
+    type Substream = CountingStream;
+    type Error = io::Error;
+
+    fn poll_inbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let inner = ready!(this.inner.poll_inbound_unpin(cx))?;
+        Poll::Ready(Ok(CountingStream {
+            inner,
+            counters: this.counters.clone(),
+            total: this.total.clone(),
+        }))
+    }
+
+    fn poll_outbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let inner = ready!(this.inner.poll_outbound_unpin(cx))?;
+        Poll::Ready(Ok(CountingStream {
+            inner,
+            counters: this.counters.clone(),
+            total: this.total.clone(),
+        }))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().inner.poll_close_unpin(cx)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        self.get_mut().inner.poll_unpin(cx)
+    }
+}
+
+struct CountingStream {
+    inner: SubstreamBox,
+    counters: Arc<PeerBandwidth>,
+    total: Arc<PeerBandwidth>,
+}
+
+impl CountingStream {
+    fn count_in(&self, n: usize) {
+        self.counters.inbound.fetch_add(n as u64, Ordering::Relaxed);
+        self.total.inbound.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn count_out(&self, n: usize) {
+        self.counters
+            .outbound
+            .fetch_add(n as u64, Ordering::Relaxed);
+        self.total.outbound.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.count_in(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_read_vectored(cx, bufs))?;
+        this.count_in(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.count_out(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_write_vectored(cx, bufs))?;
+        this.count_out(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_shared_per_peer() {
+        let tracker = BandwidthTracker::new();
+        let peer = PeerId::random();
+
+        let a = tracker.counters_for(peer);
+        let b = tracker.counters_for(peer);
+        a.inbound.fetch_add(10, Ordering::Relaxed);
+        b.outbound.fetch_add(5, Ordering::Relaxed);
+        assert_eq!(tracker.get(&peer), (10, 5));
+
+        tracker.remove(&peer);
+        assert_eq!(tracker.get(&peer), (0, 0));
+    }
+}
@@ -1,18 +1,23 @@
 use libp2p::{
+    allow_block_list::{self, BlockedPeers},
     dcutr, gossipsub, identify,
     kad::{self, store::MemoryStore},
-    relay,
+    ping, relay, rendezvous,
     swarm::NetworkBehaviour,
 };
 
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "LiberteEvent")]
 pub struct LiberteBehaviour {
+    /// Banned peers; connections to and from them are denied.
+    pub blocked: allow_block_list::Behaviour<BlockedPeers>,
     pub gossipsub: gossipsub::Behaviour,
     pub kademlia: kad::Behaviour<MemoryStore>,
     pub identify: identify::Behaviour,
     pub relay_client: relay::client::Behaviour,
     pub dcutr: dcutr::Behaviour,
+    pub ping: ping::Behaviour,
+    pub rendezvous: rendezvous::client::Behaviour,
 }
 
 #[derive(Debug)]
@@ -22,6 +27,8 @@ pub enum LiberteEvent {
     Identify(identify::Event),
     RelayClient(relay::client::Event),
     Dcutr(dcutr::Event),
+    Ping(ping::Event),
+    Rendezvous(rendezvous::client::Event),
 }
 
 impl From<gossipsub::Event> for LiberteEvent {
@@ -53,3 +60,21 @@ impl From<dcutr::Event> for LiberteEvent {
         LiberteEvent::Dcutr(event)
     }
 }
+
+impl From<ping::Event> for LiberteEvent {
+    fn from(event: ping::Event) -> Self {
+        LiberteEvent::Ping(event)
+    }
+}
+
+impl From<rendezvous::client::Event> for LiberteEvent {
+    fn from(event: rendezvous::client::Event) -> Self {
+        LiberteEvent::Rendezvous(event)
+    }
+}
+
+impl From<void::Void> for LiberteEvent {
+    fn from(event: void::Void) -> Self {
+        void::unreachable(event)
+    }
+}
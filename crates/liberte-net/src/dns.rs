@@ -2,50 +2,172 @@ use hickory_resolver::{
     config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
     TokioAsyncResolver,
 };
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use tracing::info;
 
-use liberte_shared::constants::{DOH_CLOUDFLARE, DOH_GOOGLE};
+use liberte_shared::constants::DEFAULT_DNS_UPSTREAMS;
 
-/// Builds a DoH resolver that bypasses OS/ISP DNS entirely.
-pub fn build_doh_resolver() -> TokioAsyncResolver {
-    let cloudflare_addr: IpAddr = DOH_CLOUDFLARE
-        .parse()
-        .unwrap_or(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)));
-    let google_addr: IpAddr = DOH_GOOGLE
-        .parse()
-        .unwrap_or(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
-
-    let cloudflare_ns = NameServerConfig {
-        socket_addr: SocketAddr::new(cloudflare_addr, 443),
-        protocol: Protocol::Https,
-        tls_dns_name: Some("cloudflare-dns.com".to_string()),
-        trust_negative_responses: false,
-        tls_config: None,
-        bind_addr: None,
-    };
+/// An encrypted DNS server, written as `scheme://tls-name@ip[:port]`, e.g.
+/// `https://cloudflare-dns.com@1.1.1.1` or `tls://dns.example.org@203.0.113.5:853`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsUpstream {
+    pub protocol: DnsProtocol,
+    pub addr: SocketAddr,
+    /// Name the server's TLS certificate is checked against.
+    pub tls_name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsProtocol {
+    /// DNS-over-HTTPS (default port 443)
+    Https,
+    /// DNS-over-TLS (default port 853)
+    Tls,
+}
+
+/// How `/dns*` multiaddrs and other lookups are resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsResolverConfig {
+    /// Encrypted upstreams only, bypassing OS/ISP DNS.
+    Encrypted(Vec<DnsUpstream>),
+    /// The OS resolver, for networks that block public DoH/DoT servers.
+    System,
+}
+
+impl Default for DnsResolverConfig {
+    fn default() -> Self {
+        Self::Encrypted(default_upstreams())
+    }
+}
+
+pub fn default_upstreams() -> Vec<DnsUpstream> {
+    DEFAULT_DNS_UPSTREAMS
+        .iter()
+        .map(|s| s.parse().expect("valid default DNS upstream"))
+        .collect()
+}
+
+impl FromStr for DnsUpstream {
+    type Err = DnsConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DnsConfigError::InvalidUpstream(s.to_string());
+
+        let (protocol, rest) = if let Some(rest) = s.strip_prefix("https://") {
+            (DnsProtocol::Https, rest)
+        } else if let Some(rest) = s.strip_prefix("tls://") {
+            (DnsProtocol::Tls, rest)
+        } else {
+            return Err(invalid());
+        };
+
+        let (tls_name, host) = rest.split_once('@').ok_or_else(invalid)?;
+        if tls_name.is_empty() {
+            return Err(invalid());
+        }
+
+        let default_port = match protocol {
+            DnsProtocol::Https => 443,
+            DnsProtocol::Tls => 853,
+        };
+        let addr = match host.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => {
+                let ip = host
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse::<IpAddr>()
+                    .map_err(|_| invalid())?;
+                SocketAddr::new(ip, default_port)
+            }
+        };
+
+        Ok(Self {
+            protocol,
+            addr,
+            tls_name: tls_name.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for DnsUpstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.protocol {
+            DnsProtocol::Https => "https",
+            DnsProtocol::Tls => "tls",
+        };
+        write!(f, "{scheme}://{}@{}", self.tls_name, self.addr)
+    }
+}
 
-    let google_ns = NameServerConfig {
-        socket_addr: SocketAddr::new(google_addr, 443),
-        protocol: Protocol::Https,
-        tls_dns_name: Some("dns.google".to_string()),
-        trust_negative_responses: false,
-        tls_config: None,
-        bind_addr: None,
+/// Resolver settings for both the libp2p DNS transport and standalone lookups.
+pub fn resolver_config(
+    config: &DnsResolverConfig,
+) -> Result<(ResolverConfig, ResolverOpts), DnsConfigError> {
+    let upstreams = match config {
+        DnsResolverConfig::System => {
+            let (resolver_config, opts) = hickory_resolver::system_conf::read_system_conf()
+                .map_err(|e| DnsConfigError::SystemConfig(e.to_string()))?;
+            info!("Using system DNS resolver");
+            return Ok((resolver_config, opts));
+        }
+        DnsResolverConfig::Encrypted(upstreams) => upstreams,
     };
 
+    if upstreams.is_empty() {
+        return Err(DnsConfigError::NoUpstreams);
+    }
+
     let mut resolver_config = ResolverConfig::new();
-    resolver_config.add_name_server(cloudflare_ns);
-    resolver_config.add_name_server(google_ns);
+    for upstream in upstreams {
+        resolver_config.add_name_server(NameServerConfig {
+            socket_addr: upstream.addr,
+            protocol: match upstream.protocol {
+                DnsProtocol::Https => Protocol::Https,
+                DnsProtocol::Tls => Protocol::Tls,
+            },
+            tls_dns_name: Some(upstream.tls_name.clone()),
+            trust_negative_responses: false,
+            tls_config: None,
+            bind_addr: None,
+        });
+    }
 
     let mut opts = ResolverOpts::default();
     opts.num_concurrent_reqs = 2;
     opts.cache_size = 256;
     opts.rotate = true;
 
-    info!("Built DoH resolver with Cloudflare (1.1.1.1) and Google (8.8.8.8)");
+    info!(
+        upstreams = ?upstreams.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        "Using encrypted DNS resolver"
+    );
+
+    Ok((resolver_config, opts))
+}
+
+pub fn build_resolver(config: &DnsResolverConfig) -> Result<TokioAsyncResolver, DnsConfigError> {
+    let (resolver_config, opts) = resolver_config(config)?;
+    Ok(TokioAsyncResolver::tokio(resolver_config, opts))
+}
+
+/// Builds a DoH resolver over the default upstreams, bypassing OS/ISP DNS.
+pub fn build_doh_resolver() -> TokioAsyncResolver {
+    build_resolver(&DnsResolverConfig::default()).expect("default DNS upstreams are valid")
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DnsConfigError {
+    #[error("Invalid DNS upstream '{0}', expected scheme://tls-name@ip[:port]")]
+    InvalidUpstream(String),
+
+    #[error("No encrypted DNS upstreams configured")]
+    NoUpstreams,
 
-    TokioAsyncResolver::tokio(resolver_config, opts)
+    #[error("Failed to read system DNS configuration: {0}")]
+    SystemConfig(String),
 }
 
 #[cfg(test)]
@@ -56,4 +178,31 @@ mod tests {
     fn test_build_doh_resolver_does_not_panic() {
         let _resolver = build_doh_resolver();
     }
+
+    #[test]
+    fn test_parse_upstreams() {
+        let doh: DnsUpstream = "https://dns.example.org@203.0.113.5".parse().unwrap();
+        assert_eq!(doh.protocol, DnsProtocol::Https);
+        assert_eq!(doh.addr, "203.0.113.5:443".parse().unwrap());
+        assert_eq!(doh.tls_name, "dns.example.org");
+
+        let dot: DnsUpstream = "tls://dns.example.org@[2001:db8::1]".parse().unwrap();
+        assert_eq!(dot.addr, "[2001:db8::1]:853".parse().unwrap());
+
+        let custom: DnsUpstream = "tls://dns.example.org@10.0.0.1:8853".parse().unwrap();
+        assert_eq!(custom.addr.port(), 8853);
+        assert_eq!(custom.to_string(), "tls://dns.example.org@10.0.0.1:8853");
+
+        assert!("udp://x@1.1.1.1".parse::<DnsUpstream>().is_err());
+        assert!("https://1.1.1.1".parse::<DnsUpstream>().is_err());
+        assert!("https://@1.1.1.1".parse::<DnsUpstream>().is_err());
+    }
+
+    #[test]
+    fn test_empty_upstreams_rejected() {
+        assert!(matches!(
+            resolver_config(&DnsResolverConfig::Encrypted(Vec::new())),
+            Err(DnsConfigError::NoUpstreams)
+        ));
+    }
 }
@@ -1,19 +1,38 @@
 // P2P networking layer built on libp2p with QUIC transport.
 
+pub mod bandwidth;
 pub mod behaviour;
 pub mod discovery;
 pub mod dns;
 pub mod messages;
 pub mod peers;
+pub mod presence;
+pub mod private_network;
+pub mod queues;
 pub mod relay;
+pub mod rendezvous;
+pub mod scoring;
 pub mod swarm;
+pub mod topics;
 pub mod transport;
+pub mod validation;
 
+pub use bandwidth::BandwidthTracker;
 pub use behaviour::{LiberteBehaviour, LiberteEvent};
 pub use discovery::load_bootstrap_peers;
-pub use dns::build_doh_resolver;
+pub use dns::{build_doh_resolver, build_resolver, DnsResolverConfig, DnsUpstream};
 pub use messages::{publish_message, subscribe_topic};
 pub use peers::{ConnectionInfo, PeerTracker};
+pub use presence::{sign_presence, verify_presence, PresenceInfo};
+pub use private_network::{load_swarm_key, PreSharedKey, SwarmKeyError};
+pub use queues::{
+    NotificationClass, NotificationQueueConfig, NotificationReceiver, NotificationStats,
+    OverflowPolicy, QueueConfig, QueueStats,
+};
 pub use relay::{dial_via_relay, request_relay_reservation};
+pub use rendezvous::{active_namespaces, RendezvousError};
+pub use scoring::PeerScoreConfig;
 pub use swarm::{spawn_swarm, SwarmCommand, SwarmNotification};
-pub use transport::build_swarm;
+pub use topics::{current_media_topic, current_topic, TopicDiff, TopicKind, TopicTable};
+pub use transport::{build_swarm, TransportConfig, TransportKind};
+pub use validation::{MessageAcceptance, MessageId, MessageRateLimiter};
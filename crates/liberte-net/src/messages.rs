@@ -1,34 +1,38 @@
+use chrono::Utc;
 use tokio::sync::mpsc;
 use tracing::{debug, error};
 
 use liberte_shared::crypto::{decrypt, encrypt, SymmetricKey};
 use liberte_shared::protocol::WireMessage;
-use liberte_shared::types::ChannelId;
 
 use crate::swarm::{SwarmCommand, SwarmNotification};
+use crate::topics::{active_topics_of, current_topic, TopicKind};
 
+/// Subscribe to every chat and media topic the channel is currently reachable on.
 pub async fn subscribe_topic(
     cmd_tx: &mpsc::Sender<SwarmCommand>,
-    channel_id: &ChannelId,
+    channel_key: &SymmetricKey,
 ) -> anyhow::Result<()> {
-    let topic = channel_id.to_topic();
-    debug!(topic = %topic, "Subscribing to channel topic");
-
-    cmd_tx
-        .send(SwarmCommand::SubscribeTopic(topic))
-        .await
-        .map_err(|_| anyhow::anyhow!("Swarm command channel closed"))?;
+    for kind in [TopicKind::Chat, TopicKind::Media] {
+        for topic in active_topics_of(kind, channel_key, Utc::now()) {
+            debug!(topic = %topic, ?kind, "Subscribing to channel topic");
+
+            cmd_tx
+                .send(SwarmCommand::SubscribeTopic { topic, kind })
+                .await
+                .map_err(|_| anyhow::anyhow!("Swarm command channel closed"))?;
+        }
+    }
 
     Ok(())
 }
 
 pub async fn publish_message(
     cmd_tx: &mpsc::Sender<SwarmCommand>,
-    channel_id: &ChannelId,
     channel_key: &SymmetricKey,
     message: &WireMessage,
 ) -> anyhow::Result<()> {
-    let topic = channel_id.to_topic();
+    let topic = current_topic(channel_key);
 
     let plaintext = message
         .to_bytes()
@@ -68,13 +72,15 @@ pub fn decode_message(data: &[u8], channel_key: &SymmetricKey) -> anyhow::Result
 /// Checks if a notification is a message on the given channel, and decrypts it if so.
 pub fn try_decode_notification(
     notification: &SwarmNotification,
-    channel_id: &ChannelId,
     channel_key: &SymmetricKey,
 ) -> Option<WireMessage> {
     match notification {
         SwarmNotification::MessageReceived { topic, data, .. } => {
-            let expected_topic = channel_id.to_topic();
-            if topic != &expected_topic {
+            let now = Utc::now();
+            let on_channel = [TopicKind::Chat, TopicKind::Media]
+                .into_iter()
+                .any(|kind| active_topics_of(kind, channel_key, now).contains(topic));
+            if !on_channel {
                 return None;
             }
 
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use libp2p::{Multiaddr, PeerId};
 use tracing::debug;
@@ -11,6 +12,10 @@ pub struct ConnectionInfo {
     pub address: Multiaddr,
     pub mode: ConnectionMode,
     pub connected_at: u64,
+    /// Latest ping round-trip time, once a ping has completed.
+    pub rtt: Option<Duration>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +47,9 @@ impl PeerTracker {
             address: address.clone(),
             mode: mode.clone(),
             connected_at: now,
+            rtt: None,
+            bytes_in: 0,
+            bytes_out: 0,
         };
 
         debug!(
@@ -68,6 +76,19 @@ impl PeerTracker {
         }
     }
 
+    pub fn record_rtt(&mut self, peer_id: &PeerId, rtt: Duration) {
+        if let Some(info) = self.peers.get_mut(peer_id) {
+            info.rtt = Some(rtt);
+        }
+    }
+
+    pub fn update_bandwidth(&mut self, peer_id: &PeerId, bytes_in: u64, bytes_out: u64) {
+        if let Some(info) = self.peers.get_mut(peer_id) {
+            info.bytes_in = bytes_in;
+            info.bytes_out = bytes_out;
+        }
+    }
+
     pub fn get(&self, peer_id: &PeerId) -> Option<&ConnectionInfo> {
         self.peers.get(peer_id)
     }
@@ -174,6 +195,20 @@ mod tests {
         assert_eq!(tracker.connection_mode(&peer), ConnectionMode::Direct);
     }
 
+    #[test]
+    fn test_stats_recorded() {
+        let mut tracker = PeerTracker::new();
+        let peer = test_peer_id();
+
+        tracker.on_connected(peer, test_addr(), false);
+        tracker.record_rtt(&peer, Duration::from_millis(42));
+        tracker.update_bandwidth(&peer, 1000, 250);
+
+        let info = tracker.get(&peer).unwrap();
+        assert_eq!(info.rtt, Some(Duration::from_millis(42)));
+        assert_eq!((info.bytes_in, info.bytes_out), (1000, 250));
+    }
+
     #[test]
     fn test_connected_peers_list() {
         let mut tracker = PeerTracker::new();
@@ -0,0 +1,184 @@
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::kad::{Record, RecordKey};
+use libp2p::{Multiaddr, PeerId};
+
+use liberte_shared::identity::Identity;
+use liberte_shared::presence::{presence_dht_key, PresenceRecord, SignedPresence};
+use liberte_shared::types::UserId;
+
+/// A verified presence record, ready to dial.
+#[derive(Debug, Clone)]
+pub struct PresenceInfo {
+    pub user_id: UserId,
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    pub published_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub fn presence_record_key(user_id: &UserId) -> RecordKey {
+    RecordKey::new(&presence_dht_key(user_id))
+}
+
+/// Build a presence record signed by both the identity and the libp2p key.
+pub fn sign_presence(
+    identity: &Identity,
+    keypair: &Keypair,
+    addresses: &[Multiaddr],
+    ttl: chrono::Duration,
+) -> anyhow::Result<SignedPresence> {
+    let record = PresenceRecord::new(
+        identity.user_id(),
+        keypair.public().encode_protobuf(),
+        addresses.iter().map(|a| a.to_string()).collect(),
+        ttl,
+    );
+
+    let bytes = record.signing_bytes()?;
+    let peer_signature = keypair
+        .sign(&bytes)
+        .map_err(|e| anyhow::anyhow!("libp2p signing error: {e}"))?;
+
+    Ok(SignedPresence::sign(record, identity, peer_signature)?)
+}
+
+/// Verify both signatures and the expiry of a presence record.
+pub fn verify_presence(signed: &SignedPresence) -> anyhow::Result<PresenceInfo> {
+    signed.verify_identity()?;
+
+    let public_key = PublicKey::try_decode_protobuf(&signed.record.peer_public_key)
+        .map_err(|e| anyhow::anyhow!("Invalid libp2p public key: {e}"))?;
+    let bytes = signed.record.signing_bytes()?;
+    if !public_key.verify(&bytes, &signed.peer_signature) {
+        anyhow::bail!("Invalid libp2p signature on presence record");
+    }
+
+    let addresses = signed
+        .record
+        .addresses
+        .iter()
+        .filter_map(|a| a.parse().ok())
+        .collect();
+
+    Ok(PresenceInfo {
+        user_id: signed.record.user_id.clone(),
+        peer_id: public_key.to_peer_id(),
+        addresses,
+        published_at: signed.record.published_at,
+        expires_at: signed.record.expires_at,
+    })
+}
+
+/// Keep whichever of `best` and `found` was published last: DHT peers that
+/// missed a republication still hold the stale record. Returns whether
+/// `found` was kept.
+pub fn keep_newest(best: &mut Option<PresenceInfo>, found: PresenceInfo) -> bool {
+    let newer = match best {
+        Some(best) => found.published_at > best.published_at,
+        None => true,
+    };
+    if newer {
+        *best = Some(found);
+    }
+    newer
+}
+
+/// Whether an inbound record should replace the one already stored under
+/// its key. A replayed older record, still signed and unexpired, must not
+/// roll the user's addresses back.
+pub fn supersedes_stored(stored: Option<&Record>, incoming: PresenceInfo) -> bool {
+    let mut best = stored.and_then(|r| presence_from_record(r).ok());
+    keep_newest(&mut best, incoming)
+}
+
+/// Decode and verify a DHT record, checking that it is stored under the
+/// key belonging to the user it claims to describe.
+pub fn presence_from_record(record: &Record) -> anyhow::Result<PresenceInfo> {
+    let signed = SignedPresence::from_bytes(&record.value)
+        .map_err(|e| anyhow::anyhow!("Deserialization error: {e}"))?;
+    if record.key != presence_record_key(&signed.record.user_id) {
+        anyhow::bail!("Presence record stored under a foreign key");
+    }
+    verify_presence(&signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_record(identity: &Identity, keypair: &Keypair) -> Record {
+        let addr: Multiaddr = "/ip4/127.0.0.1/udp/4001/quic-v1".parse().unwrap();
+        let signed = sign_presence(identity, keypair, &[addr], chrono::Duration::hours(1)).unwrap();
+        Record::new(
+            presence_record_key(&identity.user_id()),
+            signed.to_bytes().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_presence_record_roundtrip() {
+        let identity = Identity::generate();
+        let keypair = Keypair::generate_ed25519();
+
+        let info = presence_from_record(&test_record(&identity, &keypair)).unwrap();
+        assert_eq!(info.user_id, identity.user_id());
+        assert_eq!(info.peer_id, keypair.public().to_peer_id());
+        assert_eq!(info.addresses.len(), 1);
+    }
+
+    #[test]
+    fn test_keep_newest_presence() {
+        let identity = Identity::generate();
+        let keypair = Keypair::generate_ed25519();
+        let fresh = presence_from_record(&test_record(&identity, &keypair)).unwrap();
+        let stale = PresenceInfo {
+            published_at: fresh.published_at - chrono::Duration::minutes(30),
+            addresses: Vec::new(),
+            ..fresh.clone()
+        };
+
+        let mut best = None;
+        keep_newest(&mut best, stale.clone());
+        keep_newest(&mut best, fresh.clone());
+        keep_newest(&mut best, stale);
+        assert_eq!(best.unwrap().published_at, fresh.published_at);
+    }
+
+    #[test]
+    fn test_stale_record_does_not_overwrite_stored() {
+        let identity = Identity::generate();
+        let keypair = Keypair::generate_ed25519();
+        let stale = test_record(&identity, &keypair);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let fresh = test_record(&identity, &keypair);
+        let stale_info = presence_from_record(&stale).unwrap();
+        let fresh_info = presence_from_record(&fresh).unwrap();
+        assert!(fresh_info.published_at > stale_info.published_at);
+
+        assert!(supersedes_stored(None, stale_info.clone()));
+        assert!(supersedes_stored(Some(&stale), fresh_info.clone()));
+        assert!(!supersedes_stored(Some(&fresh), stale_info));
+        assert!(!supersedes_stored(Some(&fresh), fresh_info));
+    }
+
+    #[test]
+    fn test_presence_under_wrong_key_rejected() {
+        let identity = Identity::generate();
+        let keypair = Keypair::generate_ed25519();
+
+        let mut record = test_record(&identity, &keypair);
+        record.key = presence_record_key(&Identity::generate().user_id());
+        assert!(presence_from_record(&record).is_err());
+    }
+
+    #[test]
+    fn test_presence_with_swapped_peer_key_rejected() {
+        let identity = Identity::generate();
+        let keypair = Keypair::generate_ed25519();
+        let record = test_record(&identity, &keypair);
+
+        let mut signed = SignedPresence::from_bytes(&record.value).unwrap();
+        signed.record.peer_public_key = Keypair::generate_ed25519().public().encode_protobuf();
+        assert!(verify_presence(&signed).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::{Boxed, Transport};
+use libp2p::core::upgrade::Version;
+use libp2p::identity::Keypair;
+use libp2p::pnet::PnetConfig;
+use libp2p::{noise, yamux, PeerId};
+
+pub use libp2p::pnet::PreSharedKey;
+
+/// Read a swarm key in the go-libp2p key file format:
+///
+/// ```text
+/// /key/swarm/psk/1.0.0/
+/// /base16/
+/// <64 hex chars>
+/// ```
+pub fn load_swarm_key(path: &Path) -> Result<PreSharedKey, SwarmKeyError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| SwarmKeyError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    contents
+        .parse()
+        .map_err(|e| SwarmKeyError::Invalid(format!("{e:?}")))
+}
+
+/// Noise + Yamux over a stream transport (TCP, WebSocket, relay circuits).
+/// With a swarm key, the pnet handshake runs first, so peers without the
+/// key fail before any libp2p protocol is spoken.
+pub fn secure_stream_transport<T>(
+    transport: T,
+    key: &Keypair,
+    swarm_key: Option<PreSharedKey>,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, noise::Error>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let noise_config = noise::Config::new(key)?;
+
+    let transport = match swarm_key {
+        Some(psk) => transport
+            .and_then(move |socket, _| PnetConfig::new(psk).handshake(socket))
+            .upgrade(Version::V1Lazy)
+            .authenticate(noise_config)
+            .multiplex(yamux::Config::default())
+            .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+            .boxed(),
+        None => transport
+            .upgrade(Version::V1Lazy)
+            .authenticate(noise_config)
+            .multiplex(yamux::Config::default())
+            .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+            .boxed(),
+    };
+    Ok(transport)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SwarmKeyError {
+    #[error("Failed to read swarm key {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid swarm key file: {0}")]
+    Invalid(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use libp2p::core::transport::{DialOpts, ListenerId, MemoryTransport, PortUse, TransportEvent};
+    use libp2p::core::Endpoint;
+
+    fn psk(byte: u8) -> PreSharedKey {
+        PreSharedKey::new([byte; 32])
+    }
+
+    #[test]
+    fn test_key_file_format() {
+        let key = psk(7);
+        assert_eq!(key.to_string().parse::<PreSharedKey>().unwrap(), key);
+        assert!("/key/swarm/psk/1.0.0/\n/base16/\nabcd\n"
+            .parse::<PreSharedKey>()
+            .is_err());
+    }
+
+    /// Dial a listener over an in-memory transport; true if the connection upgraded.
+    async fn connects(
+        listener_key: Option<PreSharedKey>,
+        dialer_key: Option<PreSharedKey>,
+    ) -> bool {
+        let addr: libp2p::Multiaddr = format!("/memory/{}", next_memory_port()).parse().unwrap();
+
+        let mut listener = secure_stream_transport(
+            MemoryTransport::default(),
+            &Keypair::generate_ed25519(),
+            listener_key,
+        )
+        .unwrap();
+        listener
+            .listen_on(ListenerId::next(), addr.clone())
+            .unwrap();
+        tokio::spawn(async move {
+            while let Some(event) = listener.next().await {
+                if let TransportEvent::Incoming { upgrade, .. } = event {
+                    let _ = upgrade.await;
+                }
+            }
+        });
+
+        let mut dialer = secure_stream_transport(
+            MemoryTransport::default(),
+            &Keypair::generate_ed25519(),
+            dialer_key,
+        )
+        .unwrap();
+        let dial = dialer
+            .dial(
+                addr,
+                DialOpts {
+                    role: Endpoint::Dialer,
+                    port_use: PortUse::New,
+                },
+            )
+            .unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(2), dial)
+            .await
+            .map(|res| res.is_ok())
+            .unwrap_or(false)
+    }
+
+    fn next_memory_port() -> u64 {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT: AtomicU64 = AtomicU64::new(40_000);
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    }
+
+    #[tokio::test]
+    async fn test_peers_need_matching_swarm_key() {
+        assert!(connects(Some(psk(1)), Some(psk(1))).await);
+        assert!(!connects(Some(psk(1)), Some(psk(2))).await);
+        assert!(!connects(Some(psk(1)), None).await);
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use thiserror::Error;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::swarm::SwarmNotification;
+
+/// Which queue a notification travels on. The receiver drains control
+/// first, then realtime, then chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationClass {
+    /// Peer lifecycle, identify, relay and dial events.
+    Control,
+    /// Messages on chat topics.
+    Chat,
+    /// Messages on media topics (voice frames).
+    Realtime,
+}
+
+const DRAIN_ORDER: [NotificationClass; 3] = [
+    NotificationClass::Control,
+    NotificationClass::Realtime,
+    NotificationClass::Chat,
+];
+
+impl NotificationClass {
+    fn index(self) -> usize {
+        match self {
+            NotificationClass::Control => 0,
+            NotificationClass::Chat => 1,
+            NotificationClass::Realtime => 2,
+        }
+    }
+}
+
+/// What to do when a queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Evict the oldest queued notification to make room.
+    DropOldest,
+    /// Discard the incoming notification.
+    DropNewest,
+    /// Hold the notification on the producer side until the consumer
+    /// catches up. The hold-back is bounded by the queue capacity; past
+    /// that the incoming notification is refused, and the producer should
+    /// stop taking in work while [`NotificationSender::is_saturated`].
+    Backpressure,
+}
+
+/// The notification was not queued: its queue was full under
+/// `DropNewest`, or full with a full hold-back under `Backpressure`.
+#[derive(Debug, Error)]
+#[error("Notification queue full")]
+pub struct QueueFull(pub Box<SwarmNotification>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NotificationQueueConfig {
+    pub control: QueueConfig,
+    pub chat: QueueConfig,
+    pub realtime: QueueConfig,
+}
+
+impl Default for NotificationQueueConfig {
+    fn default() -> Self {
+        Self {
+            control: QueueConfig {
+                capacity: 256,
+                policy: OverflowPolicy::Backpressure,
+            },
+            chat: QueueConfig {
+                capacity: 512,
+                policy: OverflowPolicy::Backpressure,
+            },
+            // ~1s of voice from a handful of speakers; stale audio is useless
+            realtime: QueueConfig {
+                capacity: 128,
+                policy: OverflowPolicy::DropOldest,
+            },
+        }
+    }
+}
+
+/// Counters for one queue since the swarm started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Currently waiting in the queue.
+    pub queued: usize,
+    /// Currently held back by the producer (backpressure).
+    pub held: usize,
+    pub delivered: u64,
+    pub dropped: u64,
+    /// Notifications that had to wait in the hold-back at least once.
+    pub deferred: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NotificationStats {
+    pub control: QueueStats,
+    pub chat: QueueStats,
+    pub realtime: QueueStats,
+}
+
+struct ClassQueue {
+    config: QueueConfig,
+    items: VecDeque<SwarmNotification>,
+    delivered: u64,
+    dropped: u64,
+    deferred: u64,
+}
+
+impl ClassQueue {
+    fn new(config: QueueConfig) -> Self {
+        Self {
+            config,
+            items: VecDeque::with_capacity(config.capacity.min(1024)),
+            delivered: 0,
+            dropped: 0,
+            deferred: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.items.len() >= self.config.capacity
+    }
+}
+
+struct Shared {
+    queues: Mutex<[ClassQueue; 3]>,
+    /// Signalled when a notification is queued or the sender is dropped.
+    ready: Notify,
+    /// Signalled when the receiver frees a slot.
+    space: Notify,
+    closed: AtomicBool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, [ClassQueue; 3]> {
+        self.queues.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Create a bounded, prioritized notification channel. Sending never
+/// blocks, so a slow consumer cannot stall the swarm loop.
+pub fn notification_channel(
+    config: NotificationQueueConfig,
+) -> (NotificationSender, NotificationReceiver) {
+    let shared = Arc::new(Shared {
+        queues: Mutex::new([
+            ClassQueue::new(config.control),
+            ClassQueue::new(config.chat),
+            ClassQueue::new(config.realtime),
+        ]),
+        ready: Notify::new(),
+        space: Notify::new(),
+        closed: AtomicBool::new(false),
+    });
+    (
+        NotificationSender {
+            shared: shared.clone(),
+            held: Default::default(),
+        },
+        NotificationReceiver { shared },
+    )
+}
+
+/// Producer half, owned by the swarm loop.
+pub struct NotificationSender {
+    shared: Arc<Shared>,
+    /// Notifications held back from full `Backpressure` queues, in order.
+    held: [VecDeque<SwarmNotification>; 3],
+}
+
+impl NotificationSender {
+    /// Queue `notification`, or hand it back if the queue's policy refuses it.
+    /// Under `DropOldest`, returns the notification evicted to make room.
+    pub fn send(
+        &mut self,
+        class: NotificationClass,
+        notification: SwarmNotification,
+    ) -> Result<Option<SwarmNotification>, QueueFull> {
+        let idx = class.index();
+        let mut queues = self.shared.lock();
+        let queue = &mut queues[idx];
+
+        let mut evicted = None;
+        match queue.config.policy {
+            OverflowPolicy::DropOldest => {
+                if queue.is_full() {
+                    evicted = queue.items.pop_front();
+                    queue.dropped += 1;
+                }
+                queue.items.push_back(notification);
+            }
+            OverflowPolicy::DropNewest => {
+                if queue.is_full() {
+                    queue.dropped += 1;
+                    return Err(QueueFull(Box::new(notification)));
+                }
+                queue.items.push_back(notification);
+            }
+            OverflowPolicy::Backpressure => {
+                // Anything already held back goes first to keep ordering
+                if queue.is_full() || !self.held[idx].is_empty() {
+                    if self.held[idx].len() >= queue.config.capacity {
+                        queue.dropped += 1;
+                        if queue.dropped.is_power_of_two() {
+                            warn!(
+                                ?class,
+                                dropped = queue.dropped,
+                                "Notification consumer too slow"
+                            );
+                        }
+                        return Err(QueueFull(Box::new(notification)));
+                    }
+                    queue.deferred += 1;
+                    self.held[idx].push_back(notification);
+                    return Ok(None);
+                }
+                queue.items.push_back(notification);
+            }
+        }
+
+        drop(queues);
+        self.shared.ready.notify_one();
+        Ok(evicted)
+    }
+
+    pub fn has_held(&self) -> bool {
+        self.held.iter().any(|h| !h.is_empty())
+    }
+
+    /// Whether a `Backpressure` hold-back is full, so that the next
+    /// notification of its class would be refused.
+    pub fn is_saturated(&self) -> bool {
+        let queues = self.shared.lock();
+        queues.iter().zip(&self.held).any(|(queue, held)| {
+            queue.config.policy == OverflowPolicy::Backpressure
+                && held.len() >= queue.config.capacity
+        })
+    }
+
+    /// Resolves once the receiver has freed at least one slot.
+    pub async fn space_available(&self) {
+        self.shared.space.notified().await;
+    }
+
+    /// Move held-back notifications into their queues while there is room.
+    pub fn flush_held(&mut self) {
+        let mut moved = false;
+        {
+            let mut queues = self.shared.lock();
+            for (queue, held) in queues.iter_mut().zip(self.held.iter_mut()) {
+                while !queue.is_full() {
+                    match held.pop_front() {
+                        Some(notification) => {
+                            queue.items.push_back(notification);
+                            moved = true;
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+        if moved {
+            self.shared.ready.notify_one();
+        }
+    }
+
+    pub fn stats(&self) -> NotificationStats {
+        let queues = self.shared.lock();
+        let stats_of = |class: NotificationClass| {
+            let queue = &queues[class.index()];
+            QueueStats {
+                queued: queue.items.len(),
+                held: self.held[class.index()].len(),
+                delivered: queue.delivered,
+                dropped: queue.dropped,
+                deferred: queue.deferred,
+            }
+        };
+        NotificationStats {
+            control: stats_of(NotificationClass::Control),
+            chat: stats_of(NotificationClass::Chat),
+            realtime: stats_of(NotificationClass::Realtime),
+        }
+    }
+}
+
+impl Drop for NotificationSender {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.ready.notify_one();
+    }
+}
+
+/// Consumer half, handed to the application.
+pub struct NotificationReceiver {
+    shared: Arc<Shared>,
+}
+
+impl NotificationReceiver {
+    /// Next notification by priority, or `None` once the swarm stopped and
+    /// every queue is drained.
+    pub async fn recv(&mut self) -> Option<SwarmNotification> {
+        loop {
+            if let Some(notification) = self.try_recv() {
+                return Some(notification);
+            }
+            if self.shared.closed.load(Ordering::Acquire) {
+                // Items queued just before close are still delivered
+                return self.try_recv();
+            }
+            self.shared.ready.notified().await;
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<SwarmNotification> {
+        let notification = {
+            let mut queues = self.shared.lock();
+            DRAIN_ORDER.iter().find_map(|class| {
+                let queue = &mut queues[class.index()];
+                let notification = queue.items.pop_front()?;
+                queue.delivered += 1;
+                Some(notification)
+            })
+        };
+        if notification.is_some() {
+            self.shared.space.notify_one();
+        }
+        notification
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::PeerId;
+
+    fn config(capacity: usize, policy: OverflowPolicy) -> QueueConfig {
+        QueueConfig { capacity, policy }
+    }
+
+    fn dial_failed() -> (PeerId, SwarmNotification) {
+        let peer_id = PeerId::random();
+        (peer_id, SwarmNotification::DialFailed { peer_id })
+    }
+
+    fn peer_of(notification: Option<SwarmNotification>) -> PeerId {
+        match notification {
+            Some(SwarmNotification::DialFailed { peer_id }) => peer_id,
+            other => panic!("unexpected notification: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_realtime_drops_oldest_and_control_first() {
+        let (mut tx, mut rx) = notification_channel(NotificationQueueConfig {
+            realtime: config(2, OverflowPolicy::DropOldest),
+            ..Default::default()
+        });
+
+        let frames: Vec<_> = (0..3).map(|_| dial_failed()).collect();
+        for (_, n) in &frames[..2] {
+            assert!(tx
+                .send(NotificationClass::Realtime, n.clone())
+                .unwrap()
+                .is_none());
+        }
+        // The evicted frame is handed back so it can be reported
+        let evicted = tx.send(NotificationClass::Realtime, frames[2].1.clone());
+        assert_eq!(peer_of(evicted.unwrap()), frames[0].0);
+        let (control_peer, control) = dial_failed();
+        tx.send(NotificationClass::Control, control).unwrap();
+
+        assert_eq!(peer_of(rx.try_recv()), control_peer);
+        assert_eq!(peer_of(rx.try_recv()), frames[1].0);
+        assert_eq!(peer_of(rx.try_recv()), frames[2].0);
+        assert!(rx.try_recv().is_none());
+
+        let stats = tx.stats();
+        assert_eq!(stats.realtime.dropped, 1);
+        assert_eq!(stats.realtime.delivered, 2);
+        assert_eq!(stats.control.delivered, 1);
+    }
+
+    #[test]
+    fn test_backpressure_holds_in_order() {
+        let (mut tx, mut rx) = notification_channel(NotificationQueueConfig {
+            chat: config(1, OverflowPolicy::Backpressure),
+            ..Default::default()
+        });
+
+        let msgs: Vec<_> = (0..3).map(|_| dial_failed()).collect();
+        tx.send(NotificationClass::Chat, msgs[0].1.clone()).unwrap();
+        assert!(!tx.is_saturated());
+        tx.send(NotificationClass::Chat, msgs[1].1.clone()).unwrap();
+        assert!(tx.is_saturated());
+
+        // Capacity 1 plus a hold-back of 1; the third is handed back
+        let refused = tx.send(NotificationClass::Chat, msgs[2].1.clone());
+        assert_eq!(peer_of(refused.err().map(|e| *e.0)), msgs[2].0);
+        let stats = tx.stats().chat;
+        assert_eq!((stats.queued, stats.held, stats.dropped), (1, 1, 1));
+
+        assert_eq!(peer_of(rx.try_recv()), msgs[0].0);
+        assert!(rx.try_recv().is_none());
+        assert!(tx.has_held());
+
+        tx.flush_held();
+        assert!(!tx.has_held());
+        assert!(!tx.is_saturated());
+        assert_eq!(peer_of(rx.try_recv()), msgs[1].0);
+        assert_eq!(tx.stats().chat.deferred, 1);
+    }
+
+    #[tokio::test]
+    async fn test_recv_ends_after_sender_dropped() {
+        let (mut tx, mut rx) = notification_channel(NotificationQueueConfig::default());
+        let (peer, n) = dial_failed();
+        tx.send(NotificationClass::Control, n).unwrap();
+        drop(tx);
+
+        assert_eq!(peer_of(rx.recv().await), peer);
+        assert!(rx.recv().await.is_none());
+    }
+}
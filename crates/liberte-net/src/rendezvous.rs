@@ -0,0 +1,185 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use libp2p::multiaddr::Protocol;
+use libp2p::rendezvous::{self, Cookie, Namespace};
+use libp2p::{Multiaddr, PeerId};
+use tracing::{debug, warn};
+
+use liberte_shared::constants::RENDEZVOUS_TTL_SECS;
+use liberte_shared::crypto::{derive_rendezvous_namespace, SymmetricKey};
+
+use crate::topics::active_epochs;
+
+/// Rendezvous namespaces a channel's members register under at `at`. Like
+/// topics they rotate per epoch, so the rendezvous node only learns which
+/// peers share an opaque namespace for a while.
+pub fn active_namespaces(channel_key: &SymmetricKey, at: DateTime<Utc>) -> Vec<String> {
+    active_epochs(at)
+        .into_iter()
+        .map(|epoch| derive_rendezvous_namespace(channel_key, epoch))
+        .collect()
+}
+
+/// Registrations and discovery cookies held at one rendezvous node.
+#[derive(Debug)]
+pub struct RendezvousState {
+    pub peer_id: PeerId,
+    pub addr: Multiaddr,
+    connected: bool,
+    namespaces: HashSet<Namespace>,
+    cookies: HashMap<Namespace, Cookie>,
+}
+
+impl RendezvousState {
+    /// `addr` must end in `/p2p/<peer id>` of the rendezvous node.
+    pub fn new(addr: Multiaddr) -> Result<Self, RendezvousError> {
+        let peer_id = match addr.iter().last() {
+            Some(Protocol::P2p(peer_id)) => peer_id,
+            _ => return Err(RendezvousError::MissingPeerId(addr)),
+        };
+        Ok(Self {
+            peer_id,
+            addr,
+            connected: false,
+            namespaces: HashSet::new(),
+            cookies: HashMap::new(),
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Replace the namespace set, unregistering the ones dropped and
+    /// registering (and discovering) the new ones if connected.
+    pub fn set_namespaces(
+        &mut self,
+        client: &mut rendezvous::client::Behaviour,
+        names: impl IntoIterator<Item = String>,
+    ) {
+        let next: HashSet<Namespace> = names
+            .into_iter()
+            .filter_map(|name| match Namespace::new(name) {
+                Ok(ns) => Some(ns),
+                Err(e) => {
+                    warn!(error = %e, "Skipping invalid rendezvous namespace");
+                    None
+                }
+            })
+            .collect();
+
+        let removed: Vec<Namespace> = self.namespaces.difference(&next).cloned().collect();
+        let added: Vec<Namespace> = next.difference(&self.namespaces).cloned().collect();
+        self.namespaces = next;
+
+        for ns in removed {
+            self.cookies.remove(&ns);
+            if self.connected {
+                client.unregister(ns, self.peer_id);
+            }
+        }
+        if self.connected {
+            for ns in added {
+                self.register(client, ns.clone());
+                self.discover(client, ns);
+            }
+        }
+    }
+
+    pub fn on_connected(&mut self, client: &mut rendezvous::client::Behaviour) {
+        self.connected = true;
+        self.refresh(client);
+    }
+
+    pub fn on_disconnected(&mut self) {
+        self.connected = false;
+    }
+
+    /// Renew every registration and fetch registrations made since the
+    /// last discovery.
+    pub fn refresh(&mut self, client: &mut rendezvous::client::Behaviour) {
+        if !self.connected {
+            return;
+        }
+        let namespaces: Vec<Namespace> = self.namespaces.iter().cloned().collect();
+        for ns in namespaces {
+            self.register(client, ns.clone());
+            self.discover(client, ns);
+        }
+    }
+
+    /// Remember the cookie of a discovery so the next one only returns new
+    /// registrations. Cookies of namespaces we no longer use are ignored.
+    pub fn on_discovered(&mut self, cookie: Cookie) {
+        if let Some(ns) = cookie.namespace() {
+            if self.namespaces.contains(ns) {
+                self.cookies.insert(ns.clone(), cookie);
+            }
+        }
+    }
+
+    fn register(&self, client: &mut rendezvous::client::Behaviour, ns: Namespace) {
+        if let Err(e) = client.register(ns, self.peer_id, Some(RENDEZVOUS_TTL_SECS)) {
+            // Retried on the next refresh, once an external address is known
+            debug!(error = %e, "Rendezvous registration deferred");
+        }
+    }
+
+    fn discover(&self, client: &mut rendezvous::client::Behaviour, ns: Namespace) {
+        let cookie = self.cookies.get(&ns).cloned();
+        client.discover(Some(ns), cookie, None, self.peer_id);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RendezvousError {
+    #[error("Rendezvous point address must end in /p2p/<peer id>: {0}")]
+    MissingPeerId(Multiaddr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use libp2p::identity::Keypair;
+
+    const POINT: &str =
+        "/ip4/1.2.3.4/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN";
+
+    #[test]
+    fn test_point_requires_peer_id() {
+        let state = RendezvousState::new(POINT.parse().unwrap()).unwrap();
+        assert_eq!(
+            state.peer_id.to_string(),
+            "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN"
+        );
+        assert!(RendezvousState::new("/ip4/1.2.3.4/tcp/4001".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_namespaces_follow_channel_key_and_epoch() {
+        let at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let a = active_namespaces(&[1u8; 32], at);
+        assert_eq!(a, active_namespaces(&[1u8; 32], at));
+        assert_ne!(a, active_namespaces(&[2u8; 32], at));
+        assert!(a.iter().all(|ns| Namespace::new(ns.clone()).is_ok()));
+    }
+
+    #[test]
+    fn test_cookies_dropped_with_namespace() {
+        let mut client = rendezvous::client::Behaviour::new(Keypair::generate_ed25519());
+        let mut state = RendezvousState::new(POINT.parse().unwrap()).unwrap();
+        state.set_namespaces(&mut client, ["aa".to_string(), "bb".to_string()]);
+
+        let aa = Namespace::from_static("aa");
+        state.on_discovered(Cookie::for_namespace(aa.clone()));
+        state.on_discovered(Cookie::for_namespace(Namespace::from_static("zz")));
+        assert!(state.cookies.contains_key(&aa));
+        assert_eq!(state.cookies.len(), 1);
+
+        state.set_namespaces(&mut client, ["bb".to_string()]);
+        assert!(state.cookies.is_empty());
+        assert_eq!(state.namespaces.len(), 1);
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use libp2p::gossipsub::{PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
+
+/// GossipSub peer-scoring settings. Topic names rotate, so `topic_params`
+/// is applied to every topic as it is subscribed rather than listed up front.
+#[derive(Debug, Clone)]
+pub struct PeerScoreConfig {
+    pub params: PeerScoreParams,
+    pub thresholds: PeerScoreThresholds,
+    pub topic_params: TopicScoreParams,
+    /// Connected peers scoring below this are disconnected.
+    pub disconnect_threshold: f64,
+    pub check_interval: Duration,
+}
+
+impl Default for PeerScoreConfig {
+    fn default() -> Self {
+        Self {
+            params: PeerScoreParams::default(),
+            thresholds: PeerScoreThresholds::default(),
+            topic_params: TopicScoreParams {
+                topic_weight: 1.0,
+                // Chat channels are often quiet, so don't punish peers for
+                // low mesh delivery rates (P3/P3b); only for invalid ones (P4).
+                mesh_message_deliveries_weight: 0.0,
+                mesh_failure_penalty_weight: 0.0,
+                invalid_message_deliveries_weight: -10.0,
+                invalid_message_deliveries_decay: 0.9,
+                ..TopicScoreParams::default()
+            },
+            disconnect_threshold: -100.0,
+            check_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl PeerScoreConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.params.validate()?;
+        self.thresholds.validate()?;
+        self.topic_params.validate()?;
+        if self.disconnect_threshold > self.thresholds.graylist_threshold {
+            return Err("Disconnect threshold must be <= graylist threshold".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        PeerScoreConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn test_disconnect_above_graylist_rejected() {
+        let config = PeerScoreConfig {
+            disconnect_threshold: 0.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
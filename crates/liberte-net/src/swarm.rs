@@ -1,25 +1,105 @@
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use futures::StreamExt;
 use libp2p::{
-    gossipsub, identify, kad, multiaddr::Protocol, relay, swarm::SwarmEvent, Multiaddr, PeerId,
+    gossipsub, identify,
+    kad::{self, store::RecordStore},
+    multiaddr::Protocol,
+    ping, relay, rendezvous,
+    swarm::{dial_opts::DialOpts, SwarmEvent},
+    Multiaddr, PeerId, Swarm,
 };
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 
-use crate::behaviour::LiberteEvent;
+use crate::behaviour::{LiberteBehaviour, LiberteEvent};
 use crate::discovery::load_bootstrap_peers;
-use crate::peers::PeerTracker;
-use crate::transport::build_swarm;
+use crate::dns::DnsResolverConfig;
+use crate::peers::{ConnectionInfo, PeerTracker};
+use crate::presence::{
+    keep_newest, presence_from_record, presence_record_key, sign_presence, supersedes_stored,
+    PresenceInfo,
+};
+use crate::queues::{
+    notification_channel, NotificationClass, NotificationQueueConfig, NotificationReceiver,
+    NotificationSender, NotificationStats, QueueFull,
+};
+use crate::rendezvous::RendezvousState;
+use crate::scoring::PeerScoreConfig;
+use crate::topics::TopicKind;
+use crate::transport::{build_swarm, is_global_addr, TransportConfig, TransportKind};
 
-use liberte_shared::constants::DEFAULT_QUIC_PORT;
+use liberte_shared::constants::{
+    PRESENCE_REPUBLISH_SECS, PRESENCE_TTL_SECS, RENDEZVOUS_REFRESH_SECS,
+};
+use liberte_shared::identity::Identity;
+use liberte_shared::types::UserId;
 
 #[derive(Debug)]
 pub enum SwarmCommand {
     Dial(Multiaddr),
-    PublishMessage { topic: String, data: Vec<u8> },
-    SubscribeTopic(String),
+    /// Dial a peer over several addresses, tried in dial-preference order.
+    DialPeer {
+        peer_id: PeerId,
+        addrs: Vec<Multiaddr>,
+    },
+    PublishMessage {
+        topic: String,
+        data: Vec<u8>,
+    },
+    SubscribeTopic {
+        topic: String,
+        kind: TopicKind,
+    },
+    /// Replies false if we were not subscribed to the topic.
+    UnsubscribeTopic {
+        topic: String,
+        reply: tokio::sync::oneshot::Sender<Result<bool, String>>,
+    },
+    /// Topics we are subscribed to.
+    ListSubscriptions(tokio::sync::oneshot::Sender<Vec<String>>),
+    /// Namespaces to register and discover under at the rendezvous point,
+    /// replacing the previous set.
+    SetRendezvousNamespaces(Vec<String>),
+    /// Use another rendezvous point (ending in `/p2p/<peer id>`), keeping
+    /// the current namespaces.
+    SetRendezvousPoint(Multiaddr),
+    /// Application verdict on a received message; only accepted messages
+    /// are forwarded, rejected ones count against the propagating peer.
+    ReportValidation {
+        message_id: gossipsub::MessageId,
+        propagation_source: PeerId,
+        acceptance: gossipsub::MessageAcceptance,
+    },
     GetPeers(tokio::sync::oneshot::Sender<Vec<PeerId>>),
+    /// Close all connections to a peer; replies false if none were open.
+    DisconnectPeer {
+        peer_id: PeerId,
+        reply: tokio::sync::oneshot::Sender<bool>,
+    },
+    /// Block a peer and close its connections; replies false if already banned.
+    BanPeer {
+        peer_id: PeerId,
+        reply: tokio::sync::oneshot::Sender<bool>,
+    },
+    /// Replies false if the peer was not banned.
+    UnbanPeer {
+        peer_id: PeerId,
+        reply: tokio::sync::oneshot::Sender<bool>,
+    },
+    GetListenAddrs(tokio::sync::oneshot::Sender<Vec<Multiaddr>>),
+    /// Snapshot of every connection with latency and byte counts.
+    GetStats(tokio::sync::oneshot::Sender<Vec<ConnectionInfo>>),
+    /// Depth and drop counters of the notification queues.
+    GetNotificationStats(tokio::sync::oneshot::Sender<NotificationStats>),
+    /// Re-publish our presence record now (e.g. after addresses changed).
+    PublishPresence,
+    LookupPresence {
+        user_id: UserId,
+        reply: tokio::sync::oneshot::Sender<Option<PresenceInfo>>,
+    },
     Shutdown,
 }
 
@@ -32,7 +112,10 @@ pub enum SwarmNotification {
     PeerDisconnected {
         peer_id: PeerId,
     },
+    /// Must be answered with `SwarmCommand::ReportValidation`.
     MessageReceived {
+        message_id: gossipsub::MessageId,
+        propagation_source: PeerId,
         source: Option<PeerId>,
         topic: String,
         data: Vec<u8>,
@@ -41,91 +124,261 @@ pub enum SwarmNotification {
         relay_peer: PeerId,
         relay_addr: Multiaddr,
     },
+    /// A peer told us where it listens; only globally reachable addresses
+    /// are kept.
+    PeerIdentified {
+        peer_id: PeerId,
+        listen_addrs: Vec<Multiaddr>,
+    },
+    DialFailed {
+        peer_id: PeerId,
+    },
 }
 
 pub struct SwarmConfig {
     pub bootstrap_peers_path: Option<PathBuf>,
-    pub listen_port: u16,
+    /// Listening transports/ports and the dial-preference policy.
+    pub transports: TransportConfig,
     pub extra_dials: Vec<Multiaddr>,
+    /// Peers remembered from previous sessions, seeded into Kademlia on start.
+    pub known_peers: Vec<(PeerId, Vec<Multiaddr>)>,
+    /// When set, a signed presence record for this identity is kept in the DHT.
+    pub presence_identity: Option<Identity>,
+    /// Resolver for `/dns*` multiaddrs (bootstrap entries, relays).
+    pub dns: DnsResolverConfig,
+    /// GossipSub peer scoring; `None` disables scoring and score-based disconnects.
+    pub peer_score: Option<PeerScoreConfig>,
+    /// Peers banned in previous sessions; connections to them are refused.
+    pub banned_peers: Vec<PeerId>,
+    /// Capacity and overflow policy of each notification queue.
+    pub notification_queues: NotificationQueueConfig,
+    /// Pre-shared swarm key file. When set, only peers holding the same key
+    /// can connect, and QUIC is disabled since pnet cannot protect it.
+    pub swarm_key_path: Option<PathBuf>,
+    /// Rendezvous node (`.../p2p/<peer id>`) used to find other members of
+    /// our channels. Kept connected while the swarm runs.
+    pub rendezvous_point: Option<Multiaddr>,
 }
 
 impl Default for SwarmConfig {
     fn default() -> Self {
         Self {
             bootstrap_peers_path: None,
-            listen_port: DEFAULT_QUIC_PORT,
+            transports: TransportConfig::default(),
             extra_dials: Vec::new(),
+            known_peers: Vec::new(),
+            presence_identity: None,
+            dns: DnsResolverConfig::default(),
+            peer_score: Some(PeerScoreConfig::default()),
+            banned_peers: Vec::new(),
+            notification_queues: NotificationQueueConfig::default(),
+            swarm_key_path: None,
+            rendezvous_point: None,
+        }
+    }
+}
+
+impl SwarmConfig {
+    /// The configured transports, without QUIC in private-network mode.
+    pub fn enabled_transports(&self) -> TransportConfig {
+        let mut transports = self.transports.clone();
+        if self.swarm_key_path.is_some() {
+            transports.disable(TransportKind::Quic);
         }
+        transports
     }
 }
 
 pub async fn spawn_swarm(
     keypair: libp2p::identity::Keypair,
     config: SwarmConfig,
-) -> anyhow::Result<(
-    mpsc::Sender<SwarmCommand>,
-    mpsc::Receiver<SwarmNotification>,
-    PeerId,
-)> {
-    let mut swarm = build_swarm(keypair)?;
+) -> anyhow::Result<(mpsc::Sender<SwarmCommand>, NotificationReceiver, PeerId)> {
+    if let Some(ref scoring) = config.peer_score {
+        scoring
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid peer score config: {e}"))?;
+    }
+
+    let mut rendezvous_point = config
+        .rendezvous_point
+        .clone()
+        .map(RendezvousState::new)
+        .transpose()?;
+
+    let (mut swarm, bandwidth) = build_swarm(keypair.clone(), &config)?;
     let local_peer_id = *swarm.local_peer_id();
 
-    let listen_addr_v4: Multiaddr = format!("/ip4/0.0.0.0/udp/{}/quic-v1", config.listen_port)
-        .parse()
-        .expect("valid multiaddr");
-    let listen_addr_v6: Multiaddr = format!("/ip6/::/udp/{}/quic-v1", config.listen_port)
-        .parse()
-        .expect("valid multiaddr");
+    let transports = config.enabled_transports();
 
-    swarm.listen_on(listen_addr_v4)?;
-    swarm.listen_on(listen_addr_v6)?;
+    let banned: HashSet<PeerId> = config.banned_peers.iter().copied().collect();
+    for peer_id in &banned {
+        swarm.behaviour_mut().blocked.block_peer(*peer_id);
+    }
+    if !banned.is_empty() {
+        info!(count = banned.len(), "Loaded banned peers");
+    }
 
-    info!(peer_id = %local_peer_id, port = config.listen_port, "Swarm listening");
+    let mut listening = 0;
+    for addr in transports.listen_addrs() {
+        match swarm.listen_on(addr.clone()) {
+            Ok(_) => listening += 1,
+            Err(e) => warn!(addr = %addr, error = %e, "Failed to listen"),
+        }
+    }
+    if listening == 0 && !transports.listen.is_empty() {
+        anyhow::bail!("Could not listen on any configured transport");
+    }
+
+    info!(peer_id = %local_peer_id, transports = ?transports.listen, "Swarm listening");
+
+    for (peer_id, addrs) in &config.known_peers {
+        if banned.contains(peer_id) {
+            continue;
+        }
+        // The book may predate the filter on identified addresses
+        let addrs = addrs.iter().filter(|addr| is_global_addr(addr)).cloned();
+        for addr in transports.sort_dial_addrs(addrs) {
+            swarm.behaviour_mut().kademlia.add_address(peer_id, addr);
+        }
+    }
+    let mut needs_bootstrap = !config.known_peers.is_empty();
+    if needs_bootstrap {
+        info!(
+            count = config.known_peers.len(),
+            "Seeded Kademlia from peer book"
+        );
+    }
 
     if let Some(ref path) = config.bootstrap_peers_path {
         let bootstrap_addrs = load_bootstrap_peers(path);
+
+        // Group by peer so each bootstrap node is dialed once, QUIC first
+        let mut by_peer: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
         for addr in &bootstrap_addrs {
-            if let Err(e) = swarm.dial(addr.clone()) {
-                warn!(addr = %addr, error = %e, "Failed to dial bootstrap peer");
-            } else {
-                if let Some(peer_id) = extract_peer_id(addr) {
-                    swarm
-                        .behaviour_mut()
-                        .kademlia
-                        .add_address(&peer_id, addr.clone());
+            match extract_peer_id(addr) {
+                Some(peer_id) => by_peer.entry(peer_id).or_default().push(addr.clone()),
+                None if transports.allows_dial(addr) => {
+                    if let Err(e) = swarm.dial(addr.clone()) {
+                        warn!(addr = %addr, error = %e, "Failed to dial bootstrap peer");
+                    }
                 }
-                debug!(addr = %addr, "Dialing bootstrap peer");
+                None => debug!(addr = %addr, "Skipping bootstrap address on disabled transport"),
             }
         }
-
-        if !bootstrap_addrs.is_empty() {
-            if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
-                warn!(error = %e, "Kademlia bootstrap failed to start");
+        for (peer_id, addrs) in by_peer {
+            let addrs = transports.sort_dial_addrs(addrs);
+            for addr in &addrs {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer_id, addr.clone());
             }
+            dial_peer(&mut swarm, peer_id, addrs);
+        }
+
+        needs_bootstrap |= !bootstrap_addrs.is_empty();
+    }
+
+    if needs_bootstrap {
+        if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
+            warn!(error = %e, "Kademlia bootstrap failed to start");
         }
     }
 
     for addr in &config.extra_dials {
+        if !transports.allows_dial(addr) {
+            warn!(addr = %addr, "Extra dial address uses a disabled transport");
+            continue;
+        }
         if let Err(e) = swarm.dial(addr.clone()) {
             warn!(addr = %addr, error = %e, "Failed to dial extra address");
         }
     }
 
+    if let Some(ref point) = rendezvous_point {
+        info!(peer = %point.peer_id, addr = %point.addr, "Dialing rendezvous point");
+        dial_peer(&mut swarm, point.peer_id, vec![point.addr.clone()]);
+    }
+
     let (cmd_tx, mut cmd_rx) = mpsc::channel::<SwarmCommand>(256);
-    let (notif_tx, notif_rx) = mpsc::channel::<SwarmNotification>(256);
+    let (mut notif_tx, notif_rx) = notification_channel(config.notification_queues);
+
+    let presence_identity = config.presence_identity;
+    let peer_score = config.peer_score;
 
     tokio::spawn(async move {
+        let mut banned = banned;
+        let mut media_topics: HashSet<String> = HashSet::new();
+        // Kept even without a point, so one set later starts with them
+        let mut rendezvous_namespaces: Vec<String> = Vec::new();
         let mut peer_tracker = PeerTracker::new();
+        // Each lookup keeps the newest record found until its query ends
+        let mut pending_lookups: HashMap<
+            kad::QueryId,
+            (
+                tokio::sync::oneshot::Sender<Option<PresenceInfo>>,
+                Option<PresenceInfo>,
+            ),
+        > = HashMap::new();
+
+        // First publish once listeners and relay reservations had time to settle
+        let republish_period = Duration::from_secs(PRESENCE_REPUBLISH_SECS);
+        let mut presence_interval = tokio::time::interval_at(
+            tokio::time::Instant::now() + Duration::from_secs(30),
+            republish_period,
+        );
+
+        let score_period = peer_score
+            .as_ref()
+            .map_or(Duration::from_secs(60), |s| s.check_interval);
+        let mut score_interval = tokio::time::interval(score_period);
+
+        let rendezvous_period = Duration::from_secs(RENDEZVOUS_REFRESH_SECS);
+        let mut rendezvous_interval = tokio::time::interval_at(
+            tokio::time::Instant::now() + rendezvous_period,
+            rendezvous_period,
+        );
 
         loop {
             tokio::select! {
+                _ = presence_interval.tick(), if presence_identity.is_some() => {
+                    if let Some(ref identity) = presence_identity {
+                        publish_presence(&mut swarm, identity, &keypair);
+                    }
+                }
+
+                _ = notif_tx.space_available(), if notif_tx.has_held() => {
+                    notif_tx.flush_held();
+                }
+
+                _ = score_interval.tick(), if peer_score.is_some() => {
+                    if let Some(ref scoring) = peer_score {
+                        disconnect_low_scoring_peers(&mut swarm, &peer_tracker, scoring);
+                    }
+                }
+
+                _ = rendezvous_interval.tick(), if rendezvous_point.is_some() => {
+                    if let Some(ref mut point) = rendezvous_point {
+                        if point.is_connected() {
+                            point.refresh(&mut swarm.behaviour_mut().rendezvous);
+                        } else {
+                            dial_peer(&mut swarm, point.peer_id, vec![point.addr.clone()]);
+                        }
+                    }
+                }
+
                 cmd = cmd_rx.recv() => {
                     match cmd {
                         Some(SwarmCommand::Dial(addr)) => {
-                            if let Err(e) = swarm.dial(addr.clone()) {
+                            if !transports.allows_dial(&addr) {
+                                warn!(addr = %addr, "Not dialing address on disabled transport");
+                            } else if let Err(e) = swarm.dial(addr.clone()) {
                                 error!(addr = %addr, error = %e, "Dial failed");
                             }
                         }
+                        Some(SwarmCommand::DialPeer { peer_id, addrs }) => {
+                            dial_peer(&mut swarm, peer_id, transports.sort_dial_addrs(addrs));
+                        }
                         Some(SwarmCommand::PublishMessage { topic, data }) => {
                             let gossipsub_topic = gossipsub::IdentTopic::new(&topic);
                             if let Err(e) = swarm
@@ -136,8 +389,17 @@ pub async fn spawn_swarm(
                                 error!(topic = %topic, error = %e, "Publish failed");
                             }
                         }
-                        Some(SwarmCommand::SubscribeTopic(topic)) => {
+                        Some(SwarmCommand::SubscribeTopic { topic, kind }) => {
+                            if kind == TopicKind::Media {
+                                media_topics.insert(topic.clone());
+                            }
                             let gossipsub_topic = gossipsub::IdentTopic::new(&topic);
+                            if let Some(ref scoring) = peer_score {
+                                let _ = swarm
+                                    .behaviour_mut()
+                                    .gossipsub
+                                    .set_topic_params(gossipsub_topic.clone(), scoring.topic_params.clone());
+                            }
                             if let Err(e) = swarm
                                 .behaviour_mut()
                                 .gossipsub
@@ -146,10 +408,120 @@ pub async fn spawn_swarm(
                                 error!(topic = %topic, error = %e, "Subscribe failed");
                             }
                         }
+                        Some(SwarmCommand::UnsubscribeTopic { topic, reply }) => {
+                            media_topics.remove(&topic);
+                            let gossipsub_topic = gossipsub::IdentTopic::new(&topic);
+                            let result = swarm
+                                .behaviour_mut()
+                                .gossipsub
+                                .unsubscribe(&gossipsub_topic)
+                                .map_err(|e| e.to_string());
+                            let _ = reply.send(result);
+                        }
+                        Some(SwarmCommand::ListSubscriptions(reply)) => {
+                            let topics = swarm
+                                .behaviour()
+                                .gossipsub
+                                .topics()
+                                .map(|hash| hash.to_string())
+                                .collect();
+                            let _ = reply.send(topics);
+                        }
+                        Some(SwarmCommand::SetRendezvousNamespaces(namespaces)) => {
+                            rendezvous_namespaces = namespaces.clone();
+                            match rendezvous_point {
+                                Some(ref mut point) => point.set_namespaces(
+                                    &mut swarm.behaviour_mut().rendezvous,
+                                    namespaces,
+                                ),
+                                None => debug!("No rendezvous point configured"),
+                            }
+                        }
+                        Some(SwarmCommand::SetRendezvousPoint(addr)) => {
+                            match RendezvousState::new(addr) {
+                                Ok(mut point) => {
+                                    let client = &mut swarm.behaviour_mut().rendezvous;
+                                    if let Some(ref mut previous) = rendezvous_point {
+                                        previous.set_namespaces(client, Vec::new());
+                                    }
+                                    point.set_namespaces(client, rendezvous_namespaces.clone());
+                                    info!(peer = %point.peer_id, addr = %point.addr, "Switching rendezvous point");
+                                    if swarm.is_connected(&point.peer_id) {
+                                        point.on_connected(&mut swarm.behaviour_mut().rendezvous);
+                                    } else {
+                                        dial_peer(&mut swarm, point.peer_id, vec![point.addr.clone()]);
+                                    }
+                                    rendezvous_point = Some(point);
+                                }
+                                Err(e) => warn!(error = %e, "Ignoring invalid rendezvous point"),
+                            }
+                        }
+                        Some(SwarmCommand::ReportValidation {
+                            message_id,
+                            propagation_source,
+                            acceptance,
+                        }) => {
+                            if let Err(e) = swarm
+                                .behaviour_mut()
+                                .gossipsub
+                                .report_message_validation_result(
+                                    &message_id,
+                                    &propagation_source,
+                                    acceptance,
+                                )
+                            {
+                                debug!(error = %e, "Validation report failed");
+                            }
+                        }
                         Some(SwarmCommand::GetPeers(reply)) => {
                             let peers = peer_tracker.connected_peers();
                             let _ = reply.send(peers);
                         }
+                        Some(SwarmCommand::DisconnectPeer { peer_id, reply }) => {
+                            let _ = reply.send(swarm.disconnect_peer_id(peer_id).is_ok());
+                        }
+                        Some(SwarmCommand::BanPeer { peer_id, reply }) => {
+                            let newly_banned = banned.insert(peer_id);
+                            if newly_banned {
+                                info!(peer = %peer_id, "Banning peer");
+                                swarm.behaviour_mut().blocked.block_peer(peer_id);
+                                swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                            }
+                            let _ = reply.send(newly_banned);
+                        }
+                        Some(SwarmCommand::UnbanPeer { peer_id, reply }) => {
+                            let was_banned = banned.remove(&peer_id);
+                            if was_banned {
+                                info!(peer = %peer_id, "Unbanning peer");
+                                swarm.behaviour_mut().blocked.unblock_peer(peer_id);
+                            }
+                            let _ = reply.send(was_banned);
+                        }
+                        Some(SwarmCommand::GetListenAddrs(reply)) => {
+                            let _ = reply.send(swarm.listeners().cloned().collect());
+                        }
+                        Some(SwarmCommand::GetNotificationStats(reply)) => {
+                            let _ = reply.send(notif_tx.stats());
+                        }
+                        Some(SwarmCommand::GetStats(reply)) => {
+                            for peer_id in peer_tracker.connected_peers() {
+                                let (bytes_in, bytes_out) = bandwidth.get(&peer_id);
+                                peer_tracker.update_bandwidth(&peer_id, bytes_in, bytes_out);
+                            }
+                            let _ = reply.send(peer_tracker.all_connections());
+                        }
+                        Some(SwarmCommand::PublishPresence) => {
+                            if let Some(ref identity) = presence_identity {
+                                publish_presence(&mut swarm, identity, &keypair);
+                            }
+                        }
+                        Some(SwarmCommand::LookupPresence { user_id, reply }) => {
+                            let query_id = swarm
+                                .behaviour_mut()
+                                .kademlia
+                                .get_record(presence_record_key(&user_id));
+                            pending_lookups.insert(query_id, (reply, None));
+                        }
                         Some(SwarmCommand::Shutdown) => {
                             info!("Swarm shutdown requested");
                             break;
@@ -161,12 +533,13 @@ pub async fn spawn_swarm(
                     }
                 }
 
-                event = swarm.select_next_some() => {
+                // Stop reading the network until the application catches up
+                event = swarm.select_next_some(), if !notif_tx.is_saturated() => {
                     match event {
                         SwarmEvent::Behaviour(LiberteEvent::Gossipsub(
                             gossipsub::Event::Message {
-                                propagation_source: _,
-                                message_id: _,
+                                propagation_source,
+                                message_id,
                                 message,
                             },
                         )) => {
@@ -177,20 +550,106 @@ pub async fn spawn_swarm(
                                 len = message.data.len(),
                                 "GossipSub message received"
                             );
-                            let _ = notif_tx
-                                .send(SwarmNotification::MessageReceived {
+                            let class = if media_topics.contains(&topic) {
+                                NotificationClass::Realtime
+                            } else {
+                                NotificationClass::Chat
+                            };
+                            let dropped = notif_tx.send(
+                                class,
+                                SwarmNotification::MessageReceived {
+                                    message_id,
+                                    propagation_source,
                                     source: message.source,
                                     topic,
                                     data: message.data,
-                                })
-                                .await;
+                                },
+                            );
+                            // Refused, or evicted to make room for this one
+                            let dropped = match dropped {
+                                Ok(evicted) => evicted,
+                                Err(QueueFull(refused)) => Some(*refused),
+                            };
+                            if let Some(SwarmNotification::MessageReceived {
+                                message_id,
+                                propagation_source,
+                                ..
+                            }) = dropped
+                            {
+                                // The application will never validate it; let
+                                // gossipsub drop it without penalizing the peer
+                                let _ = swarm
+                                    .behaviour_mut()
+                                    .gossipsub
+                                    .report_message_validation_result(
+                                        &message_id,
+                                        &propagation_source,
+                                        gossipsub::MessageAcceptance::Ignore,
+                                    );
+                            }
                         }
 
                         SwarmEvent::Behaviour(LiberteEvent::Kademlia(
-                            kad::Event::OutboundQueryProgressed { result, .. },
-                        )) => {
-                            debug!(result = ?result, "Kademlia query progressed");
-                        }
+                            kad::Event::OutboundQueryProgressed { id, result, step, .. },
+                        )) => match result {
+                            kad::QueryResult::GetRecord(result) => {
+                                if let Ok(kad::GetRecordOk::FoundRecord(peer_record)) = result {
+                                    match presence_from_record(&peer_record.record) {
+                                        Ok(info) => {
+                                            if let Some((_, best)) = pending_lookups.get_mut(&id) {
+                                                keep_newest(best, info);
+                                            }
+                                        }
+                                        Err(e) => {
+                                            debug!(error = %e, "Ignoring invalid presence record");
+                                        }
+                                    }
+                                }
+                                // Other peers may hold a newer record than the
+                                // first one found, so wait for the query to end
+                                if step.last {
+                                    if let Some((reply, best)) = pending_lookups.remove(&id) {
+                                        let _ = reply.send(best);
+                                    }
+                                }
+                            }
+                            kad::QueryResult::PutRecord(Err(e)) => {
+                                debug!(error = %e, "Presence publication incomplete");
+                            }
+                            other => {
+                                debug!(result = ?other, "Kademlia query progressed");
+                            }
+                        },
+
+                        SwarmEvent::Behaviour(LiberteEvent::Kademlia(
+                            kad::Event::InboundRequest { request },
+                        )) => match request {
+                            kad::InboundRequest::PutRecord {
+                                source,
+                                record: Some(record),
+                                ..
+                            } => match presence_from_record(&record) {
+                                Ok(info) => {
+                                    let store = swarm.behaviour_mut().kademlia.store_mut();
+                                    let newer =
+                                        supersedes_stored(store.get(&record.key).as_deref(), info);
+                                    if !newer {
+                                        debug!(peer = %source, "Kept newer stored presence record");
+                                    } else if let Err(e) = store.put(record) {
+                                        warn!(error = %e, "Failed to store presence record");
+                                    }
+                                }
+                                Err(e) => {
+                                    debug!(peer = %source, error = %e, "Rejected inbound DHT record");
+                                }
+                            },
+                            kad::InboundRequest::AddProvider {
+                                record: Some(record),
+                            } => {
+                                let _ = swarm.behaviour_mut().kademlia.store_mut().add_provider(record);
+                            }
+                            _ => {}
+                        },
 
                         SwarmEvent::Behaviour(LiberteEvent::Identify(
                             identify::Event::Received { peer_id, info, .. },
@@ -200,12 +659,33 @@ pub async fn spawn_swarm(
                                 protocol = ?info.protocol_version,
                                 "Identify: received info from peer"
                             );
-                            for addr in &info.listen_addrs {
-                                swarm
-                                    .behaviour_mut()
-                                    .kademlia
-                                    .add_address(&peer_id, addr.clone());
+                            // Loopback and LAN addresses would have us dial our
+                            // own network, and be stored in the peer book
+                            let listen_addrs: Vec<Multiaddr> = info
+                                .listen_addrs
+                                .into_iter()
+                                .filter(is_global_addr)
+                                .collect();
+                            for addr in transports.sort_dial_addrs(listen_addrs.iter().cloned()) {
+                                swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
                             }
+                            // Registrations need an external address; take the
+                            // one the rendezvous point sees us on
+                            if let Some(ref mut point) = rendezvous_point {
+                                if point.peer_id == peer_id
+                                    && swarm.external_addresses().next().is_none()
+                                {
+                                    swarm.add_external_address(info.observed_addr.clone());
+                                    point.refresh(&mut swarm.behaviour_mut().rendezvous);
+                                }
+                            }
+                            notify(
+                                &mut notif_tx,
+                                SwarmNotification::PeerIdentified {
+                                    peer_id,
+                                    listen_addrs,
+                                },
+                            );
                         }
 
                         SwarmEvent::Behaviour(LiberteEvent::RelayClient(
@@ -218,17 +698,60 @@ pub async fn spawn_swarm(
                                 relay = %relay_peer_id,
                                 "Relay reservation accepted"
                             );
+                            // Circuit address is now dialable, advertise it
+                            if let Some(ref identity) = presence_identity {
+                                publish_presence(&mut swarm, identity, &keypair);
+                            }
                             let relay_addr = swarm
                                 .external_addresses()
                                 .next()
                                 .cloned()
                                 .unwrap_or_else(Multiaddr::empty);
-                            let _ = notif_tx
-                                .send(SwarmNotification::RelayReservation {
+                            notify(
+                                &mut notif_tx,
+                                SwarmNotification::RelayReservation {
                                     relay_peer: relay_peer_id,
                                     relay_addr,
-                                })
-                                .await;
+                                },
+                            );
+                        }
+
+                        SwarmEvent::Behaviour(LiberteEvent::Rendezvous(
+                            rendezvous::client::Event::Discovered {
+                                registrations,
+                                cookie,
+                                ..
+                            },
+                        )) => {
+                            if let Some(ref mut point) = rendezvous_point {
+                                point.on_discovered(cookie);
+                            }
+                            for registration in registrations {
+                                let peer_id = registration.record.peer_id();
+                                if peer_id == local_peer_id
+                                    || banned.contains(&peer_id)
+                                    || swarm.is_connected(&peer_id)
+                                {
+                                    continue;
+                                }
+                                let addrs = transports
+                                    .sort_dial_addrs(registration.record.addresses().iter().cloned());
+                                debug!(peer = %peer_id, "Discovered channel peer via rendezvous");
+                                for addr in &addrs {
+                                    swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
+                                }
+                                dial_peer(&mut swarm, peer_id, addrs);
+                            }
+                        }
+
+                        SwarmEvent::Behaviour(LiberteEvent::Rendezvous(
+                            rendezvous::client::Event::RegisterFailed { namespace, error, .. },
+                        )) => {
+                            warn!(namespace = %namespace, error = ?error, "Rendezvous registration failed");
+                        }
+
+                        SwarmEvent::Behaviour(LiberteEvent::Rendezvous(event)) => {
+                            debug!(event = ?event, "Rendezvous event");
                         }
 
                         SwarmEvent::Behaviour(LiberteEvent::Dcutr(event)) => {
@@ -241,6 +764,12 @@ pub async fn spawn_swarm(
                             let addr = endpoint.get_remote_address().clone();
                             let is_relayed = addr.iter().any(|p| matches!(p, Protocol::P2pCircuit));
                             peer_tracker.on_connected(peer_id, addr.clone(), is_relayed);
+                            if let Some(ref mut point) = rendezvous_point {
+                                if point.peer_id == peer_id && !point.is_connected() {
+                                    info!(peer = %peer_id, "Connected to rendezvous point");
+                                    point.on_connected(&mut swarm.behaviour_mut().rendezvous);
+                                }
+                            }
 
                             info!(
                                 peer = %peer_id,
@@ -248,12 +777,13 @@ pub async fn spawn_swarm(
                                 relayed = is_relayed,
                                 "Peer connected"
                             );
-                            let _ = notif_tx
-                                .send(SwarmNotification::PeerConnected {
+                            notify(
+                                &mut notif_tx,
+                                SwarmNotification::PeerConnected {
                                     peer_id,
                                     address: addr,
-                                })
-                                .await;
+                                },
+                            );
                         }
 
                         SwarmEvent::ConnectionClosed {
@@ -263,13 +793,26 @@ pub async fn spawn_swarm(
                         } => {
                             if num_established == 0 {
                                 peer_tracker.on_disconnected(&peer_id);
+                                if let Some(ref mut point) = rendezvous_point {
+                                    if point.peer_id == peer_id {
+                                        point.on_disconnected();
+                                    }
+                                }
+                                bandwidth.remove(&peer_id);
                                 info!(peer = %peer_id, "Peer disconnected");
-                                let _ = notif_tx
-                                    .send(SwarmNotification::PeerDisconnected { peer_id })
-                                    .await;
+                                notify(&mut notif_tx, SwarmNotification::PeerDisconnected { peer_id });
                             }
                         }
 
+                        SwarmEvent::Behaviour(LiberteEvent::Ping(ping::Event {
+                            peer,
+                            result,
+                            ..
+                        })) => match result {
+                            Ok(rtt) => peer_tracker.record_rtt(&peer, rtt),
+                            Err(e) => debug!(peer = %peer, error = %e, "Ping failed"),
+                        },
+
                         SwarmEvent::NewListenAddr { address, .. } => {
                             info!(addr = %address, "Listening on new address");
                         }
@@ -280,6 +823,9 @@ pub async fn spawn_swarm(
                                 error = %error,
                                 "Outgoing connection error"
                             );
+                            if let Some(peer_id) = peer_id {
+                                notify(&mut notif_tx, SwarmNotification::DialFailed { peer_id });
+                            }
                         }
 
                         SwarmEvent::IncomingConnectionError { error, .. } => {
@@ -298,6 +844,98 @@ pub async fn spawn_swarm(
     Ok((cmd_tx, notif_rx, local_peer_id))
 }
 
+/// Sign and put our presence record, covering every address peers could dial.
+fn publish_presence(
+    swarm: &mut Swarm<LiberteBehaviour>,
+    identity: &Identity,
+    keypair: &libp2p::identity::Keypair,
+) {
+    let mut addresses: Vec<Multiaddr> = swarm
+        .listeners()
+        .chain(swarm.external_addresses())
+        .filter(|addr| is_global_addr(addr))
+        .cloned()
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+
+    if addresses.is_empty() {
+        debug!("No dialable addresses yet, skipping presence publication");
+        return;
+    }
+
+    let ttl = chrono::Duration::seconds(PRESENCE_TTL_SECS);
+    let signed = match sign_presence(identity, keypair, &addresses, ttl) {
+        Ok(s) => s,
+        Err(e) => {
+            error!(error = %e, "Failed to sign presence record");
+            return;
+        }
+    };
+    let value = match signed.to_bytes() {
+        Ok(v) => v,
+        Err(e) => {
+            error!(error = %e, "Failed to serialize presence record");
+            return;
+        }
+    };
+
+    let mut record = kad::Record::new(presence_record_key(&identity.user_id()), value);
+    record.expires =
+        Some(std::time::Instant::now() + Duration::from_secs(PRESENCE_TTL_SECS as u64));
+
+    match swarm
+        .behaviour_mut()
+        .kademlia
+        .put_record(record, kad::Quorum::One)
+    {
+        Ok(_) => debug!(count = addresses.len(), "Publishing presence record"),
+        Err(e) => warn!(error = %e, "Failed to publish presence record"),
+    }
+}
+
+/// Queue a control notification. The loop stops polling the swarm while a
+/// queue is saturated, so a refusal means the queue was configured to drop.
+fn notify(notif_tx: &mut NotificationSender, notification: SwarmNotification) {
+    let dropped = match notif_tx.send(NotificationClass::Control, notification) {
+        Ok(evicted) => evicted,
+        Err(QueueFull(refused)) => Some(*refused),
+    };
+    if let Some(notification) = dropped {
+        warn!(?notification, "Control notification dropped");
+    }
+}
+
+/// Dial `peer_id` over `addrs`, which must already be in preference order.
+/// With a dial concurrency of one, later addresses are only tried if earlier ones fail.
+fn dial_peer(swarm: &mut Swarm<LiberteBehaviour>, peer_id: PeerId, addrs: Vec<Multiaddr>) {
+    if addrs.is_empty() {
+        debug!(peer = %peer_id, "No dialable addresses for peer");
+        return;
+    }
+    let opts = DialOpts::peer_id(peer_id).addresses(addrs).build();
+    if let Err(e) = swarm.dial(opts) {
+        warn!(peer = %peer_id, error = %e, "Dial failed");
+    }
+}
+
+/// Drop connections to peers whose gossipsub score fell below the configured threshold.
+fn disconnect_low_scoring_peers(
+    swarm: &mut Swarm<LiberteBehaviour>,
+    peer_tracker: &PeerTracker,
+    scoring: &PeerScoreConfig,
+) {
+    for peer_id in peer_tracker.connected_peers() {
+        let Some(score) = swarm.behaviour().gossipsub.peer_score(&peer_id) else {
+            continue;
+        };
+        if score < scoring.disconnect_threshold {
+            warn!(peer = %peer_id, score, "Disconnecting peer with low gossipsub score");
+            let _ = swarm.disconnect_peer_id(peer_id);
+        }
+    }
+}
+
 fn extract_peer_id(addr: &Multiaddr) -> Option<PeerId> {
     addr.iter().find_map(|p| {
         if let Protocol::P2p(peer_id) = p {
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use liberte_shared::constants::{TOPIC_EPOCH_GRACE_SECS, TOPIC_EPOCH_SECS};
+use liberte_shared::crypto::{derive_channel_topic, derive_media_topic, SymmetricKey};
+use liberte_shared::types::ChannelId;
+
+/// What a channel topic carries; media topics are queued as realtime and
+/// may be dropped under load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TopicKind {
+    Chat,
+    Media,
+}
+
+impl TopicKind {
+    pub fn derive(self, channel_key: &SymmetricKey, epoch: u64) -> String {
+        match self {
+            TopicKind::Chat => derive_channel_topic(channel_key, epoch),
+            TopicKind::Media => derive_media_topic(channel_key, epoch),
+        }
+    }
+}
+
+pub fn topic_epoch(at: DateTime<Utc>) -> u64 {
+    at.timestamp().max(0) as u64 / TOPIC_EPOCH_SECS
+}
+
+/// Topic to publish on right now.
+pub fn current_topic(channel_key: &SymmetricKey) -> String {
+    derive_channel_topic(channel_key, topic_epoch(Utc::now()))
+}
+
+/// Media topic to publish voice frames on right now.
+pub fn current_media_topic(channel_key: &SymmetricKey) -> String {
+    derive_media_topic(channel_key, topic_epoch(Utc::now()))
+}
+
+/// Epochs to listen on at `at`: the current one, plus its neighbour when
+/// within the grace window of a boundary.
+pub fn active_epochs(at: DateTime<Utc>) -> Vec<u64> {
+    let epoch = topic_epoch(at);
+    let offset = at.timestamp().max(0) as u64 % TOPIC_EPOCH_SECS;
+
+    let mut epochs = vec![epoch];
+    if offset < TOPIC_EPOCH_GRACE_SECS && epoch > 0 {
+        epochs.push(epoch - 1);
+    }
+    if offset >= TOPIC_EPOCH_SECS - TOPIC_EPOCH_GRACE_SECS {
+        epochs.push(epoch + 1);
+    }
+    epochs
+}
+
+/// Chat topics a channel is reachable on at `at`.
+pub fn active_topics(channel_key: &SymmetricKey, at: DateTime<Utc>) -> Vec<String> {
+    active_topics_of(TopicKind::Chat, channel_key, at)
+}
+
+pub fn active_topics_of(
+    kind: TopicKind,
+    channel_key: &SymmetricKey,
+    at: DateTime<Utc>,
+) -> Vec<String> {
+    active_epochs(at)
+        .into_iter()
+        .map(|epoch| kind.derive(channel_key, epoch))
+        .collect()
+}
+
+/// Subscription changes produced by [`TopicTable::refresh`].
+#[derive(Debug, Default)]
+pub struct TopicDiff {
+    pub subscribe: Vec<(String, TopicKind)>,
+    pub unsubscribe: Vec<String>,
+}
+
+/// Local mapping from opaque topic names back to channels. Topic names
+/// carry no channel id, so incoming messages are routed through this.
+#[derive(Debug, Default)]
+pub struct TopicTable {
+    topics: HashMap<String, (ChannelId, TopicKind)>,
+}
+
+impl TopicTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild the table for `channels` at `at`, returning which topics
+    /// must be newly subscribed and which are no longer needed.
+    pub fn refresh(
+        &mut self,
+        channels: &[(ChannelId, SymmetricKey)],
+        at: DateTime<Utc>,
+    ) -> TopicDiff {
+        let mut next = HashMap::new();
+        for (channel_id, key) in channels {
+            for kind in [TopicKind::Chat, TopicKind::Media] {
+                for topic in active_topics_of(kind, key, at) {
+                    next.insert(topic, (channel_id.clone(), kind));
+                }
+            }
+        }
+
+        let diff = TopicDiff {
+            subscribe: next
+                .iter()
+                .filter(|(t, _)| !self.topics.contains_key(*t))
+                .map(|(t, (_, kind))| (t.clone(), *kind))
+                .collect(),
+            unsubscribe: self
+                .topics
+                .keys()
+                .filter(|t| !next.contains_key(*t))
+                .cloned()
+                .collect(),
+        };
+        self.topics = next;
+        diff
+    }
+
+    pub fn channel_for(&self, topic: &str) -> Option<&ChannelId> {
+        self.topics.get(topic).map(|(channel_id, _)| channel_id)
+    }
+
+    pub fn kind_of(&self, topic: &str) -> Option<TopicKind> {
+        self.topics.get(topic).map(|(_, kind)| *kind)
+    }
+
+    pub fn topics(&self) -> impl Iterator<Item = &String> {
+        self.topics.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use liberte_shared::crypto::generate_symmetric_key;
+
+    fn at(secs: u64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs as i64, 0).unwrap()
+    }
+
+    #[test]
+    fn test_grace_window_adds_neighbour_epoch() {
+        let start = 10 * TOPIC_EPOCH_SECS;
+        assert_eq!(active_epochs(at(start + TOPIC_EPOCH_SECS / 2)), vec![10]);
+        assert_eq!(active_epochs(at(start + 1)), vec![10, 9]);
+        assert_eq!(
+            active_epochs(at(start + TOPIC_EPOCH_SECS - 1)),
+            vec![10, 11]
+        );
+    }
+
+    #[test]
+    fn test_refresh_rotates_topics() {
+        let channel = ChannelId::new();
+        let channels = vec![(channel.clone(), generate_symmetric_key())];
+        let mut table = TopicTable::new();
+
+        let first = table.refresh(&channels, at(10 * TOPIC_EPOCH_SECS + 3600));
+        assert_eq!(first.subscribe.len(), 2);
+        assert!(first.unsubscribe.is_empty());
+        for (topic, kind) in &first.subscribe {
+            assert_eq!(table.channel_for(topic), Some(&channel));
+            assert_eq!(table.kind_of(topic), Some(*kind));
+        }
+        assert_ne!(first.subscribe[0].1, first.subscribe[1].1);
+
+        let second = table.refresh(&channels, at(11 * TOPIC_EPOCH_SECS + 3600));
+        assert_eq!(second.subscribe.len(), 2);
+        let mut old: Vec<String> = first.subscribe.into_iter().map(|(t, _)| t).collect();
+        let mut removed = second.unsubscribe.clone();
+        old.sort();
+        removed.sort();
+        assert_eq!(removed, old);
+        assert!(table.channel_for(&old[0]).is_none());
+    }
+}
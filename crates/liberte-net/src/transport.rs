@@ -1,27 +1,181 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::{Boxed, Transport};
 use libp2p::identity::Keypair;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
 use tracing::info;
 
+use liberte_shared::constants::{DEFAULT_QUIC_PORT, DEFAULT_TCP_PORT, DEFAULT_WS_PORT};
+
+use crate::bandwidth::BandwidthTracker;
+use crate::private_network::{load_swarm_key, secure_stream_transport};
+use crate::swarm::SwarmConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransportKind {
+    Quic,
+    Tcp,
+    WebSocket,
+}
+
+impl TransportKind {
+    /// Transport an address is dialed over. Relayed addresses are classified
+    /// by the hop to the relay; `None` for anything we can't dial directly.
+    pub fn of(addr: &Multiaddr) -> Option<Self> {
+        let mut kind = None;
+        for protocol in addr.iter() {
+            match protocol {
+                Protocol::QuicV1 => kind = Some(Self::Quic),
+                Protocol::Tcp(_) => kind = Some(Self::Tcp),
+                Protocol::Ws(_) | Protocol::Wss(_) => kind = Some(Self::WebSocket),
+                Protocol::P2pCircuit => break,
+                _ => {}
+            }
+        }
+        kind
+    }
+}
+
+/// Which transports listen and which may dial, in order of preference.
+#[derive(Debug, Clone)]
+pub struct TransportConfig {
+    pub listen: Vec<TransportKind>,
+    /// Dial-preference policy: a peer's addresses are tried one at a time in
+    /// this order, so QUIC is attempted before falling back to TCP/WebSocket.
+    pub dial: Vec<TransportKind>,
+    pub quic_port: u16,
+    pub tcp_port: u16,
+    pub ws_port: u16,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        let all = vec![
+            TransportKind::Quic,
+            TransportKind::Tcp,
+            TransportKind::WebSocket,
+        ];
+        Self {
+            listen: all.clone(),
+            dial: all,
+            quic_port: DEFAULT_QUIC_PORT,
+            tcp_port: DEFAULT_TCP_PORT,
+            ws_port: DEFAULT_WS_PORT,
+        }
+    }
+}
+
+impl TransportConfig {
+    /// IPv4 and IPv6 wildcard listen addresses for every listening transport.
+    pub fn listen_addrs(&self) -> Vec<Multiaddr> {
+        let mut addrs = Vec::new();
+        for kind in &self.listen {
+            let suffix = match kind {
+                TransportKind::Quic => format!("udp/{}/quic-v1", self.quic_port),
+                TransportKind::Tcp => format!("tcp/{}", self.tcp_port),
+                TransportKind::WebSocket => format!("tcp/{}/ws", self.ws_port),
+            };
+            for ip in ["/ip4/0.0.0.0", "/ip6/::"] {
+                addrs.push(format!("{ip}/{suffix}").parse().expect("valid multiaddr"));
+            }
+        }
+        addrs
+    }
+
+    /// Stop listening and dialing over `kind`.
+    pub fn disable(&mut self, kind: TransportKind) {
+        self.listen.retain(|k| *k != kind);
+        self.dial.retain(|k| *k != kind);
+    }
+
+    /// `/dnsaddr` entries can't be classified until resolved, so they pass.
+    pub fn allows_dial(&self, addr: &Multiaddr) -> bool {
+        match TransportKind::of(addr) {
+            Some(kind) => self.dial.contains(&kind),
+            None => matches!(addr.iter().next(), Some(Protocol::Dnsaddr(_))),
+        }
+    }
+
+    /// Drop addresses over disabled transports and order the rest by preference.
+    pub fn sort_dial_addrs(&self, addrs: impl IntoIterator<Item = Multiaddr>) -> Vec<Multiaddr> {
+        let rank = |addr: &Multiaddr| {
+            TransportKind::of(addr)
+                .and_then(|kind| self.dial.iter().position(|k| *k == kind))
+                .unwrap_or(self.dial.len())
+        };
+        let mut addrs: Vec<Multiaddr> = addrs.into_iter().filter(|a| self.allows_dial(a)).collect();
+        addrs.sort_by_key(rank);
+        addrs
+    }
+}
+
+/// Whether `addr` can be reached from the internet: no loopback, private,
+/// link-local, unspecified or otherwise reserved IP in any of its parts
+/// (a circuit address carries the relay's). Addresses by DNS name pass.
+pub fn is_global_addr(addr: &Multiaddr) -> bool {
+    addr.iter().all(|p| match p {
+        Protocol::Ip4(ip) => is_global_ipv4(ip),
+        Protocol::Ip6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_global_ipv4(ip),
+            None => is_global_ipv6(ip),
+        },
+        _ => true,
+    })
+}
+
+fn is_global_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        // Shared address space (carrier-grade NAT)
+        || (a == 100 && (64..128).contains(&b))
+        // Benchmarking and reserved for future use
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_global_ipv6(ip: Ipv6Addr) -> bool {
+    let [first, second, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        // Unique local, link-local and documentation ranges
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && second == 0x0db8))
+}
+
 pub fn build_swarm(
     keypair: Keypair,
-) -> anyhow::Result<libp2p::Swarm<super::behaviour::LiberteBehaviour>> {
+    config: &SwarmConfig,
+) -> anyhow::Result<(
+    libp2p::Swarm<super::behaviour::LiberteBehaviour>,
+    BandwidthTracker,
+)> {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
+    use std::num::NonZeroU8;
     use std::time::Duration;
 
     use libp2p::gossipsub::{self, MessageAuthenticity, MessageId, ValidationMode};
     use libp2p::kad::{self, store::MemoryStore};
-    use libp2p::{dcutr, identify, noise, SwarmBuilder};
+    use libp2p::{dcutr, identify, ping, relay, rendezvous, SwarmBuilder};
 
     use liberte_shared::constants::{GOSSIPSUB_HEARTBEAT_SECS, MAX_MESSAGE_SIZE, PROTOCOL_VERSION};
 
+    let bandwidth = BandwidthTracker::new();
+    let (relay_transport, relay_client) = relay::client::new(keypair.public().to_peer_id());
+
     let swarm = SwarmBuilder::with_existing_identity(keypair)
         .with_tokio()
-        .with_quic()
-        .with_relay_client(noise::Config::new, libp2p::yamux::Config::default)?
+        .with_other_transport(|key| build_transport(key, config, relay_transport, &bandwidth))?
         .with_behaviour(
-            |key,
-             relay_client|
-             -> std::result::Result<
+            |key| -> std::result::Result<
                 super::behaviour::LiberteBehaviour,
                 Box<dyn std::error::Error + Send + Sync>,
             > {
@@ -39,6 +193,8 @@ pub fn build_swarm(
                 let gossipsub_config = gossipsub::ConfigBuilder::default()
                     .heartbeat_interval(Duration::from_secs(GOSSIPSUB_HEARTBEAT_SECS))
                     .validation_mode(ValidationMode::Strict)
+                    // Messages are only forwarded once the application reports them valid
+                    .validate_messages()
                     .max_transmit_size(MAX_MESSAGE_SIZE)
                     .message_id_fn(message_id_fn)
                     .build()
@@ -46,7 +202,7 @@ pub fn build_swarm(
                         format!("GossipSub config: {e}").into()
                     })?;
 
-                let gossipsub = gossipsub::Behaviour::new(
+                let mut gossipsub = gossipsub::Behaviour::new(
                     MessageAuthenticity::Signed(key.clone()),
                     gossipsub_config,
                 )
@@ -54,8 +210,20 @@ pub fn build_swarm(
                     format!("GossipSub init: {e}").into()
                 })?;
 
+                if let Some(ref scoring) = config.peer_score {
+                    gossipsub
+                        .with_peer_score(scoring.params.clone(), scoring.thresholds.clone())
+                        .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
+                            format!("GossipSub peer score: {e}").into()
+                        })?;
+                }
+
+                // Inbound records are validated by the swarm loop before being stored
+                let mut kad_config = kad::Config::new(kad::PROTOCOL_NAME);
+                kad_config.set_record_filtering(kad::StoreInserts::FilterBoth);
+
                 let store = MemoryStore::new(local_peer_id);
-                let mut kademlia = kad::Behaviour::new(local_peer_id, store);
+                let mut kademlia = kad::Behaviour::with_config(local_peer_id, store, kad_config);
                 kademlia.set_mode(Some(kad::Mode::Server));
 
                 let identify_config =
@@ -65,23 +233,303 @@ pub fn build_swarm(
                 let identify = identify::Behaviour::new(identify_config);
 
                 let dcutr = dcutr::Behaviour::new(local_peer_id);
+                let ping = ping::Behaviour::new(ping::Config::new());
+                let rendezvous = rendezvous::client::Behaviour::new(key.clone());
 
                 Ok(super::behaviour::LiberteBehaviour {
+                    blocked: Default::default(),
                     gossipsub,
                     kademlia,
                     identify,
                     relay_client,
                     dcutr,
+                    ping,
+                    rendezvous,
                 })
             },
         )?
-        .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(60)))
+        .with_swarm_config(|cfg| {
+            cfg.with_idle_connection_timeout(Duration::from_secs(60))
+                // One address at a time, so the dial-preference order is honoured
+                .with_dial_concurrency_factor(NonZeroU8::MIN)
+        })
         .build();
 
+    let transports = config.enabled_transports();
     info!(
         peer_id = %swarm.local_peer_id(),
-        "Built Liberte swarm with QUIC + Relay transport"
+        private = config.swarm_key_path.is_some(),
+        listen = ?transports.listen,
+        dial = ?transports.dial,
+        "Built Liberte swarm with DNS + Relay transport"
     );
 
-    Ok(swarm)
+    Ok((swarm, bandwidth))
+}
+
+/// Relay circuits, WebSocket, then QUIC and TCP behind DNS resolution, all
+/// resolving through the configured (encrypted by default) upstreams, with
+/// every connection instrumented for per-peer byte counts. With a swarm key,
+/// QUIC is left out since pnet can only protect stream transports.
+///
+/// A DNS transport accepts every dial and only fails once resolved, so it
+/// must come last: anything after it in the chain would never be tried.
+fn build_transport(
+    key: &Keypair,
+    config: &SwarmConfig,
+    relay_transport: libp2p::relay::client::Transport,
+    bandwidth: &BandwidthTracker,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>, Box<dyn std::error::Error + Send + Sync>> {
+    use libp2p::{dns, quic, tcp, websocket};
+
+    // Resolves /dns, /dns4, /dns6 and /dnsaddr multiaddrs through the configured upstreams
+    let (dns_config, dns_opts) = crate::dns::resolver_config(&config.dns)?;
+
+    let swarm_key = config
+        .swarm_key_path
+        .as_deref()
+        .map(load_swarm_key)
+        .transpose()?;
+    if let Some(ref psk) = swarm_key {
+        info!(fingerprint = %psk.fingerprint(), "Private network mode enabled");
+    }
+
+    let tcp = secure_stream_transport(
+        tcp::tokio::Transport::new(tcp::Config::default().nodelay(true)),
+        key,
+        swarm_key,
+    )?;
+
+    // WebSocket resolves its own host names so /wss keeps the name for TLS
+    let websocket = secure_stream_transport(
+        websocket::WsConfig::new(dns::tokio::Transport::custom(
+            tcp::tokio::Transport::new(tcp::Config::default().nodelay(true)),
+            dns_config.clone(),
+            dns_opts.clone(),
+        )),
+        key,
+        swarm_key,
+    )?;
+
+    let relay = secure_stream_transport(relay_transport, key, swarm_key)?;
+
+    let direct = match swarm_key {
+        Some(_) => tcp,
+        None => quic::tokio::Transport::new(quic::Config::new(key))
+            .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+            .or_transport(tcp)
+            .map(|either, _| either.into_inner())
+            .boxed(),
+    };
+    let direct = dns::tokio::Transport::custom(direct, dns_config, dns_opts);
+
+    let bandwidth = bandwidth.clone();
+    Ok(relay
+        .or_transport(websocket)
+        .map(|either, _| either.into_inner())
+        .or_transport(direct)
+        .map(|either, _| either.into_inner())
+        .map(move |(peer_id, muxer), _| (peer_id, bandwidth.instrument(peer_id, muxer)))
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::pin::Pin;
+
+    use libp2p::core::transport::{DialOpts, ListenerId, PortUse, TransportEvent};
+    use libp2p::core::Endpoint;
+
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_transport_kind_of() {
+        assert_eq!(
+            TransportKind::of(&addr("/ip4/1.2.3.4/udp/4001/quic-v1")),
+            Some(TransportKind::Quic)
+        );
+        assert_eq!(
+            TransportKind::of(&addr("/dns4/relay.example.org/tcp/443/wss")),
+            Some(TransportKind::WebSocket)
+        );
+        assert_eq!(
+            TransportKind::of(&addr(
+                "/ip4/1.2.3.4/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN/p2p-circuit"
+            )),
+            Some(TransportKind::Tcp)
+        );
+        assert_eq!(TransportKind::of(&addr("/ip4/1.2.3.4/udp/9")), None);
+    }
+
+    #[test]
+    fn test_dial_addrs_sorted_and_filtered() {
+        let config = TransportConfig {
+            dial: vec![TransportKind::Quic, TransportKind::Tcp],
+            ..Default::default()
+        };
+        let sorted = config.sort_dial_addrs([
+            addr("/ip4/1.2.3.4/tcp/4002/ws"),
+            addr("/ip4/1.2.3.4/tcp/4001"),
+            addr("/ip4/1.2.3.4/udp/4001/quic-v1"),
+            addr("/dnsaddr/bootstrap.example.org"),
+        ]);
+        assert_eq!(
+            sorted,
+            vec![
+                addr("/ip4/1.2.3.4/udp/4001/quic-v1"),
+                addr("/ip4/1.2.3.4/tcp/4001"),
+                addr("/dnsaddr/bootstrap.example.org"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_private_network_disables_quic() {
+        let mut config = SwarmConfig {
+            transports: TransportConfig {
+                listen: vec![TransportKind::Quic, TransportKind::Tcp],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            config.enabled_transports().listen,
+            vec![TransportKind::Quic, TransportKind::Tcp]
+        );
+
+        config.swarm_key_path = Some("swarm.key".into());
+        let transports = config.enabled_transports();
+        assert_eq!(transports.listen, vec![TransportKind::Tcp]);
+        assert_eq!(
+            transports.dial,
+            vec![TransportKind::Tcp, TransportKind::WebSocket]
+        );
+    }
+
+    #[test]
+    fn test_is_global_addr() {
+        for global in [
+            "/ip4/1.2.3.4/udp/4001/quic-v1",
+            "/ip6/2a01:4f8::1/tcp/4001",
+            "/dns4/relay.example.org/tcp/443/wss",
+            "/ip4/1.2.3.4/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN/p2p-circuit",
+        ] {
+            assert!(is_global_addr(&addr(global)), "{global}");
+        }
+        for local in [
+            "/ip4/127.0.0.1/tcp/4001",
+            "/ip4/0.0.0.0/udp/4001/quic-v1",
+            "/ip4/10.1.2.3/tcp/4001",
+            "/ip4/172.16.0.1/tcp/4001",
+            "/ip4/192.168.1.20/udp/4001/quic-v1",
+            "/ip4/169.254.0.1/tcp/4001",
+            "/ip4/100.64.0.1/tcp/4001",
+            "/ip6/::1/tcp/4001",
+            "/ip6/::/tcp/4001",
+            "/ip6/fd00::1/tcp/4001",
+            "/ip6/fe80::1/tcp/4001",
+            "/ip6/::ffff:192.168.1.1/tcp/4001",
+            "/ip4/10.0.0.1/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN/p2p-circuit",
+        ] {
+            assert!(!is_global_addr(&addr(local)), "{local}");
+        }
+    }
+
+    fn transport(key: &Keypair) -> Boxed<(PeerId, StreamMuxerBox)> {
+        let (relay_transport, _) = libp2p::relay::client::new(key.public().to_peer_id());
+        build_transport(
+            key,
+            &SwarmConfig::default(),
+            relay_transport,
+            &BandwidthTracker::new(),
+        )
+        .unwrap()
+    }
+
+    fn dialer() -> DialOpts {
+        DialOpts {
+            role: Endpoint::Dialer,
+            port_use: PortUse::New,
+        }
+    }
+
+    async fn next_event(
+        transport: &mut Boxed<(PeerId, StreamMuxerBox)>,
+    ) -> TransportEvent<
+        <Boxed<(PeerId, StreamMuxerBox)> as Transport>::ListenerUpgrade,
+        std::io::Error,
+    > {
+        poll_fn(|cx| Pin::new(&mut *transport).poll(cx)).await
+    }
+
+    #[tokio::test]
+    async fn test_websocket_dial() {
+        let listener_key = Keypair::generate_ed25519();
+        let mut listener = transport(&listener_key);
+        listener
+            .listen_on(ListenerId::next(), addr("/ip4/127.0.0.1/tcp/0/ws"))
+            .unwrap();
+        let port = loop {
+            if let TransportEvent::NewAddress { listen_addr, .. } = next_event(&mut listener).await
+            {
+                let port = listen_addr.iter().find_map(|p| match p {
+                    Protocol::Tcp(port) => Some(port),
+                    _ => None,
+                });
+                break port.unwrap();
+            }
+        };
+        tokio::spawn(async move {
+            loop {
+                if let TransportEvent::Incoming { upgrade, .. } = next_event(&mut listener).await {
+                    tokio::spawn(upgrade);
+                }
+            }
+        });
+
+        // The host name goes through the transport's own resolver
+        let target = addr(&format!("/dns4/localhost/tcp/{port}/ws"));
+        let mut dialing = transport(&Keypair::generate_ed25519());
+        let dial = dialing.dial(target, dialer()).unwrap();
+
+        let (peer_id, _muxer) = tokio::time::timeout(std::time::Duration::from_secs(10), dial)
+            .await
+            .expect("WebSocket dial timed out")
+            .unwrap();
+        assert_eq!(peer_id, listener_key.public().to_peer_id());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_dial_reaches_relay_transport() {
+        let key = Keypair::generate_ed25519();
+        let (relay_transport, relay_behaviour) =
+            libp2p::relay::client::new(key.public().to_peer_id());
+        let mut transport = build_transport(
+            &key,
+            &SwarmConfig::default(),
+            relay_transport,
+            &BandwidthTracker::new(),
+        )
+        .unwrap();
+        // Without its behaviour the relay transport fails the dial itself,
+        // which is how we tell it was the one to get the address
+        drop(relay_behaviour);
+
+        let relay = PeerId::random();
+        let target = PeerId::random();
+        let circuit = addr(&format!(
+            "/ip4/127.0.0.1/tcp/4001/p2p/{relay}/p2p-circuit/p2p/{target}"
+        ));
+        let error = transport
+            .dial(circuit, dialer())
+            .unwrap()
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("relay behaviour"), "{error}");
+    }
 }
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+pub use libp2p::gossipsub::{MessageAcceptance, MessageId};
+
+/// Above this many tracked authors, expired windows are swept.
+const MAX_TRACKED_PEERS: usize = 4096;
+
+/// Fixed-window message counter per author, used by the application when
+/// deciding whether to accept a gossipsub message.
+pub struct MessageRateLimiter {
+    limit: u32,
+    window: Duration,
+    counters: HashMap<PeerId, (Instant, u32)>,
+}
+
+impl MessageRateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            counters: HashMap::new(),
+        }
+    }
+
+    /// Count one message from `peer`; returns false once over the limit.
+    pub fn check(&mut self, peer: &PeerId) -> bool {
+        self.check_at(peer, Instant::now())
+    }
+
+    fn check_at(&mut self, peer: &PeerId, now: Instant) -> bool {
+        let window = self.window;
+        let entry = self.counters.entry(*peer).or_insert((now, 0));
+        if now.duration_since(entry.0) >= window {
+            *entry = (now, 0);
+        }
+        entry.1 += 1;
+        let allowed = entry.1 <= self.limit;
+
+        if self.counters.len() > MAX_TRACKED_PEERS {
+            self.counters
+                .retain(|_, (start, _)| now.duration_since(*start) < window);
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_resets_after_window() {
+        let mut limiter = MessageRateLimiter::new(2, Duration::from_secs(1));
+        let peer = PeerId::random();
+        let start = Instant::now();
+
+        assert!(limiter.check_at(&peer, start));
+        assert!(limiter.check_at(&peer, start));
+        assert!(!limiter.check_at(&peer, start));
+        assert!(limiter.check_at(&PeerId::random(), start));
+        assert!(limiter.check_at(&peer, start + Duration::from_secs(1)));
+    }
+}
@@ -1,56 +1,136 @@
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
 
 use axum::{
-    extract::{DefaultBodyLimit, Multipart, Path, State},
-    http::{HeaderMap, Method},
-    middleware,
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Extension, Multipart, Path, Query, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{delete, get, post},
     Json, Router,
 };
+use chrono::{DateTime, Utc};
+use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing::info;
 use uuid::Uuid;
 
-use crate::blob_store::BlobStore;
-use crate::config::ServerConfig;
+use crate::audit::{AuditEntry, AuditLog};
+use crate::auth::{authenticate, verify_admin_token, Caller};
+use crate::backup_store::BackupStore;
+use crate::blob_store::{BlobId, BlobStore};
+use crate::config::{BlobLimits, ServerConfig, MAX_REQUEST_BODY_SIZE};
 use crate::error::ServerError;
+use crate::metrics::{BlobOp, BlobOpLabels, Metrics, OPENMETRICS_CONTENT_TYPE};
 use crate::premium::PremiumVerifier;
-use crate::rate_limit::{rate_limit_middleware, RateLimiter};
+use crate::range::{parse_range, RangeRequest};
+use crate::rate_limit::{client_ip_middleware, rate_limit_middleware, ClientIp, RateLimiter};
+use crate::relay::RelayInfo;
+use crate::relay_policy::{AdmissionSettings, RelayPolicy};
+use crate::sfu::SfuManager;
+use crate::sfu_socket::sfu_connect;
+use crate::upload_session::{UploadSession, UploadSessions};
 
 use liberte_shared::premium::PremiumToken;
+use liberte_shared::presence::SignedPresence;
+use liberte_shared::request_auth::SeenSignatures;
 
 #[derive(Clone)]
 pub struct AppState {
     pub blob_store: Arc<BlobStore>,
+    pub uploads: Arc<UploadSessions>,
+    pub backup_store: Arc<BackupStore>,
     pub premium_verifier: Arc<PremiumVerifier>,
     pub rate_limiter: RateLimiter,
     pub config: Arc<ServerConfig>,
+    pub relay: RelayInfo,
+    pub relay_policy: RelayPolicy,
+    pub sfu: SfuManager,
+    pub metrics: Arc<Metrics>,
+    pub audit: AuditLog,
+    /// Request signatures already accepted, see [`authenticate`].
+    pub seen_signatures: Arc<Mutex<SeenSignatures>>,
 }
 
 pub fn build_router(state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
+        .allow_methods([
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
         .allow_headers(Any);
 
-    Router::new()
+    // Blob writes and backups are signed by the user's identity key (or
+    // use the admin token); blob reads stay open since blobs are encrypted
+    // client-side. Rate limits are route layers so that signed requests
+    // can be charged to their signer once it is known (see `authenticate`)
+    let signed = Router::new()
+        .route("/blob/upload", post(blob_upload))
+        .route("/blob/content", post(blob_upload_content))
+        .route("/blob/content/:id", post(blob_reference))
+        .route("/blob/quota", get(blob_quota))
+        .route("/blob/uploads", post(upload_create))
+        .route(
+            "/blob/uploads/:upload_id",
+            get(upload_status).put(upload_append).delete(upload_abort),
+        )
+        .route("/blob/uploads/:upload_id/finalize", post(upload_finalize))
+        .route("/blob/:id", delete(blob_delete))
+        .route("/backup/sync", post(backup_sync_upload))
+        .route("/backup/:pubkey_hex", get(backup_sync_download))
+        .route("/backup/:pubkey_hex/versions", get(backup_versions))
+        .route("/sfu", get(sfu_connect))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authenticate))
+        .route_layer(middleware::from_fn_with_state(
+            state.rate_limiter.clone(),
+            rate_limit_middleware,
+        ));
+
+    let public = Router::new()
         .route("/health", get(health_check))
         .route("/info", get(server_info))
+        .route("/metrics", get(metrics))
         .route("/premium/verify", post(premium_verify))
-        .route("/blob/upload", post(blob_upload))
-        .route("/blob/{id}", get(blob_download))
-        .route("/blob/{id}", delete(blob_delete))
-        .route("/backup/sync", post(backup_sync_upload))
-        .route("/backup/{pubkey_hex}", get(backup_sync_download))
+        .route("/relay/bind", post(relay_bind))
+        .route("/blob/:id", get(blob_download).head(blob_head))
         .route("/admin/status", get(admin_status))
         .route("/admin/grant-premium", post(admin_grant_premium))
         .route("/admin/revoke-premium", post(admin_revoke_premium))
-        .layer(DefaultBodyLimit::max(50 * 1024 * 1024))
-        .layer(middleware::from_fn_with_state(
+        .route("/admin/premium/grants", get(admin_premium_grants))
+        .route("/admin/premium/revocations", get(admin_premium_revocations))
+        .route("/admin/peers", get(admin_peers))
+        .route("/admin/circuits", get(admin_circuits))
+        .route("/admin/blobs", get(admin_blobs))
+        .route("/admin/blobs/:id", delete(admin_delete_blob))
+        .route("/admin/bans", get(admin_bans))
+        .route("/admin/ban", post(admin_ban))
+        .route("/admin/unban", post(admin_unban))
+        .route("/admin/config", get(admin_config).post(admin_set_config))
+        .route("/admin/audit", get(admin_audit))
+        .route_layer(middleware::from_fn_with_state(
             state.rate_limiter.clone(),
             rate_limit_middleware,
+        ));
+
+    Router::new()
+        .merge(signed)
+        .merge(public)
+        .layer(DefaultBodyLimit::max(MAX_REQUEST_BODY_SIZE))
+        .layer(middleware::from_fn_with_state(
+            state.relay_policy.clone(),
+            ip_ban_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.rate_limiter.clone(),
+            client_ip_middleware,
         ))
         .layer(cors)
         .layer(TraceLayer::new_for_http())
@@ -70,7 +150,16 @@ struct PremiumVerifyResponse {
 
 #[derive(Serialize)]
 struct BlobUploadResponse {
-    id: Uuid,
+    id: BlobId,
+}
+
+#[derive(Serialize)]
+struct BlobQuotaResponse {
+    used_bytes: u64,
+    /// 0 when unlimited.
+    quota_bytes: u64,
+    /// Idle time before a blob is collected; `None` when kept forever.
+    ttl_secs: Option<u64>,
 }
 
 #[derive(Serialize)]
@@ -86,6 +175,10 @@ struct ServerInfoResponse {
     premium_required: bool,
     registration_open: bool,
     max_peers: usize,
+    /// Relay peer id and the multiaddrs to dial it on, so clients can be
+    /// configured from the HTTP URL alone.
+    peer_id: String,
+    multiaddrs: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -97,9 +190,47 @@ struct AdminStatusResponse {
     uptime_secs: u64,
 }
 
+/// Binds the sender's relay peer id to its identity: the presence record
+/// is signed by both keys. The premium token is optional when the identity
+/// was granted premium by an admin.
+#[derive(Deserialize)]
+struct RelayBindRequest {
+    presence: SignedPresence,
+    premium_token: Option<PremiumToken>,
+}
+
+#[derive(Serialize)]
+struct RelayBindResponse {
+    peer_id: String,
+    premium: bool,
+}
+
+/// Grants until `valid_until`, or for good when left out.
 #[derive(Deserialize)]
 struct AdminPremiumRequest {
     user_pubkey_hex: String,
+    valid_until: Option<DateTime<Utc>>,
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AdminRevokePremiumRequest {
+    user_pubkey_hex: String,
+    reason: Option<String>,
+}
+
+/// Refuse every request from an IP banned through the admin API.
+async fn ip_ban_middleware(
+    State(policy): State<RelayPolicy>,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if let Some(ClientIp(ip)) = req.extensions().get() {
+        if policy.is_ip_banned(ip) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    Ok(next.run(req).await)
 }
 
 async fn health_check() -> Json<HealthResponse> {
@@ -109,38 +240,104 @@ async fn health_check() -> Json<HealthResponse> {
     })
 }
 
+/// Requires the admin token: the figures describe the server's users.
+async fn metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<([(header::HeaderName, &'static str); 1], String), ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+    let body = state
+        .metrics
+        .render(&state.blob_store, &state.sfu)
+        .await
+        .map_err(|e| ServerError::Internal(format!("Failed to encode metrics: {e}")))?;
+    Ok(([(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)], body))
+}
+
 async fn premium_verify(
     State(state): State<AppState>,
     Json(token): Json<PremiumToken>,
 ) -> Json<PremiumVerifyResponse> {
-    if !state.config.premium_required {
+    if !state.relay_policy.settings().premium_required {
         return Json(PremiumVerifyResponse { valid: true });
     }
     let valid = state.premium_verifier.verify(&token).await;
     Json(PremiumVerifyResponse { valid })
 }
 
-async fn blob_upload(
+async fn relay_bind(
     State(state): State<AppState>,
-    mut multipart: Multipart,
-) -> Result<Json<BlobUploadResponse>, ServerError> {
+    Json(req): Json<RelayBindRequest>,
+) -> Result<Json<RelayBindResponse>, ServerError> {
+    let presence = liberte_net::verify_presence(&req.presence)
+        .map_err(|e| ServerError::BadRequest(format!("Invalid presence record: {e}")))?;
+    let user_pubkey = presence.user_id.0;
+
+    if let Some(ref token) = req.premium_token {
+        if token.user_pubkey != user_pubkey {
+            return Err(ServerError::BadRequest(
+                "Premium token belongs to another identity".into(),
+            ));
+        }
+        state.premium_verifier.verify(token).await;
+    }
+    let premium_until = state.premium_verifier.cached_until(&user_pubkey).await;
+
+    state.relay_policy.bind(
+        presence.peer_id,
+        user_pubkey,
+        presence.expires_at,
+        premium_until,
+    );
+    info!(
+        peer = %presence.peer_id,
+        user = %presence.user_id.short(),
+        premium = premium_until.is_some(),
+        "Relay peer bound to identity"
+    );
+
+    Ok(Json(RelayBindResponse {
+        peer_id: presence.peer_id.to_string(),
+        premium: premium_until.is_some(),
+    }))
+}
+
+/// Identity an upload is charged to and the limits of its tier, enforcing
+/// `premium_required`.
+async fn uploader(state: &AppState, caller: Caller) -> Result<([u8; 32], BlobLimits), ServerError> {
+    let Caller::User(owner) = caller else {
+        return Err(ServerError::Forbidden(
+            "Uploads must be signed with an identity key".into(),
+        ));
+    };
+    let premium = state.premium_verifier.is_premium_cached(&owner).await;
+    if state.relay_policy.settings().premium_required && !premium {
+        return Err(ServerError::Forbidden(
+            "Premium required for blob uploads".into(),
+        ));
+    }
+    Ok((owner, tier_limits(&state.config, premium)))
+}
+
+fn tier_limits(config: &ServerConfig, premium: bool) -> BlobLimits {
+    if premium {
+        config.blob_limits_premium
+    } else {
+        config.blob_limits_free
+    }
+}
+
+async fn read_file_field(mut multipart: Multipart) -> Result<Bytes, ServerError> {
     while let Some(field) = multipart
         .next_field()
         .await
         .map_err(|e| ServerError::BadRequest(format!("Multipart error: {}", e)))?
     {
-        let name = field.name().unwrap_or("").to_string();
-        if name == "file" {
-            let data = field
+        if field.name() == Some("file") {
+            return field
                 .bytes()
                 .await
-                .map_err(|e| ServerError::BadRequest(format!("Failed to read field: {}", e)))?;
-
-            let id = state.blob_store.store_blob(&data).await?;
-
-            info!(id = %id, size = data.len(), "Blob uploaded via API");
-
-            return Ok(Json(BlobUploadResponse { id }));
+                .map_err(|e| ServerError::BadRequest(format!("Failed to read field: {}", e)));
         }
     }
 
@@ -149,100 +346,819 @@ async fn blob_upload(
     ))
 }
 
+async fn blob_upload(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    multipart: Multipart,
+) -> Result<Json<BlobUploadResponse>, ServerError> {
+    let (owner, limits) = uploader(&state, caller).await?;
+    let data = read_file_field(multipart).await?;
+
+    state
+        .metrics
+        .blob_requests
+        .get_or_create(&BlobOpLabels { op: BlobOp::Upload })
+        .inc();
+    let id = state.blob_store.store_blob(&data, &owner, limits).await?;
+
+    info!(
+        id = %id,
+        size = data.len(),
+        owner = %hex::encode(owner),
+        "Blob uploaded via API"
+    );
+
+    Ok(Json(BlobUploadResponse { id }))
+}
+
+/// Upload stored under the BLAKE3 hash of its content; uploading content
+/// the server already has only adds a reference.
+async fn blob_upload_content(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    multipart: Multipart,
+) -> Result<Json<BlobUploadResponse>, ServerError> {
+    let (owner, limits) = uploader(&state, caller).await?;
+    let data = read_file_field(multipart).await?;
+
+    state
+        .metrics
+        .blob_requests
+        .get_or_create(&BlobOpLabels { op: BlobOp::Upload })
+        .inc();
+    let id = state
+        .blob_store
+        .store_content(&data, &owner, limits)
+        .await?;
+
+    info!(
+        id = %id,
+        size = data.len(),
+        owner = %hex::encode(owner),
+        "Content-addressed blob uploaded via API"
+    );
+
+    Ok(Json(BlobUploadResponse { id }))
+}
+
+/// Reference content the server already stores (see `HEAD /blob/:id`)
+/// without uploading it again.
+async fn blob_reference(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<BlobId>,
+) -> Result<Json<BlobUploadResponse>, ServerError> {
+    let (owner, limits) = uploader(&state, caller).await?;
+    state.blob_store.add_reference(id, &owner, limits).await?;
+    Ok(Json(BlobUploadResponse { id }))
+}
+
+/// Storage used by the signing identity and the limits of its tier.
+async fn blob_quota(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+) -> Result<Json<BlobQuotaResponse>, ServerError> {
+    let Caller::User(owner) = caller else {
+        return Err(ServerError::BadRequest(
+            "Quota is per identity; sign the request".into(),
+        ));
+    };
+    let premium = state.premium_verifier.is_premium_cached(&owner).await;
+    let limits = tier_limits(&state.config, premium);
+    Ok(Json(BlobQuotaResponse {
+        used_bytes: state.blob_store.usage_of(&owner).await?,
+        quota_bytes: limits.quota_bytes,
+        ttl_secs: limits.ttl.map(|ttl| ttl.as_secs()),
+    }))
+}
+
+/// Whether blob `id` is stored, with its size in `Content-Length`, so
+/// clients can skip uploading content the server already has.
+async fn blob_head(
+    State(state): State<AppState>,
+    Path(id): Path<BlobId>,
+) -> Result<Response, ServerError> {
+    let size = state
+        .blob_store
+        .blob_size(id)
+        .await?
+        .ok_or(ServerError::BlobNotFound(id))?;
+    Ok((
+        [
+            (header::CONTENT_LENGTH, size.to_string()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ],
+        (),
+    )
+        .into_response())
+}
+
+/// Streams the blob, or the single byte range asked for in `Range` so
+/// interrupted downloads can resume.
 async fn blob_download(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<Vec<u8>, ServerError> {
-    let data = state.blob_store.get_blob(id).await?;
-    Ok(data)
+    Path(id): Path<BlobId>,
+    headers: HeaderMap,
+) -> Result<Response, ServerError> {
+    state
+        .metrics
+        .blob_requests
+        .get_or_create(&BlobOpLabels {
+            op: BlobOp::Download,
+        })
+        .inc();
+    let size = state
+        .blob_store
+        .blob_size(id)
+        .await?
+        .ok_or(ServerError::BlobNotFound(id))?;
+
+    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    let (status, start, len) = match parse_range(range, size) {
+        RangeRequest::Full => (StatusCode::OK, 0, size),
+        RangeRequest::Partial(range) => (StatusCode::PARTIAL_CONTENT, range.start, range.len()),
+        RangeRequest::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{size}"))],
+            )
+                .into_response());
+        }
+    };
+    let stream = state.blob_store.read_blob(id, start, len).await?;
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(
+            header::CONTENT_RANGE,
+            format!("bytes {start}-{}/{size}", start + len - 1),
+        );
+    }
+    response
+        .body(Body::from_stream(stream))
+        .map_err(|e| ServerError::Internal(e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct CreateUploadRequest {
+    size: u64,
+}
+
+#[derive(Deserialize)]
+struct AppendUploadQuery {
+    offset: u64,
+}
+
+#[derive(Deserialize)]
+struct FinalizeUploadRequest {
+    /// BLAKE3 of the whole blob, hex encoded.
+    hash: String,
+}
+
+#[derive(Serialize)]
+struct UploadSessionResponse {
+    upload_id: Uuid,
+    size: u64,
+    /// Bytes received so far; the next chunk starts here.
+    offset: u64,
+    chunk_size: usize,
+}
+
+impl UploadSessionResponse {
+    fn new(state: &AppState, session: &UploadSession, offset: u64) -> Self {
+        Self {
+            upload_id: session.id,
+            size: session.size,
+            offset,
+            chunk_size: state.config.max_chunk_size,
+        }
+    }
+}
+
+/// Start a resumable upload of `size` bytes.
+async fn upload_create(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<CreateUploadRequest>,
+) -> Result<Json<UploadSessionResponse>, ServerError> {
+    let (owner, limits) = uploader(&state, caller).await?;
+    // Checked again on finalize; this only spares a doomed upload
+    let used = state.blob_store.usage_of(&owner).await?;
+    let session = state
+        .uploads
+        .create(&owner, req.size, used, limits.quota_bytes)
+        .await?;
+    Ok(Json(UploadSessionResponse::new(&state, &session, 0)))
+}
+
+/// Where to resume an interrupted upload.
+async fn upload_status(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Json<UploadSessionResponse>, ServerError> {
+    let owner = session_owner(caller)?;
+    let (session, offset) = state.uploads.status(upload_id, &owner).await?;
+    Ok(Json(UploadSessionResponse::new(&state, &session, offset)))
+}
+
+/// Append the request body at `?offset=`, which must match the bytes
+/// received so far (409 otherwise).
+async fn upload_append(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(upload_id): Path<Uuid>,
+    Query(query): Query<AppendUploadQuery>,
+    chunk: Bytes,
+) -> Result<Json<UploadSessionResponse>, ServerError> {
+    let owner = session_owner(caller)?;
+    if chunk.len() > state.config.max_chunk_size {
+        return Err(ServerError::BadRequest(format!(
+            "Chunk larger than {} bytes",
+            state.config.max_chunk_size
+        )));
+    }
+
+    let offset = state
+        .uploads
+        .append(upload_id, &owner, query.offset, &chunk)
+        .await?;
+    let (session, _) = state.uploads.status(upload_id, &owner).await?;
+    Ok(Json(UploadSessionResponse::new(&state, &session, offset)))
+}
+
+/// Check the received blob against `hash` and store it content-addressed.
+async fn upload_finalize(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(upload_id): Path<Uuid>,
+    Json(req): Json<FinalizeUploadRequest>,
+) -> Result<Json<BlobUploadResponse>, ServerError> {
+    let (owner, limits) = uploader(&state, caller).await?;
+    let expected: BlobId = req.hash.parse()?;
+
+    let (session, path, hash) = state.uploads.completed(upload_id, &owner).await?;
+    let id = BlobId::Content(hash);
+    if id != expected {
+        return Err(ServerError::BadRequest(format!(
+            "Uploaded data hashes to {id}, not {expected}"
+        )));
+    }
+
+    state
+        .metrics
+        .blob_requests
+        .get_or_create(&BlobOpLabels { op: BlobOp::Upload })
+        .inc();
+    state
+        .blob_store
+        .adopt_content(&path, id, session.size, &owner, limits)
+        .await?;
+    state.uploads.remove(upload_id).await?;
+
+    info!(
+        id = %id,
+        size = session.size,
+        owner = %hex::encode(owner),
+        "Blob uploaded in chunks via API"
+    );
+
+    Ok(Json(BlobUploadResponse { id }))
+}
+
+async fn upload_abort(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ServerError> {
+    let owner = session_owner(caller)?;
+    state.uploads.status(upload_id, &owner).await?;
+    state.uploads.remove(upload_id).await?;
+    Ok(Json(serde_json::json!({ "deleted": true })))
+}
+
+fn session_owner(caller: Caller) -> Result<[u8; 32], ServerError> {
+    match caller {
+        Caller::User(owner) => Ok(owner),
+        Caller::Admin => Err(ServerError::Forbidden(
+            "Uploads must be signed with an identity key".into(),
+        )),
+    }
 }
 
+/// Owners drop their own reference, which deletes the blob once no one
+/// else holds one; the admin token deletes any blob outright.
 async fn blob_delete(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
+    Extension(caller): Extension<Caller>,
+    client_ip: Option<Extension<ClientIp>>,
+    Path(id): Path<BlobId>,
 ) -> Result<Json<serde_json::Value>, ServerError> {
-    state.blob_store.delete_blob(id).await?;
+    state
+        .metrics
+        .blob_requests
+        .get_or_create(&BlobOpLabels { op: BlobOp::Delete })
+        .inc();
+    match caller {
+        Caller::User(pubkey) => state.blob_store.release_blob(id, &pubkey).await?,
+        Caller::Admin => {
+            state.blob_store.delete_blob(id).await?;
+            // Same trail as `DELETE /admin/blobs/:id`
+            state
+                .audit
+                .record(admin_actor(client_ip), "delete_blob", Some(id.to_string()))
+                .await;
+        }
+    }
     Ok(Json(serde_json::json!({ "deleted": true })))
 }
 
 async fn server_info(State(state): State<AppState>) -> Json<ServerInfoResponse> {
+    let settings = state.relay_policy.settings();
     Json(ServerInfoResponse {
         name: state.config.instance_name.clone(),
         version: env!("CARGO_PKG_VERSION"),
-        premium_required: state.config.premium_required,
-        registration_open: state.config.registration_open,
+        premium_required: settings.premium_required,
+        registration_open: settings.registration_open,
         max_peers: state.config.max_peers,
+        peer_id: state.relay.peer_id.to_string(),
+        multiaddrs: state
+            .relay
+            .multiaddrs()
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect(),
     })
 }
 
-fn verify_admin_token(headers: &HeaderMap, config: &ServerConfig) -> Result<(), ServerError> {
-    let Some(ref expected) = config.admin_token else {
-        return Err(ServerError::Forbidden(
-            "Admin API is disabled (no ADMIN_TOKEN configured)".into(),
-        ));
-    };
-
-    let auth = headers
-        .get("authorization")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-
-    let token = auth.strip_prefix("Bearer ").unwrap_or(auth);
-
-    // Constant-time comparison to prevent timing attacks on admin token.
-    use subtle::ConstantTimeEq;
-    let token_bytes = token.as_bytes();
-    let expected_bytes = expected.as_bytes();
-    if token_bytes.len() != expected_bytes.len()
-        || token_bytes.ct_eq(expected_bytes).unwrap_u8() != 1
-    {
-        return Err(ServerError::Forbidden("Invalid admin token".into()));
-    }
-
-    Ok(())
-}
-
 async fn admin_status(
     headers: HeaderMap,
     State(state): State<AppState>,
 ) -> Result<Json<AdminStatusResponse>, ServerError> {
     verify_admin_token(&headers, &state.config)?;
 
+    let settings = state.relay_policy.settings();
     Ok(Json(AdminStatusResponse {
         name: state.config.instance_name.clone(),
-        premium_required: state.config.premium_required,
-        registration_open: state.config.registration_open,
+        premium_required: settings.premium_required,
+        registration_open: settings.registration_open,
         max_peers: state.config.max_peers,
-        uptime_secs: 0, // TODO: track with start instant
+        uptime_secs: state.metrics.uptime_secs(),
     }))
 }
 
 async fn admin_grant_premium(
     headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
     State(state): State<AppState>,
     Json(req): Json<AdminPremiumRequest>,
 ) -> Result<Json<serde_json::Value>, ServerError> {
     verify_admin_token(&headers, &state.config)?;
 
     let pubkey = parse_hex_32(&req.user_pubkey_hex)?;
-    state.premium_verifier.admin_grant(&pubkey).await;
+    if req.valid_until.is_some_and(|until| until <= Utc::now()) {
+        return Err(ServerError::BadRequest(
+            "'valid_until' must be in the future".into(),
+        ));
+    }
+    let actor = admin_actor(client_ip);
+    let grant = state
+        .premium_verifier
+        .admin_grant(&pubkey, req.valid_until, actor.clone(), req.note)
+        .await?;
+    if let Some(until) = state.premium_verifier.cached_until(&pubkey).await {
+        state.relay_policy.set_premium(pubkey, until);
+    }
 
-    info!(user = %req.user_pubkey_hex, "Admin granted premium");
-    Ok(Json(serde_json::json!({ "granted": true })))
+    state
+        .audit
+        .record(actor, "grant_premium", Some(hex::encode(pubkey)))
+        .await;
+    Ok(Json(
+        serde_json::json!({ "granted": true, "valid_until": grant.valid_until }),
+    ))
 }
 
 async fn admin_revoke_premium(
     headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
     State(state): State<AppState>,
-    Json(req): Json<AdminPremiumRequest>,
+    Json(req): Json<AdminRevokePremiumRequest>,
 ) -> Result<Json<serde_json::Value>, ServerError> {
     verify_admin_token(&headers, &state.config)?;
 
     let pubkey = parse_hex_32(&req.user_pubkey_hex)?;
-    state.premium_verifier.admin_revoke(&pubkey).await;
+    let actor = admin_actor(client_ip);
+    let changed = state
+        .premium_verifier
+        .admin_revoke(&pubkey, actor.clone(), req.reason)
+        .await?;
+    state.relay_policy.revoke_premium(&pubkey);
 
-    info!(user = %req.user_pubkey_hex, "Admin revoked premium");
-    Ok(Json(serde_json::json!({ "revoked": true })))
+    state
+        .audit
+        .record(actor, "revoke_premium", Some(hex::encode(pubkey)))
+        .await;
+    Ok(Json(
+        serde_json::json!({ "revoked": true, "changed": changed }),
+    ))
+}
+
+/// `q` matches a pubkey hex prefix or part of the note (grants) or reason
+/// (revocations).
+#[derive(Deserialize)]
+struct AdminPremiumQuery {
+    q: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+impl AdminPremiumQuery {
+    fn search(&self) -> &str {
+        self.q.as_deref().map(str::trim).unwrap_or_default()
+    }
+
+    fn limit(&self) -> u32 {
+        self.limit.unwrap_or(100).min(1000)
+    }
+}
+
+#[derive(Serialize)]
+struct AdminPremiumGrant {
+    user_pubkey_hex: String,
+    granted_at: DateTime<Utc>,
+    granted_by: Option<String>,
+    valid_until: DateTime<Utc>,
+    active: bool,
+    note: Option<String>,
+    /// End of the latest payment token the identity presented, if any.
+    token_valid_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct AdminPremiumRevocation {
+    user_pubkey_hex: String,
+    revoked_at: DateTime<Utc>,
+    revoked_by: Option<String>,
+    reason: Option<String>,
+}
+
+/// Admin grants, most recent first, expired ones included.
+async fn admin_premium_grants(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Query(query): Query<AdminPremiumQuery>,
+) -> Result<Json<Vec<AdminPremiumGrant>>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let store = state.premium_verifier.store();
+    let now = Utc::now();
+    let grants = store.search_grants(query.search(), query.limit(), query.offset.unwrap_or(0))?;
+    let mut entries = Vec::with_capacity(grants.len());
+    for grant in grants {
+        entries.push(AdminPremiumGrant {
+            user_pubkey_hex: hex::encode(grant.user_pubkey),
+            granted_at: grant.granted_at,
+            granted_by: grant.granted_by,
+            valid_until: grant.valid_until,
+            active: grant.valid_until > now,
+            note: grant.note,
+            token_valid_until: store
+                .token_of(&grant.user_pubkey)?
+                .map(|stored| stored.token.valid_until),
+        });
+    }
+    Ok(Json(entries))
+}
+
+/// Revocations, most recent first.
+async fn admin_premium_revocations(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Query(query): Query<AdminPremiumQuery>,
+) -> Result<Json<Vec<AdminPremiumRevocation>>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let revocations = state.premium_verifier.store().search_revocations(
+        query.search(),
+        query.limit(),
+        query.offset.unwrap_or(0),
+    )?;
+    Ok(Json(
+        revocations
+            .into_iter()
+            .map(|revocation| AdminPremiumRevocation {
+                user_pubkey_hex: hex::encode(revocation.user_pubkey),
+                revoked_at: revocation.revoked_at,
+                revoked_by: revocation.revoked_by,
+                reason: revocation.reason,
+            })
+            .collect(),
+    ))
+}
+
+/// Who performed an admin action, for the audit log.
+fn admin_actor(client_ip: Option<Extension<ClientIp>>) -> Option<String> {
+    client_ip.map(|Extension(ClientIp(ip))| ip.to_string())
+}
+
+#[derive(Serialize)]
+struct AdminPeer {
+    peer_id: String,
+    addr: String,
+    connected_at: DateTime<Utc>,
+    reserved: bool,
+}
+
+async fn admin_peers(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AdminPeer>>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let mut peers: Vec<AdminPeer> = state
+        .relay
+        .peers()
+        .into_iter()
+        .map(|peer| AdminPeer {
+            peer_id: peer.peer_id.to_string(),
+            addr: peer.addr.to_string(),
+            connected_at: peer.connected_at,
+            reserved: peer.reserved,
+        })
+        .collect();
+    peers.sort_by_key(|peer| peer.connected_at);
+    Ok(Json(peers))
+}
+
+#[derive(Serialize)]
+struct AdminCircuit {
+    src: String,
+    dst: String,
+    opened_at: DateTime<Utc>,
+}
+
+async fn admin_circuits(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AdminCircuit>>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    Ok(Json(
+        state
+            .relay
+            .circuits()
+            .into_iter()
+            .map(|circuit| AdminCircuit {
+                src: circuit.src.to_string(),
+                dst: circuit.dst.to_string(),
+                opened_at: circuit.opened_at,
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Serialize)]
+struct AdminBlob {
+    id: BlobId,
+    /// Hex identity key of the uploader, if recorded.
+    owner: Option<String>,
+    size_bytes: u64,
+    stored_at: DateTime<Utc>,
+    age_secs: i64,
+    last_accessed_at: DateTime<Utc>,
+    /// When the blob is collected unless read again; `None` never.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+async fn admin_blobs(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AdminBlob>>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let now = Utc::now();
+    let blobs = state
+        .blob_store
+        .list_blob_info()
+        .await?
+        .into_iter()
+        .map(|blob| AdminBlob {
+            id: blob.id,
+            owner: blob.owner.map(hex::encode),
+            size_bytes: blob.size,
+            stored_at: blob.created_at,
+            age_secs: (now - blob.created_at).num_seconds().max(0),
+            last_accessed_at: blob.last_accessed_at,
+            expires_at: blob.expires_at(),
+        })
+        .collect();
+    Ok(Json(blobs))
+}
+
+async fn admin_delete_blob(
+    headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
+    State(state): State<AppState>,
+    Path(id): Path<BlobId>,
+) -> Result<Json<serde_json::Value>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    state.blob_store.delete_blob(id).await?;
+
+    state
+        .audit
+        .record(admin_actor(client_ip), "delete_blob", Some(id.to_string()))
+        .await;
+    Ok(Json(serde_json::json!({ "deleted": true })))
+}
+
+/// Exactly one of `peer_id` and `ip`.
+#[derive(Deserialize)]
+struct AdminBanRequest {
+    peer_id: Option<String>,
+    ip: Option<String>,
+}
+
+enum BanTarget {
+    Peer(PeerId),
+    Ip(IpAddr),
+}
+
+impl AdminBanRequest {
+    fn target(&self) -> Result<BanTarget, ServerError> {
+        match (&self.peer_id, &self.ip) {
+            (Some(peer_id), None) => peer_id
+                .trim()
+                .parse()
+                .map(BanTarget::Peer)
+                .map_err(|e| ServerError::BadRequest(format!("Invalid peer id: {e}"))),
+            (None, Some(ip)) => ip
+                .trim()
+                .parse()
+                .map(BanTarget::Ip)
+                .map_err(|e| ServerError::BadRequest(format!("Invalid IP address: {e}"))),
+            _ => Err(ServerError::BadRequest(
+                "Expected exactly one of 'peer_id' and 'ip'".into(),
+            )),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AdminBansResponse {
+    peers: Vec<String>,
+    ips: Vec<String>,
+}
+
+async fn admin_bans(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<AdminBansResponse>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let mut peers: Vec<String> = state
+        .relay_policy
+        .banned_peers()
+        .iter()
+        .map(PeerId::to_string)
+        .collect();
+    peers.sort();
+    let mut ips = state.relay_policy.banned_ips();
+    ips.sort();
+
+    Ok(Json(AdminBansResponse {
+        peers,
+        ips: ips.iter().map(IpAddr::to_string).collect(),
+    }))
+}
+
+/// Ban a peer id or an IP and drop its relay connections. Bans last until
+/// the next restart; permanent ones belong in `RELAY_DENYLIST` or
+/// `BANNED_IPS`.
+async fn admin_ban(
+    headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
+    State(state): State<AppState>,
+    Json(req): Json<AdminBanRequest>,
+) -> Result<Json<serde_json::Value>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let (action, target, changed) = match req.target()? {
+        BanTarget::Peer(peer_id) => {
+            let changed = state.relay_policy.ban_peer(peer_id);
+            state.relay.disconnect(peer_id);
+            ("ban_peer", peer_id.to_string(), changed)
+        }
+        BanTarget::Ip(ip) => {
+            let changed = state.relay_policy.ban_ip(ip);
+            state.relay.disconnect_ip(ip);
+            ("ban_ip", ip.to_string(), changed)
+        }
+    };
+
+    state
+        .audit
+        .record(admin_actor(client_ip), action, Some(target))
+        .await;
+    Ok(Json(
+        serde_json::json!({ "banned": true, "changed": changed }),
+    ))
+}
+
+async fn admin_unban(
+    headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
+    State(state): State<AppState>,
+    Json(req): Json<AdminBanRequest>,
+) -> Result<Json<serde_json::Value>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let (action, target, changed) = match req.target()? {
+        BanTarget::Peer(peer_id) => (
+            "unban_peer",
+            peer_id.to_string(),
+            state.relay_policy.unban_peer(&peer_id),
+        ),
+        BanTarget::Ip(ip) => ("unban_ip", ip.to_string(), state.relay_policy.unban_ip(&ip)),
+    };
+
+    state
+        .audit
+        .record(admin_actor(client_ip), action, Some(target))
+        .await;
+    Ok(Json(
+        serde_json::json!({ "unbanned": true, "changed": changed }),
+    ))
+}
+
+async fn admin_config(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<AdmissionSettings>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+    Ok(Json(state.relay_policy.settings()))
+}
+
+/// Fields left out keep their current value.
+#[derive(Deserialize)]
+struct AdminConfigRequest {
+    premium_required: Option<bool>,
+    registration_open: Option<bool>,
+}
+
+/// Toggle admission settings without a restart. Existing reservations are
+/// kept; the new settings apply from their next renewal.
+async fn admin_set_config(
+    headers: HeaderMap,
+    client_ip: Option<Extension<ClientIp>>,
+    State(state): State<AppState>,
+    Json(req): Json<AdminConfigRequest>,
+) -> Result<Json<AdmissionSettings>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let mut settings = state.relay_policy.settings();
+    if let Some(premium_required) = req.premium_required {
+        settings.premium_required = premium_required;
+    }
+    if let Some(registration_open) = req.registration_open {
+        settings.registration_open = registration_open;
+    }
+    state.relay_policy.set_settings(settings);
+
+    state
+        .audit
+        .record(
+            admin_actor(client_ip),
+            "set_config",
+            Some(format!(
+                "premium_required={} registration_open={}",
+                settings.premium_required, settings.registration_open
+            )),
+        )
+        .await;
+    Ok(Json(settings))
+}
+
+#[derive(Deserialize)]
+struct AdminAuditQuery {
+    limit: Option<usize>,
+}
+
+/// Most recent audit entries first, 100 by default.
+async fn admin_audit(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Query(query): Query<AdminAuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, ServerError> {
+    verify_admin_token(&headers, &state.config)?;
+
+    let limit = query.limit.unwrap_or(100).min(1000);
+    Ok(Json(state.audit.recent(limit).await?))
 }
 
 fn parse_hex_32(hex: &str) -> Result<[u8; 32], ServerError> {
@@ -264,7 +1180,7 @@ fn parse_hex_32(hex: &str) -> Result<[u8; 32], ServerError> {
 
 #[derive(Deserialize)]
 struct BackupSyncRequest {
-    /// User's Ed25519 pubkey (hex, 64 chars)
+    /// User's Ed25519 pubkey (hex, 64 chars); must be the request signer
     user_pubkey_hex: String,
     /// Encrypted backup data (the client encrypts before sending)
     encrypted_data: String,
@@ -274,71 +1190,100 @@ struct BackupSyncRequest {
 struct BackupSyncResponse {
     stored: bool,
     size_bytes: usize,
+    version: i64,
+    blake3: String,
 }
 
-/// Upload an encrypted backup blob, keyed by user pubkey.
-/// Overwrites any previous backup for that user.
+#[derive(Serialize)]
+struct BackupVersionResponse {
+    version: i64,
+    created_at: DateTime<Utc>,
+    size_bytes: u64,
+    blake3: String,
+}
+
+#[derive(Deserialize)]
+struct BackupDownloadQuery {
+    /// Older version to restore; the latest when absent.
+    version: Option<i64>,
+}
+
+/// Backups are only readable and writable by the identity they belong to.
+fn require_backup_owner(caller: Caller, pubkey_hex: &str) -> Result<[u8; 32], ServerError> {
+    let pubkey = parse_hex_32(pubkey_hex)?;
+    match caller {
+        Caller::User(signer) if signer == pubkey => Ok(pubkey),
+        _ => Err(ServerError::Forbidden(
+            "Backups can only be accessed with their owner's signature".into(),
+        )),
+    }
+}
+
+/// Upload an encrypted backup blob, keyed by user pubkey. Previous uploads
+/// are kept as older versions, up to `BACKUP_VERSIONS`.
 async fn backup_sync_upload(
     State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
     Json(req): Json<BackupSyncRequest>,
 ) -> Result<Json<BackupSyncResponse>, ServerError> {
-    // Validate pubkey is exactly 64 hex chars (rejects any path-traversal chars)
-    let pubkey = parse_hex_32(&req.user_pubkey_hex)?;
+    let pubkey = require_backup_owner(caller, &req.user_pubkey_hex)?;
     let data = req.encrypted_data.as_bytes();
 
-    // Re-encode from validated bytes — produces a guaranteed-safe hex string
-    // that is detached from the raw user input (breaks taint chain).
-    let safe_hex = hex::encode(pubkey);
-
-    // Ensure backups directory exists via safe path construction
-    state.blob_store.ensure_subdir("backups").await?;
-
-    let filename = format!("{safe_hex}.enc");
-    let file_path = state.blob_store.safe_subpath("backups", &filename)?;
-    tokio::fs::write(&file_path, data)
-        .await
-        .map_err(|e| ServerError::Internal(format!("Failed to write backup: {e}")))?;
+    let version = state.backup_store.store(&pubkey, data).await?;
 
     info!(
-        user = %safe_hex,
+        user = %hex::encode(pubkey),
         size = data.len(),
+        version = version.version,
         "Backup synced to server"
     );
 
     Ok(Json(BackupSyncResponse {
         stored: true,
         size_bytes: data.len(),
+        version: version.version,
+        blake3: version.hash,
     }))
 }
 
-/// Download the encrypted backup for a given user pubkey.
+/// Download the encrypted backup for a given user pubkey, optionally an
+/// older `?version=`.
 async fn backup_sync_download(
     State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
     Path(pubkey_hex): Path<String>,
+    Query(query): Query<BackupDownloadQuery>,
 ) -> Result<String, ServerError> {
-    // Validate pubkey is exactly 64 hex chars (rejects any path-traversal chars)
-    let pubkey = parse_hex_32(&pubkey_hex)?;
-
-    // Re-encode from validated bytes — produces a guaranteed-safe hex string
-    // that is detached from the raw user input (breaks taint chain).
-    let safe_hex = hex::encode(pubkey);
-    let filename = format!("{safe_hex}.enc");
-    let file_path = state.blob_store.safe_subpath("backups", &filename)?;
-
-    if !file_path.exists() {
-        return Err(ServerError::NotFound(
-            "No backup found for this user".into(),
-        ));
-    }
+    let pubkey = require_backup_owner(caller, &pubkey_hex)?;
+    let (_, data) = state.backup_store.load(&pubkey, query.version).await?;
 
-    let data = tokio::fs::read_to_string(&file_path)
-        .await
-        .map_err(|e| ServerError::Internal(format!("Failed to read backup: {e}")))?;
+    String::from_utf8(data)
+        .map_err(|e| ServerError::Internal(format!("Stored backup is not UTF-8: {e}")))
+}
+
+/// Stored versions of a user's backup, newest first.
+async fn backup_versions(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(pubkey_hex): Path<String>,
+) -> Result<Json<Vec<BackupVersionResponse>>, ServerError> {
+    let pubkey = require_backup_owner(caller, &pubkey_hex)?;
+    let versions = state.backup_store.versions(&pubkey).await?;
 
-    Ok(data)
+    Ok(Json(
+        versions
+            .into_iter()
+            .map(|v| BackupVersionResponse {
+                version: v.version,
+                created_at: v.created_at,
+                size_bytes: v.size,
+                blake3: v.hash,
+            })
+            .collect(),
+    ))
 }
 
-pub async fn serve(state: AppState, addr: std::net::SocketAddr) -> anyhow::Result<()> {
+pub async fn serve(state: AppState, addr: SocketAddr) -> anyhow::Result<()> {
     let app = build_router(state);
 
     info!(addr = %addr, "Starting HTTP API server");
@@ -346,9 +1291,279 @@ pub async fn serve(state: AppState, addr: std::net::SocketAddr) -> anyhow::Resul
     let listener = tokio::net::TcpListener::bind(addr).await?;
     axum::serve(
         listener,
-        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
     )
     .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+impl AppState {
+    /// State for router tests, storing everything under `dir`.
+    pub async fn for_tests(dir: &std::path::Path) -> Self {
+        let config = ServerConfig {
+            admin_token: Some("admin-secret".to_string()),
+            blob_storage_path: dir.to_path_buf(),
+            audit_log_path: dir.join("audit.log"),
+            ..Default::default()
+        };
+        let premium_verifier = Arc::new(
+            PremiumVerifier::new(
+                config.payment_server_pubkey,
+                crate::premium_store::PremiumStore::open_in_memory().unwrap(),
+            )
+            .unwrap(),
+        );
+        let rate_limiter = RateLimiter::default();
+        let relay = RelayInfo::detached(libp2p::PeerId::random());
+        Self {
+            blob_store: Arc::new(
+                BlobStore::new(dir.join("blobs"), config.max_blob_size)
+                    .await
+                    .unwrap(),
+            ),
+            uploads: Arc::new(
+                UploadSessions::new(dir.join("uploads"), config.max_blob_size as u64)
+                    .await
+                    .unwrap(),
+            ),
+            backup_store: Arc::new(
+                BackupStore::new(
+                    dir.join("backups"),
+                    config.backup_versions,
+                    config.backup_quota_bytes,
+                )
+                .await
+                .unwrap(),
+            ),
+            metrics: Arc::new(Metrics::new(&rate_limiter, &premium_verifier)),
+            premium_verifier,
+            rate_limiter,
+            relay,
+            relay_policy: RelayPolicy::new(&config),
+            sfu: SfuManager::new(config.sfu_max_participants),
+            audit: AuditLog::new(config.audit_log_path.clone()),
+            seen_signatures: Default::default(),
+            config: Arc::new(config),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use liberte_shared::identity::Identity;
+    use liberte_shared::request_auth::{
+        RequestSignature, HEADER_NONCE, HEADER_PUBKEY, HEADER_SIGNATURE, HEADER_TIMESTAMP,
+    };
+    use tower::ServiceExt;
+
+    use crate::config::BlobLimits;
+
+    const LIMITS: BlobLimits = BlobLimits {
+        quota_bytes: 0,
+        ttl: None,
+    };
+
+    fn signed(identity: &Identity, method: &str, path: &str) -> Request<Body> {
+        let signature =
+            RequestSignature::sign(identity, method, path, b"", chrono::Utc::now().timestamp());
+        let [pubkey, timestamp, nonce, signature] = signature.header_values();
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .header(HEADER_PUBKEY, pubkey)
+            .header(HEADER_TIMESTAMP, timestamp)
+            .header(HEADER_NONCE, nonce)
+            .header(HEADER_SIGNATURE, signature)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn unsigned(method: &str, path: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_blob_get_head_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::for_tests(dir.path()).await;
+        let owner = Identity::generate();
+        let id = state
+            .blob_store
+            .store_blob(b"hello", &owner.public_key_bytes(), LIMITS)
+            .await
+            .unwrap();
+        let path = format!("/blob/{id}");
+        let app = build_router(state);
+
+        let resp = app.clone().oneshot(unsigned("GET", &path)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        let resp = app.clone().oneshot(unsigned("HEAD", &path)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_LENGTH], "5");
+
+        let stranger = Identity::generate();
+        let resp = app
+            .clone()
+            .oneshot(signed(&stranger, "DELETE", &path))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = app
+            .clone()
+            .oneshot(signed(&owner, "DELETE", &path))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = app.oneshot(unsigned("GET", &path)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_blob_delete_is_audited() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::for_tests(dir.path()).await;
+        let id = state
+            .blob_store
+            .store_blob(b"hello", &[1u8; 32], LIMITS)
+            .await
+            .unwrap();
+        let audit = state.audit.clone();
+        let app = build_router(state);
+
+        let mut req = unsigned("DELETE", &format!("/blob/{id}"));
+        req.headers_mut().insert(
+            header::AUTHORIZATION,
+            "Bearer admin-secret".parse().unwrap(),
+        );
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let entries = audit.recent(10).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, "delete_blob");
+        assert_eq!(entries[0].target, Some(id.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_empty_admin_token_grants_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path()).await;
+        state.config = Arc::new(ServerConfig {
+            admin_token: Some(String::new()),
+            ..(*state.config).clone()
+        });
+        let app = build_router(state);
+
+        for path in ["/admin/status", "/admin/audit", "/metrics"] {
+            let resp = app.clone().oneshot(unsigned("GET", path)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{path}");
+        }
+        let mut req = unsigned("GET", "/admin/status");
+        req.headers_mut()
+            .insert(header::AUTHORIZATION, "Bearer ".parse().unwrap());
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_signed_routes_require_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = build_router(AppState::for_tests(dir.path()).await);
+        let identity = Identity::generate();
+        let path = format!("/blob/{}", BlobId::for_content(b"x"));
+
+        let resp = app
+            .clone()
+            .oneshot(unsigned("DELETE", &path))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        // Signed for another path
+        let mut req = signed(&identity, "DELETE", "/blob/quota");
+        *req.uri_mut() = path.parse().unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let resp = app
+            .clone()
+            .oneshot(unsigned("GET", "/blob/quota"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let resp = app
+            .oneshot(signed(&identity, "GET", "/blob/quota"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_signed_requests_charged_to_ip_and_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::for_tests(dir.path()).await;
+        state.rate_limiter.set_limits(crate::config::RateLimits {
+            upload: crate::config::RateLimit {
+                per_sec: 0.001,
+                burst: 1.0,
+            },
+            per_identity: true,
+            ..Default::default()
+        });
+        let app = build_router(state);
+        let path = format!("/blob/{}", BlobId::for_content(b"x"));
+        let from = |mut req: Request<Body>, ip: [u8; 4]| {
+            req.extensions_mut()
+                .insert(axum::extract::ConnectInfo(SocketAddr::from((ip, 1234))));
+            req
+        };
+        let status = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+        let alice = Identity::generate();
+
+        assert_eq!(
+            status(from(signed(&alice, "DELETE", &path), [10, 0, 0, 1])).await,
+            StatusCode::NOT_FOUND
+        );
+        // A fresh key does not get a fresh bucket on the same address
+        let other = Identity::generate();
+        assert_eq!(
+            status(from(signed(&other, "DELETE", &path), [10, 0, 0, 1])).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // Nor does the same key on another address
+        let path_y = format!("/blob/{}", BlobId::for_content(b"y"));
+        assert_eq!(
+            status(from(signed(&alice, "DELETE", &path_y), [10, 0, 0, 2])).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // Unsigned requests are charged before they are refused
+        assert_eq!(
+            status(from(unsigned("DELETE", &path), [10, 0, 0, 3])).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(from(signed(&other, "DELETE", &path), [10, 0, 0, 3])).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
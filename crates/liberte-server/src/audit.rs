@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::error::ServerError;
+
+/// One admin action, stored as a JSON line.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    /// Address the request came from; the admin token carries no identity.
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+}
+
+/// Append-only log of admin actions. Writes are serialized so concurrent
+/// requests never interleave lines.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Record an action. A failed write is logged but does not fail the
+    /// action itself, which has already been applied.
+    pub async fn record(&self, actor: Option<String>, action: &str, target: Option<String>) {
+        let entry = AuditEntry {
+            at: Utc::now(),
+            actor,
+            action: action.to_string(),
+            target,
+        };
+        info!(
+            action = %entry.action,
+            target = entry.target.as_deref().unwrap_or("-"),
+            actor = entry.actor.as_deref().unwrap_or("-"),
+            "Admin action"
+        );
+        if let Err(e) = self.append(&entry).await {
+            warn!(error = %e, path = %self.path.display(), "Failed to write audit log");
+        }
+    }
+
+    /// The `limit` most recent entries, newest first.
+    pub async fn recent(&self, limit: usize) -> Result<Vec<AuditEntry>, ServerError> {
+        let _guard = self.lock.lock().await;
+        let content = match fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(ServerError::Internal(format!(
+                    "Failed to read audit log: {e}"
+                )))
+            }
+        };
+        Ok(content
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .take(limit)
+            .collect())
+    }
+
+    async fn append(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().await;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        file.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recent_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit").join("audit.log"));
+        assert!(log.recent(10).await.unwrap().is_empty());
+
+        log.record(Some("10.0.0.1".into()), "ban_peer", Some("a".into()))
+            .await;
+        log.record(None, "set_config", None).await;
+        log.record(None, "delete_blob", Some("b".into())).await;
+
+        let recent = log.recent(2).await.unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].action, "delete_blob");
+        assert_eq!(recent[1].action, "set_config");
+
+        let all = log.recent(10).await.unwrap();
+        assert_eq!(all[2].actor.as_deref(), Some("10.0.0.1"));
+    }
+}
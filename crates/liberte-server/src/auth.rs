@@ -0,0 +1,136 @@
+use std::sync::Mutex;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+
+use liberte_shared::request_auth::{
+    RequestSignature, SeenSignatures, HEADER_NONCE, HEADER_PUBKEY, HEADER_SIGNATURE,
+    HEADER_TIMESTAMP,
+};
+
+use crate::api::AppState;
+use crate::config::{ServerConfig, MAX_REQUEST_BODY_SIZE};
+use crate::error::ServerError;
+use crate::rate_limit::{Client, RateClass};
+
+/// Room left for multipart boundaries and headers on top of the blob itself.
+const MULTIPART_OVERHEAD: usize = 64 * 1024;
+
+/// Who a signed route is acting for, set by [`authenticate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Caller {
+    /// Ed25519 identity key that signed the request.
+    User([u8; 32]),
+    /// Holder of the admin token.
+    Admin,
+}
+
+pub fn verify_admin_token(headers: &HeaderMap, config: &ServerConfig) -> Result<(), ServerError> {
+    // An empty token would match a request without the header
+    let Some(expected) = config.admin_token.as_ref().filter(|t| !t.trim().is_empty()) else {
+        return Err(ServerError::Forbidden(
+            "Admin API is disabled (no ADMIN_TOKEN configured)".into(),
+        ));
+    };
+
+    let auth = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    let token = auth.strip_prefix("Bearer ").unwrap_or(auth);
+
+    // Constant-time comparison to prevent timing attacks on admin token.
+    use subtle::ConstantTimeEq;
+    let token_bytes = token.as_bytes();
+    let expected_bytes = expected.as_bytes();
+    if token_bytes.len() != expected_bytes.len()
+        || token_bytes.ct_eq(expected_bytes).unwrap_u8() != 1
+    {
+        return Err(ServerError::Forbidden("Invalid admin token".into()));
+    }
+
+    Ok(())
+}
+
+/// Require an identity signature over the request (see
+/// `liberte_shared::request_auth`), or the admin token when one is sent.
+/// The body is buffered to be hashed, then handed on unchanged; the
+/// [`Caller`] is stored in the request extensions.
+///
+/// Requests the rate limiter deferred are charged here: to the signing
+/// identity once the signature checks out, else to the client IP.
+pub async fn authenticate(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ServerError> {
+    let (mut parts, body) = req.into_parts();
+
+    if parts.headers.contains_key("authorization") {
+        verify_admin_token(&parts.headers, &state.config)?;
+        parts.extensions.insert(Caller::Admin);
+        return Ok(next.run(Request::from_parts(parts, body)).await);
+    }
+
+    let (pubkey, bytes) = verify_signature(&parts, body, &state.seen_signatures).await?;
+    // The IP was already charged; this only adds a limit per signer
+    let limiter = &state.rate_limiter;
+    if limiter.per_identity() {
+        let class = RateClass::of(&parts.method, parts.uri.path());
+        limiter.admit(Client::Identity(pubkey), class).await?;
+    }
+
+    parts.extensions.insert(Caller::User(pubkey));
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await)
+}
+
+/// Check the identity signature of a request, returning the signer and
+/// the buffered body. Each signature is accepted once.
+async fn verify_signature(
+    parts: &axum::http::request::Parts,
+    body: Body,
+    seen: &Mutex<SeenSignatures>,
+) -> Result<([u8; 32], axum::body::Bytes), ServerError> {
+    let header = |name: &str| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| ServerError::Unauthorized(format!("Missing {name} header")))
+    };
+    let signature = RequestSignature::from_headers(
+        header(HEADER_PUBKEY)?,
+        header(HEADER_TIMESTAMP)?,
+        header(HEADER_NONCE)?,
+        header(HEADER_SIGNATURE)?,
+    )
+    .map_err(|e| ServerError::Unauthorized(e.to_string()))?;
+
+    let bytes = axum::body::to_bytes(body, MAX_REQUEST_BODY_SIZE + MULTIPART_OVERHEAD)
+        .await
+        .map_err(|e| ServerError::BadRequest(format!("Failed to read body: {e}")))?;
+
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let now = Utc::now().timestamp();
+    signature
+        .verify(parts.method.as_str(), path, &bytes, now)
+        .map_err(|e| ServerError::Unauthorized(e.to_string()))?;
+    seen.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(&signature, now)
+        .map_err(|e| ServerError::Unauthorized(e.to_string()))?;
+    Ok((signature.pubkey, bytes))
+}
@@ -0,0 +1,333 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, TimeZone, Utc};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info};
+
+use crate::blob_store::{ensure_within, KeyedLocks};
+use crate::error::ServerError;
+
+/// A sync being written, renamed to its version once complete; never
+/// listed as a version itself.
+const SYNC_TMP_FILE: &str = "sync.tmp";
+
+/// One stored backup of a user. `version` is the upload time in unix
+/// milliseconds and doubles as its id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupVersion {
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+    /// BLAKE3 of the encrypted backup, hex encoded.
+    pub hash: String,
+}
+
+impl BackupVersion {
+    fn file_name(&self) -> String {
+        format!("{}-{}.enc", self.version, self.hash)
+    }
+
+    /// Parse `<version>-<hash>.enc`; anything else is ignored.
+    fn parse(file_name: &str, size: u64) -> Option<Self> {
+        let (version, hash) = file_name.strip_suffix(".enc")?.split_once('-')?;
+        let version: i64 = version.parse().ok()?;
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(Self {
+            version,
+            created_at: Utc.timestamp_millis_opt(version).single()?,
+            size,
+            hash: hash.to_string(),
+        })
+    }
+}
+
+/// Encrypted backups, one directory per user holding its last
+/// `max_versions` uploads so a bad sync can be rolled back, within
+/// `quota_bytes` (0 for no limit) all together.
+#[derive(Debug, Clone)]
+pub struct BackupStore {
+    base_path: PathBuf,
+    max_versions: usize,
+    quota_bytes: u64,
+    /// Serializes syncs of a user, which pick the next version and prune
+    /// the oldest ones.
+    locks: KeyedLocks<[u8; 32]>,
+}
+
+impl BackupStore {
+    pub async fn new(
+        base_path: PathBuf,
+        max_versions: usize,
+        quota_bytes: u64,
+    ) -> Result<Self, ServerError> {
+        fs::create_dir_all(&base_path).await.map_err(|e| {
+            ServerError::Internal(format!(
+                "Failed to create backup directory '{}': {e}",
+                base_path.display()
+            ))
+        })?;
+        Ok(Self {
+            base_path,
+            max_versions: max_versions.max(1),
+            quota_bytes,
+            locks: KeyedLocks::default(),
+        })
+    }
+
+    /// Store `data` as the newest version and drop the oldest ones beyond
+    /// the version count or the quota. Refused only if `data` alone is
+    /// over the quota.
+    pub async fn store(&self, user: &[u8; 32], data: &[u8]) -> Result<BackupVersion, ServerError> {
+        let size = data.len() as u64;
+        let _user = self.locks.lock(*user).await;
+        let dir = self.user_dir(user).await?;
+        let existing = self.versions(user).await?;
+        if self.quota_bytes > 0 && size > self.quota_bytes {
+            return Err(ServerError::QuotaExceeded {
+                used: existing.iter().map(|v| v.size).sum(),
+                quota: self.quota_bytes,
+            });
+        }
+
+        // Two syncs within a millisecond still get distinct, ordered ids
+        let mut version = Utc::now().timestamp_millis();
+        if let Some(latest) = existing.first() {
+            version = version.max(latest.version + 1);
+        }
+        let entry = BackupVersion {
+            version,
+            created_at: Utc
+                .timestamp_millis_opt(version)
+                .single()
+                .unwrap_or_else(Utc::now),
+            size,
+            hash: blake3::hash(data).to_hex().to_string(),
+        };
+
+        // Written aside first, so a crash cannot leave a truncated file
+        // listed as the newest version
+        let tmp = ensure_within(&self.base_path, &dir.join(SYNC_TMP_FILE))?;
+        let path = ensure_within(&self.base_path, &dir.join(entry.file_name()))?;
+        write_synced(&tmp, data)
+            .await
+            .map_err(|e| ServerError::Internal(format!("Failed to write backup: {e}")))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| ServerError::Internal(format!("Failed to write backup: {e}")))?;
+
+        // Keep the newest older versions that still fit beside this one
+        let mut kept = (1, size);
+        let fits = |kept: (usize, u64), old: &BackupVersion| {
+            kept.0 < self.max_versions
+                && (self.quota_bytes == 0 || kept.1 + old.size <= self.quota_bytes)
+        };
+        let mut keeping = true;
+        for old in &existing {
+            keeping = keeping && fits(kept, old);
+            if keeping {
+                kept = (kept.0 + 1, kept.1 + old.size);
+                continue;
+            }
+            let old_path = ensure_within(&self.base_path, &dir.join(old.file_name()))?;
+            if let Err(e) = fs::remove_file(&old_path).await {
+                debug!(error = %e, version = old.version, "Failed to prune backup version");
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// All versions of `user`, newest first.
+    pub async fn versions(&self, user: &[u8; 32]) -> Result<Vec<BackupVersion>, ServerError> {
+        let dir = self.user_dir(user).await?;
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .map_err(|e| ServerError::Internal(format!("Failed to list backups: {e}")))?;
+
+        let mut versions = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| ServerError::Internal(format!("Failed to list backups: {e}")))?
+        {
+            let size = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+            if let Some(version) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| BackupVersion::parse(name, size))
+            {
+                versions.push(version);
+            }
+        }
+        versions.sort_by_key(|v| std::cmp::Reverse(v.version));
+        Ok(versions)
+    }
+
+    /// The given version, or the newest one.
+    pub async fn load(
+        &self,
+        user: &[u8; 32],
+        version: Option<i64>,
+    ) -> Result<(BackupVersion, Vec<u8>), ServerError> {
+        let versions = self.versions(user).await?;
+        let entry = match version {
+            Some(version) => versions.into_iter().find(|v| v.version == version),
+            None => versions.into_iter().next(),
+        }
+        .ok_or_else(|| ServerError::NotFound("No backup found for this user".into()))?;
+
+        let dir = self.user_dir(user).await?;
+        let path = ensure_within(&self.base_path, &dir.join(entry.file_name()))?;
+        let data = fs::read(&path)
+            .await
+            .map_err(|e| ServerError::Internal(format!("Failed to read backup: {e}")))?;
+        Ok((entry, data))
+    }
+
+    /// Directory of `user`, created on demand. A single `<hex>.enc` left by
+    /// the unversioned layout becomes the first version.
+    async fn user_dir(&self, user: &[u8; 32]) -> Result<PathBuf, ServerError> {
+        let user_hex = hex::encode(user);
+        let dir = ensure_within(&self.base_path, &self.base_path.join(&user_hex))?;
+        if fs::try_exists(&dir).await.unwrap_or(false) {
+            return Ok(dir);
+        }
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| ServerError::Internal(format!("Failed to create backup dir: {e}")))?;
+
+        let legacy = ensure_within(
+            &self.base_path,
+            &self.base_path.join(format!("{user_hex}.enc")),
+        )?;
+        if let Ok(data) = fs::read(&legacy).await {
+            let modified = fs::metadata(&legacy)
+                .await
+                .and_then(|m| m.modified())
+                .map(DateTime::<Utc>::from)
+                .unwrap_or_else(|_| Utc::now());
+            let entry = BackupVersion {
+                version: modified.timestamp_millis(),
+                created_at: modified,
+                size: data.len() as u64,
+                hash: blake3::hash(&data).to_hex().to_string(),
+            };
+            let path = ensure_within(&self.base_path, &dir.join(entry.file_name()))?;
+            fs::rename(&legacy, &path)
+                .await
+                .map_err(|e| ServerError::Internal(format!("Failed to migrate backup: {e}")))?;
+            info!(user = %user_hex, "Migrated unversioned backup");
+        }
+        Ok(dir)
+    }
+}
+
+/// Write `data` to `path` and flush it to disk.
+async fn write_synced(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+    let mut file = fs::File::create(path).await?;
+    file.write_all(data).await?;
+    file.sync_all().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: [u8; 32] = [3u8; 32];
+
+    #[tokio::test]
+    async fn test_keeps_last_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BackupStore::new(dir.path().to_path_buf(), 2, 0)
+            .await
+            .unwrap();
+        assert!(store.load(&USER, None).await.is_err());
+
+        let first = store.store(&USER, b"one").await.unwrap();
+        let second = store.store(&USER, b"two").await.unwrap();
+        let third = store.store(&USER, b"three").await.unwrap();
+        assert!(second.version > first.version);
+
+        let versions = store.versions(&USER).await.unwrap();
+        assert_eq!(versions, vec![third.clone(), second.clone()]);
+        assert_eq!(third.hash, blake3::hash(b"three").to_hex().to_string());
+
+        assert_eq!(store.load(&USER, None).await.unwrap().1, b"three");
+        assert_eq!(
+            store.load(&USER, Some(second.version)).await.unwrap().1,
+            b"two"
+        );
+        assert!(store.load(&USER, Some(first.version)).await.is_err());
+        assert!(store.versions(&[4u8; 32]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_migrates_unversioned_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(format!("{}.enc", hex::encode(USER))),
+            b"old",
+        )
+        .unwrap();
+
+        let store = BackupStore::new(dir.path().to_path_buf(), 5, 0)
+            .await
+            .unwrap();
+        let (version, data) = store.load(&USER, None).await.unwrap();
+        assert_eq!(data, b"old");
+        assert_eq!(version.size, 3);
+    }
+
+    #[tokio::test]
+    async fn test_quota_drops_oldest_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BackupStore::new(dir.path().to_path_buf(), 5, 10)
+            .await
+            .unwrap();
+
+        let first = store.store(&USER, b"1234").await.unwrap();
+        let second = store.store(&USER, b"5678").await.unwrap();
+        // 4 + 4 + 4 would not fit: the oldest makes way
+        let third = store.store(&USER, b"abcd").await.unwrap();
+        assert_eq!(
+            store.versions(&USER).await.unwrap(),
+            vec![third.clone(), second]
+        );
+        assert!(store.load(&USER, Some(first.version)).await.is_err());
+
+        assert!(matches!(
+            store.store(&USER, b"eleven byte").await,
+            Err(ServerError::QuotaExceeded { used: 8, quota: 10 })
+        ));
+        let big = store.store(&USER, b"ten bytes!").await.unwrap();
+        assert_eq!(store.versions(&USER).await.unwrap(), vec![big]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_syncs_and_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BackupStore::new(dir.path().to_path_buf(), 2, 0)
+            .await
+            .unwrap();
+
+        // Left by a crash mid-write
+        let user_dir = dir.path().join(hex::encode(USER));
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join(SYNC_TMP_FILE), b"trunc").unwrap();
+        assert!(store.versions(&USER).await.unwrap().is_empty());
+
+        let syncs = (0..4).map(|_| store.store(&USER, b"same"));
+        let stored: Vec<_> = futures::future::try_join_all(syncs).await.unwrap();
+        let mut ids: Vec<i64> = stored.iter().map(|v| v.version).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+
+        let versions = store.versions(&USER).await.unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(std::fs::read_dir(&user_dir).unwrap().count(), 2);
+    }
+}
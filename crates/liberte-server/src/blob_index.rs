@@ -0,0 +1,397 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::blob_store::BlobId;
+use crate::error::ServerError;
+
+/// One row per reference rather than per blob: content-addressed blobs
+/// are shared by every identity that uploaded them.
+const V001_SQL: &str = "
+CREATE TABLE IF NOT EXISTS blobs (
+    id               TEXT NOT NULL,
+    owner            TEXT,
+    size             INTEGER NOT NULL,
+    created_at       INTEGER NOT NULL,
+    last_accessed_at INTEGER NOT NULL,
+    ttl_secs         INTEGER,
+    expires_at       INTEGER
+);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_blobs_ref ON blobs(id, COALESCE(owner, ''));
+CREATE INDEX IF NOT EXISTS idx_blobs_owner ON blobs(owner);
+CREATE INDEX IF NOT EXISTS idx_blobs_expires_at ON blobs(expires_at);
+";
+
+/// One reference to a stored blob: random-id blobs have exactly one,
+/// content-addressed blobs one per identity that uploaded them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobInfo {
+    pub id: BlobId,
+    /// Identity key of the uploader; `None` for blobs stored before
+    /// ownership was recorded, which only an admin can delete.
+    pub owner: Option<[u8; 32]>,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed_at: DateTime<Utc>,
+    /// Idle time after which the blob is collected; `None` keeps it forever.
+    pub ttl_secs: Option<i64>,
+}
+
+impl BlobInfo {
+    /// `None` also when the expiry lies beyond what a date can hold.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.ttl_secs
+            .and_then(chrono::Duration::try_seconds)
+            .and_then(|ttl| self.last_accessed_at.checked_add_signed(ttl))
+    }
+
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let id: String = row.get(0)?;
+        let owner: Option<String> = row.get(1)?;
+        Ok(Self {
+            id: id.parse().map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e))
+            })?,
+            owner: owner
+                .and_then(|hex| hex::decode(hex).ok())
+                .and_then(|bytes| bytes.try_into().ok()),
+            size: row.get::<_, i64>(2)? as u64,
+            created_at: timestamp(row.get(3)?),
+            last_accessed_at: timestamp(row.get(4)?),
+            ttl_secs: row.get(5)?,
+        })
+    }
+}
+
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
+
+fn index_error(e: rusqlite::Error) -> ServerError {
+    ServerError::BlobStorage(format!("Blob index error: {e}"))
+}
+
+/// `owner` as stored; legacy blobs without one match on the empty string.
+fn owner_key(owner: Option<[u8; 32]>) -> String {
+    owner.map(hex::encode).unwrap_or_default()
+}
+
+const SELECT_COLUMNS: &str =
+    "SELECT id, owner, size, created_at, last_accessed_at, ttl_secs FROM blobs";
+
+/// SQLite index of blob references (owner, size, access times), next to
+/// the blob files. Queries are short, so a plain mutex around the connection does.
+#[derive(Debug, Clone)]
+pub struct BlobIndex {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl BlobIndex {
+    pub fn open(path: &Path) -> Result<Self, ServerError> {
+        let conn = Connection::open(path).map_err(index_error)?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(index_error)?;
+
+        let current: u32 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(index_error)?;
+        if current < 1 {
+            conn.execute_batch(V001_SQL).map_err(index_error)?;
+            conn.pragma_update(None, "user_version", 1)
+                .map_err(index_error)?;
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Insert `blob` unless its owner would go over `quota` bytes (0 means
+    /// no quota). The check and the insert happen under one lock so
+    /// concurrent uploads cannot both slip under the limit. A reference the
+    /// owner already holds is replaced, not counted twice, and returned so
+    /// that it can be put back if the upload fails.
+    pub fn insert_within_quota(
+        &self,
+        blob: &BlobInfo,
+        quota: u64,
+    ) -> Result<Option<BlobInfo>, ServerError> {
+        let conn = self.conn();
+        if let (Some(owner), true) = (blob.owner, quota > 0) {
+            let used: i64 = conn
+                .query_row(
+                    "SELECT COALESCE(SUM(size), 0) FROM blobs WHERE owner = ?1 AND id != ?2",
+                    params![hex::encode(owner), blob.id.to_string()],
+                    |row| row.get(0),
+                )
+                .map_err(index_error)?;
+            let used = used as u64;
+            if used + blob.size > quota {
+                return Err(ServerError::QuotaExceeded { used, quota });
+            }
+        }
+        let previous = conn
+            .query_row(
+                &format!("{SELECT_COLUMNS} WHERE id = ?1 AND COALESCE(owner, '') = ?2"),
+                params![blob.id.to_string(), owner_key(blob.owner)],
+                BlobInfo::from_row,
+            )
+            .optional()
+            .map_err(index_error)?;
+        insert(&conn, blob)?;
+        Ok(previous)
+    }
+
+    pub fn insert(&self, blob: &BlobInfo) -> Result<(), ServerError> {
+        insert(&self.conn(), blob)
+    }
+
+    /// All references to `id`.
+    #[cfg(test)]
+    pub fn refs(&self, id: BlobId) -> Result<Vec<BlobInfo>, ServerError> {
+        self.query(
+            &format!("{SELECT_COLUMNS} WHERE id = ?1"),
+            params![id.to_string()],
+        )
+    }
+
+    /// Record a download, which pushes back the expiry of every reference.
+    pub fn touch(&self, id: BlobId, at: DateTime<Utc>) -> Result<(), ServerError> {
+        self.conn()
+            .execute(
+                "UPDATE blobs SET last_accessed_at = ?1,
+                     expires_at = CASE WHEN ttl_secs IS NULL THEN NULL ELSE ?1 + ttl_secs END
+                 WHERE id = ?2",
+                params![at.timestamp(), id.to_string()],
+            )
+            .map_err(index_error)?;
+        Ok(())
+    }
+
+    /// Forget `id` and all its references.
+    pub fn remove(&self, id: BlobId) -> Result<(), ServerError> {
+        self.conn()
+            .execute("DELETE FROM blobs WHERE id = ?1", params![id.to_string()])
+            .map_err(index_error)?;
+        Ok(())
+    }
+
+    /// Drop `owner`'s reference to `id`. Returns whether there was one.
+    pub fn remove_ref(&self, id: BlobId, owner: Option<[u8; 32]>) -> Result<bool, ServerError> {
+        let removed = self
+            .conn()
+            .execute(
+                "DELETE FROM blobs WHERE id = ?1 AND COALESCE(owner, '') = ?2",
+                params![id.to_string(), owner_key(owner)],
+            )
+            .map_err(index_error)?;
+        Ok(removed > 0)
+    }
+
+    pub fn ref_count(&self, id: BlobId) -> Result<u64, ServerError> {
+        self.conn()
+            .query_row(
+                "SELECT COUNT(*) FROM blobs WHERE id = ?1",
+                params![id.to_string()],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as u64)
+            .map_err(index_error)
+    }
+
+    /// Every reference, oldest first.
+    pub fn list(&self) -> Result<Vec<BlobInfo>, ServerError> {
+        self.query(&format!("{SELECT_COLUMNS} ORDER BY created_at"), [])
+    }
+
+    pub fn ids(&self) -> Result<HashSet<BlobId>, ServerError> {
+        Ok(self.list()?.into_iter().map(|blob| blob.id).collect())
+    }
+
+    /// Bytes stored by `owner`.
+    pub fn usage_of(&self, owner: &[u8; 32]) -> Result<u64, ServerError> {
+        self.conn()
+            .query_row(
+                "SELECT COALESCE(SUM(size), 0) FROM blobs WHERE owner = ?1",
+                params![hex::encode(owner)],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|used| used as u64)
+            .map_err(index_error)
+    }
+
+    /// Number of distinct blobs and their total size, counting shared
+    /// content once.
+    pub fn totals(&self) -> Result<(u64, u64), ServerError> {
+        self.conn()
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(size), 0)
+                 FROM (SELECT MAX(size) AS size FROM blobs GROUP BY id)",
+                [],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+            )
+            .map_err(index_error)
+    }
+
+    /// References whose expiry is at or before `now`.
+    pub fn expired(&self, now: DateTime<Utc>) -> Result<Vec<BlobInfo>, ServerError> {
+        self.query(
+            &format!("{SELECT_COLUMNS} WHERE expires_at IS NOT NULL AND expires_at <= ?1"),
+            params![now.timestamp()],
+        )
+    }
+
+    fn query(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<BlobInfo>, ServerError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(sql).map_err(index_error)?;
+        let rows = stmt
+            .query_map(params, BlobInfo::from_row)
+            .map_err(index_error)?;
+        rows.collect::<Result<_, _>>().map_err(index_error)
+    }
+}
+
+fn insert(conn: &Connection, blob: &BlobInfo) -> Result<(), ServerError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO blobs
+             (id, owner, size, created_at, last_accessed_at, ttl_secs, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            blob.id.to_string(),
+            blob.owner.map(hex::encode),
+            blob.size as i64,
+            blob.created_at.timestamp(),
+            blob.last_accessed_at.timestamp(),
+            blob.ttl_secs,
+            blob.expires_at().map(|at| at.timestamp()),
+        ],
+    )
+    .map_err(index_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    fn blob(owner: [u8; 32], size: u64, ttl_secs: Option<i64>) -> BlobInfo {
+        let now = Utc::now();
+        BlobInfo {
+            id: BlobId::Random(Uuid::new_v4()),
+            owner: Some(owner),
+            size,
+            created_at: now,
+            last_accessed_at: now,
+            ttl_secs,
+        }
+    }
+
+    #[test]
+    fn test_quota_per_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = BlobIndex::open(&dir.path().join("index.db")).unwrap();
+
+        index
+            .insert_within_quota(&blob([1; 32], 60, None), 100)
+            .unwrap();
+        assert!(matches!(
+            index.insert_within_quota(&blob([1; 32], 41, None), 100),
+            Err(ServerError::QuotaExceeded {
+                used: 60,
+                quota: 100
+            })
+        ));
+        index
+            .insert_within_quota(&blob([1; 32], 40, None), 100)
+            .unwrap();
+        index
+            .insert_within_quota(&blob([2; 32], 100, None), 100)
+            .unwrap();
+        index
+            .insert_within_quota(&blob([1; 32], 500, None), 0)
+            .unwrap();
+
+        assert_eq!(index.usage_of(&[1; 32]).unwrap(), 600);
+        assert_eq!(index.totals().unwrap(), (4, 700));
+    }
+
+    #[test]
+    fn test_expiry_follows_last_access() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = BlobIndex::open(&dir.path().join("index.db")).unwrap();
+
+        let idle = blob([1; 32], 1, Some(60));
+        let forever = blob([1; 32], 1, None);
+        index.insert(&idle).unwrap();
+        index.insert(&forever).unwrap();
+
+        let later = Utc::now() + Duration::seconds(120);
+        let expired = index.expired(later).unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, idle.id);
+
+        index.touch(idle.id, later).unwrap();
+        assert!(index.expired(later).unwrap().is_empty());
+        let touched = index.refs(idle.id).unwrap().remove(0);
+        assert_eq!(
+            touched.expires_at().unwrap().timestamp(),
+            later.timestamp() + 60
+        );
+
+        index.remove(idle.id).unwrap();
+        assert!(index.refs(idle.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_shared_content_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = BlobIndex::open(&dir.path().join("index.db")).unwrap();
+
+        let first = BlobInfo {
+            id: BlobId::for_content(b"shared"),
+            ..blob([1; 32], 50, None)
+        };
+        let second = BlobInfo {
+            owner: Some([2; 32]),
+            ..first.clone()
+        };
+        assert_eq!(index.insert_within_quota(&first, 60).unwrap(), None);
+        // Uploading the same content again does not count twice
+        let replaced = index.insert_within_quota(&first, 60).unwrap().unwrap();
+        assert_eq!(replaced.owner, first.owner);
+        index.insert_within_quota(&second, 60).unwrap();
+
+        assert_eq!(index.ref_count(first.id).unwrap(), 2);
+        assert_eq!(index.usage_of(&[1; 32]).unwrap(), 50);
+        assert_eq!(index.totals().unwrap(), (1, 50));
+
+        assert!(index.remove_ref(first.id, Some([1; 32])).unwrap());
+        assert!(!index.remove_ref(first.id, Some([1; 32])).unwrap());
+        assert_eq!(index.ref_count(first.id).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_huge_ttl_never_expires() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = BlobIndex::open(&dir.path().join("index.db")).unwrap();
+
+        let huge = blob([1; 32], 1, Some(100_000_000 * 86_400));
+        assert_eq!(huge.expires_at(), None);
+        assert_eq!(blob([1; 32], 1, Some(i64::MAX)).expires_at(), None);
+        index.insert_within_quota(&huge, 0).unwrap();
+    }
+}
@@ -1,14 +1,26 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
 
+use bytes::Bytes;
+use chrono::Utc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio::fs;
-use tracing::{debug, info};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use crate::blob_index::BlobIndex;
+pub use crate::blob_index::BlobInfo;
+use crate::config::BlobLimits;
 use crate::error::ServerError;
+use crate::storage::{BlobBackend, ObjectMeta, ObjectStream};
 
 /// Verify that a resolved path stays within the expected base directory.
 /// Prevents path traversal attacks.
-fn ensure_within(base: &Path, target: &Path) -> Result<PathBuf, ServerError> {
+pub fn ensure_within(base: &Path, target: &Path) -> Result<PathBuf, ServerError> {
     // Canonicalize base — must succeed (directory created at init).
     let canonical_base = base.canonicalize().map_err(|e| {
         ServerError::Internal(format!(
@@ -44,15 +56,126 @@ fn ensure_within(base: &Path, target: &Path) -> Result<PathBuf, ServerError> {
     Ok(resolved)
 }
 
+/// Name of a stored blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlobId {
+    /// Random id of a blob uploaded through `POST /blob/upload`.
+    Random(Uuid),
+    /// BLAKE3 hash of the ciphertext, for blobs uploaded through
+    /// `POST /blob/content`; identical uploads share one file.
+    Content([u8; 32]),
+}
+
+impl BlobId {
+    pub fn for_content(data: &[u8]) -> Self {
+        Self::Content(*blake3::hash(data).as_bytes())
+    }
+}
+
+impl fmt::Display for BlobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Random(id) => write!(f, "{id}"),
+            Self::Content(hash) => f.write_str(&hex::encode(hash)),
+        }
+    }
+}
+
+/// A 64-character hex hash or a UUID.
+impl FromStr for BlobId {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ServerError::BadRequest("Invalid blob ID format".to_string());
+        if s.len() == 64 {
+            let hash = hex::decode(s).map_err(|_| invalid())?;
+            return Ok(Self::Content(hash.try_into().map_err(|_| invalid())?));
+        }
+        Uuid::parse_str(s).map(Self::Random).map_err(|_| invalid())
+    }
+}
+
+impl Serialize for BlobId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BlobId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Content-addressed blobs, sharded by the first two bytes of their hash:
+/// `content/ab/cd/abcd…`.
+const CONTENT_DIR: &str = "content";
+
+const INDEX_FILE: &str = "index.db";
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct BlobStore {
     base_path: PathBuf,
     max_size: usize,
+    index: BlobIndex,
+    backend: Arc<dyn BlobBackend>,
+    /// Serializes reference changes to a blob with the writes and deletes
+    /// they imply, so a shared file is never removed under a new reference.
+    locks: KeyedLocks<BlobId>,
+}
+
+/// One lock per key (a blob id, an upload session...), held only while
+/// someone uses it.
+#[derive(Debug)]
+pub(crate) struct KeyedLocks<K>(Arc<std::sync::Mutex<HashMap<K, Weak<Mutex<()>>>>>);
+
+impl<K> Default for KeyedLocks<K> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<K> Clone for KeyedLocks<K> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K: Eq + std::hash::Hash> KeyedLocks<K> {
+    pub(crate) async fn lock(&self, key: K) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            locks.retain(|_, lock| lock.strong_count() > 0);
+            match locks.get(&key).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(Mutex::new(()));
+                    locks.insert(key, Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
 }
 
 impl BlobStore {
+    /// A store keeping blobs as files under `base_path`.
+    #[cfg(test)]
     pub async fn new(base_path: PathBuf, max_size: usize) -> Result<Self, ServerError> {
+        let backend = Arc::new(crate::storage::FsBackend::new(base_path.clone()));
+        Self::with_backend(base_path, max_size, backend).await
+    }
+
+    /// A store keeping its index under `base_path` and blobs in `backend`.
+    pub async fn with_backend(
+        base_path: PathBuf,
+        max_size: usize,
+        backend: Arc<dyn BlobBackend>,
+    ) -> Result<Self, ServerError> {
         fs::create_dir_all(&base_path).await.map_err(|e| {
             ServerError::BlobStorage(format!(
                 "Failed to create blob directory '{}': {}",
@@ -61,12 +184,18 @@ impl BlobStore {
             ))
         })?;
 
-        info!(path = %base_path.display(), "Blob store initialized");
-
-        Ok(Self {
+        let index = BlobIndex::open(&base_path.join(INDEX_FILE))?;
+        let store = Self {
             base_path,
             max_size,
-        })
+            index,
+            backend,
+            locks: KeyedLocks::default(),
+        };
+        store.reconcile().await?;
+
+        info!(path = %store.base_path.display(), "Blob store initialized");
+        Ok(store)
     }
 
     #[allow(dead_code)]
@@ -74,134 +203,405 @@ impl BlobStore {
         &self.base_path
     }
 
-    pub async fn store_blob(&self, data: &[u8]) -> Result<Uuid, ServerError> {
-        if data.is_empty() {
-            return Err(ServerError::BlobStorage("Empty blob".to_string()));
+    /// Store `data` as a new blob under a random id, owned by the identity
+    /// key `owner`, within the quota and TTL of its tier.
+    pub async fn store_blob(
+        &self,
+        data: &[u8],
+        owner: &[u8; 32],
+        limits: BlobLimits,
+    ) -> Result<BlobId, ServerError> {
+        self.check_size(data)?;
+
+        let id = BlobId::Random(Uuid::new_v4());
+
+        // Indexed first so the quota check also counts in-flight uploads
+        self.index.insert_within_quota(
+            &new_reference(id, owner, data.len() as u64, limits),
+            limits.quota_bytes,
+        )?;
+        if let Err(e) = self
+            .backend
+            .put(&blob_key(id), Bytes::copy_from_slice(data))
+            .await
+        {
+            let _ = self.index.remove(id);
+            return Err(ServerError::BlobStorage(format!(
+                "Failed to write blob {}: {}",
+                id, e
+            )));
         }
-        if data.len() > self.max_size {
-            return Err(ServerError::BlobTooLarge {
-                size: data.len(),
-                max: self.max_size,
-            });
+
+        debug!(id = %id, size = data.len(), "Stored blob");
+        Ok(id)
+    }
+
+    /// Store `data` under its BLAKE3 hash and add a reference for `owner`.
+    /// Content already stored is not written again; each owner's reference
+    /// counts against its own quota.
+    pub async fn store_content(
+        &self,
+        data: &[u8],
+        owner: &[u8; 32],
+        limits: BlobLimits,
+    ) -> Result<BlobId, ServerError> {
+        self.check_size(data)?;
+
+        let id = BlobId::for_content(data);
+
+        let _blob = self.locks.lock(id).await;
+        let previous = self.index.insert_within_quota(
+            &new_reference(id, owner, data.len() as u64, limits),
+            limits.quota_bytes,
+        )?;
+        let stored = match self.blob_size(id).await {
+            Ok(Some(_)) => {
+                debug!(id = %id, "Deduplicated blob");
+                Ok(())
+            }
+            Ok(None) => {
+                let put = self
+                    .backend
+                    .put(&blob_key(id), Bytes::copy_from_slice(data))
+                    .await;
+                debug!(id = %id, size = data.len(), "Stored blob");
+                put.map_err(|e| {
+                    ServerError::BlobStorage(format!("Failed to write blob {}: {}", id, e))
+                })
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = stored {
+            self.undo_reference(id, owner, previous);
+            return Err(e);
         }
+        Ok(id)
+    }
 
-        let id = Uuid::new_v4();
-        let path = self.safe_blob_path(&id)?;
+    /// Add a reference for `owner` to content-addressed blob `id`, already
+    /// stored by someone, without uploading it again.
+    pub async fn add_reference(
+        &self,
+        id: BlobId,
+        owner: &[u8; 32],
+        limits: BlobLimits,
+    ) -> Result<(), ServerError> {
+        if !matches!(id, BlobId::Content(_)) {
+            return Err(ServerError::BadRequest(
+                "Only content-addressed blobs can be referenced".to_string(),
+            ));
+        }
 
-        fs::write(&path, data)
-            .await
-            .map_err(|e| ServerError::BlobStorage(format!("Failed to write blob {}: {}", id, e)))?;
+        let _blob = self.locks.lock(id).await;
+        let size = self
+            .blob_size(id)
+            .await?
+            .ok_or(ServerError::BlobNotFound(id))?;
+        self.index
+            .insert_within_quota(&new_reference(id, owner, size, limits), limits.quota_bytes)?;
+        Ok(())
+    }
 
-        debug!(id = %id, size = data.len(), "Stored blob");
-        Ok(id)
+    /// Take back the reference a failed store added for `owner`, restoring
+    /// the one it replaced: a retried upload must not cost the owner the
+    /// copy they already had.
+    fn undo_reference(&self, id: BlobId, owner: &[u8; 32], previous: Option<BlobInfo>) {
+        let undone = match previous {
+            Some(previous) => self.index.insert(&previous),
+            None => self.index.remove_ref(id, Some(*owner)).map(|_| ()),
+        };
+        if let Err(e) = undone {
+            warn!(id = %id, error = %e, "Failed to roll back blob reference");
+        }
     }
 
-    pub async fn get_blob(&self, id: Uuid) -> Result<Vec<u8>, ServerError> {
-        let path = self.safe_blob_path(&id)?;
+    /// Move a completed upload at `file`, whose BLAKE3 hash is `id`, into
+    /// content-addressed storage and add a reference for `owner`. If the
+    /// content is already stored the file is simply dropped.
+    pub async fn adopt_content(
+        &self,
+        file: &Path,
+        id: BlobId,
+        size: u64,
+        owner: &[u8; 32],
+        limits: BlobLimits,
+    ) -> Result<(), ServerError> {
+        if size > self.max_size as u64 {
+            return Err(ServerError::BlobTooLarge {
+                size: size as usize,
+                max: self.max_size,
+            });
+        }
 
-        if !path.exists() {
-            return Err(ServerError::BlobNotFound(id));
+        let _blob = self.locks.lock(id).await;
+        let previous = self
+            .index
+            .insert_within_quota(&new_reference(id, owner, size, limits), limits.quota_bytes)?;
+        let moved = match self.blob_size(id).await {
+            Ok(Some(_)) => {
+                debug!(id = %id, "Deduplicated blob");
+                fs::remove_file(file).await.map_err(|e| e.to_string())
+            }
+            Ok(None) => self
+                .backend
+                .put_file(&blob_key(id), file)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = moved {
+            self.undo_reference(id, owner, previous);
+            return Err(ServerError::BlobStorage(format!(
+                "Failed to store blob {}: {}",
+                id, e
+            )));
         }
+        Ok(())
+    }
 
-        let data = fs::read(&path)
+    /// Size of blob `id`, or `None` if it is not stored.
+    pub async fn blob_size(&self, id: BlobId) -> Result<Option<u64>, ServerError> {
+        self.backend
+            .size(&blob_key(id))
             .await
-            .map_err(|e| ServerError::BlobStorage(format!("Failed to read blob {}: {}", id, e)))?;
-
-        debug!(id = %id, size = data.len(), "Retrieved blob");
-        Ok(data)
+            .map_err(|e| ServerError::BlobStorage(format!("Failed to read blob {}: {}", id, e)))
     }
 
-    pub async fn delete_blob(&self, id: Uuid) -> Result<(), ServerError> {
-        let path = self.safe_blob_path(&id)?;
+    /// Stream `len` bytes of blob `id` from `offset`.
+    pub async fn read_blob(
+        &self,
+        id: BlobId,
+        offset: u64,
+        len: u64,
+    ) -> Result<ObjectStream, ServerError> {
+        let stream = match self.backend.read(&blob_key(id), offset, len).await {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ServerError::BlobNotFound(id))
+            }
+            Err(e) => {
+                return Err(ServerError::BlobStorage(format!(
+                    "Failed to open blob {}: {}",
+                    id, e
+                )))
+            }
+        };
+
+        if let Err(e) = self.index.touch(id, Utc::now()) {
+            warn!(id = %id, error = %e, "Failed to record blob access");
+        }
+        Ok(stream)
+    }
 
-        if !path.exists() {
+    /// Delete blob `id` whoever references it.
+    pub async fn delete_blob(&self, id: BlobId) -> Result<(), ServerError> {
+        let _blob = self.locks.lock(id).await;
+        if self.blob_size(id).await?.is_none() {
             return Err(ServerError::BlobNotFound(id));
         }
 
-        fs::remove_file(&path).await.map_err(|e| {
-            ServerError::BlobStorage(format!("Failed to delete blob {}: {}", id, e))
-        })?;
+        self.remove_object(id).await?;
+        self.index.remove(id)?;
 
         debug!(id = %id, "Deleted blob");
         Ok(())
     }
 
-    #[allow(dead_code)]
-    pub async fn list_blobs(&self) -> Result<Vec<Uuid>, ServerError> {
-        let mut ids = Vec::new();
-        let mut entries = fs::read_dir(&self.base_path)
-            .await
-            .map_err(|e| ServerError::BlobStorage(format!("Failed to list blobs: {}", e)))?;
+    /// Drop `owner`'s reference to blob `id`, deleting the blob once no one
+    /// references it.
+    pub async fn release_blob(&self, id: BlobId, owner: &[u8; 32]) -> Result<(), ServerError> {
+        let _blob = self.locks.lock(id).await;
+        if !self.index.remove_ref(id, Some(*owner))? {
+            return Err(if self.index.ref_count(id)? == 0 {
+                ServerError::BlobNotFound(id)
+            } else {
+                ServerError::Forbidden("Only the blob owner can delete it".into())
+            });
+        }
+        if self.index.ref_count(id)? == 0 {
+            self.remove_object(id).await?;
+            debug!(id = %id, "Deleted blob");
+        }
+        Ok(())
+    }
+
+    /// Ids of every blob in the backend.
+    #[cfg(test)]
+    pub async fn list_blobs(&self) -> Result<Vec<BlobId>, ServerError> {
+        Ok(self
+            .list_objects()
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    /// Every blob reference, oldest first.
+    pub async fn list_blob_info(&self) -> Result<Vec<BlobInfo>, ServerError> {
+        self.index.list()
+    }
+
+    /// Bytes referenced by `owner`.
+    pub async fn usage_of(&self, owner: &[u8; 32]) -> Result<u64, ServerError> {
+        self.index.usage_of(owner)
+    }
 
-        while let Some(entry) = entries.next_entry().await.map_err(|e| {
-            ServerError::BlobStorage(format!("Failed to read directory entry: {}", e))
-        })? {
-            if let Some(name) = entry.file_name().to_str() {
-                if let Ok(id) = Uuid::parse_str(name) {
-                    ids.push(id);
+    /// Number of blobs and their total size in bytes, counting shared
+    /// content once.
+    pub async fn usage(&self) -> Result<(u64, u64), ServerError> {
+        self.index.totals()
+    }
+
+    /// Drop references left unread for longer than their TTL, deleting
+    /// blobs no one references anymore. Returns how many references expired.
+    pub async fn collect_garbage(&self) -> Result<usize, ServerError> {
+        let mut removed = 0;
+        for blob in self.index.expired(Utc::now())? {
+            let _blob = self.locks.lock(blob.id).await;
+            self.index.remove_ref(blob.id, blob.owner)?;
+            if self.index.ref_count(blob.id)? == 0 {
+                if let Err(e) = self.remove_object(blob.id).await {
+                    warn!(id = %blob.id, error = %e, "Failed to delete expired blob");
                 }
             }
+            removed += 1;
         }
-
-        Ok(ids)
+        Ok(removed)
     }
 
-    /// Safe blob path that validates against traversal.
-    fn safe_blob_path(&self, id: &Uuid) -> Result<PathBuf, ServerError> {
-        let filename = id.to_string();
-        // Allowlist: UUID strings contain only hex digits and hyphens.
-        // This explicit check satisfies static-analysis path-traversal audits.
-        if !filename.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
-            return Err(ServerError::BadRequest(
-                "Invalid blob ID format".to_string(),
-            ));
+    fn check_size(&self, data: &[u8]) -> Result<(), ServerError> {
+        if data.is_empty() {
+            return Err(ServerError::BlobStorage("Empty blob".to_string()));
         }
-        let path = self.base_path.join(&filename);
-        ensure_within(&self.base_path, &path)
-    }
-
-    /// Build a safe path for a sub-directory file (e.g. backups).
-    pub fn safe_subpath(&self, subdir: &str, filename: &str) -> Result<PathBuf, ServerError> {
-        // Reject any path separator or traversal characters in inputs
-        if subdir.contains('/')
-            || subdir.contains('\\')
-            || subdir.contains("..")
-            || filename.contains('/')
-            || filename.contains('\\')
-            || filename.contains("..")
-        {
-            return Err(ServerError::BadRequest(
-                "Path traversal detected".to_string(),
-            ));
+        if data.len() > self.max_size {
+            return Err(ServerError::BlobTooLarge {
+                size: data.len(),
+                max: self.max_size,
+            });
         }
-        let target = self.base_path.join(subdir).join(filename);
-        ensure_within(&self.base_path, &target)
+        Ok(())
     }
 
-    /// Create a sub-directory safely (validates against traversal).
-    pub async fn ensure_subdir(&self, subdir: &str) -> Result<PathBuf, ServerError> {
-        if subdir.contains('/') || subdir.contains('\\') || subdir.contains("..") {
-            return Err(ServerError::BadRequest(
-                "Path traversal detected".to_string(),
-            ));
+    /// Delete the stored bytes of blob `id`; already gone is not an error.
+    async fn remove_object(&self, id: BlobId) -> Result<(), ServerError> {
+        self.backend
+            .delete(&blob_key(id))
+            .await
+            .map_err(|e| ServerError::BlobStorage(format!("Failed to delete blob {}: {}", id, e)))
+    }
+
+    /// Blobs in the backend; other objects (the index, upload staging
+    /// with the filesystem backend) are skipped.
+    async fn list_objects(&self) -> Result<Vec<(BlobId, ObjectMeta)>, ServerError> {
+        let objects = self
+            .backend
+            .list()
+            .await
+            .map_err(|e| ServerError::BlobStorage(format!("Failed to list blobs: {}", e)))?;
+        Ok(objects
+            .into_iter()
+            .filter_map(|object| Some((blob_id_of_key(&object.key)?, object)))
+            .collect())
+    }
+
+    /// Bring the index in line with the backend: index blobs it does not
+    /// know, such as those stored before the index existed, without an
+    /// owner, and forget entries whose blob is gone.
+    async fn reconcile(&self) -> Result<(), ServerError> {
+        let stored: HashMap<BlobId, ObjectMeta> = self.list_objects().await?.into_iter().collect();
+        let indexed = self.index.ids()?;
+
+        let mut missing = 0;
+        for (id, object) in &stored {
+            if indexed.contains(id) {
+                continue;
+            }
+            let created_at = object.modified.unwrap_or_else(Utc::now);
+            self.index.insert(&BlobInfo {
+                id: *id,
+                owner: None,
+                size: object.size,
+                created_at,
+                last_accessed_at: created_at,
+                ttl_secs: None,
+            })?;
+            missing += 1;
         }
-        let dir_path = self.base_path.join(subdir);
-        let safe_path = ensure_within(&self.base_path, &dir_path)?;
-        fs::create_dir_all(&safe_path).await.map_err(|e| {
-            ServerError::Internal(format!(
-                "Failed to create directory '{}': {e}",
-                safe_path.display()
-            ))
-        })?;
-        Ok(safe_path)
+
+        let stale: Vec<&BlobId> = indexed
+            .iter()
+            .filter(|id| !stored.contains_key(id))
+            .collect();
+        for id in &stale {
+            self.index.remove(**id)?;
+        }
+
+        if missing > 0 || !stale.is_empty() {
+            info!(
+                indexed = missing,
+                forgotten = stale.len(),
+                "Blob index reconciled with storage"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Backend key of blob `id`: the UUID, or the hash sharded under
+/// `content/ab/cd/` so no directory grows too large.
+fn blob_key(id: BlobId) -> String {
+    let name = id.to_string();
+    match id {
+        BlobId::Random(_) => name,
+        BlobId::Content(_) => format!("{CONTENT_DIR}/{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+/// The blob stored under `key`, if it is a blob key at all.
+fn blob_id_of_key(key: &str) -> Option<BlobId> {
+    let name = key.rsplit('/').next()?;
+    let id = name.parse::<BlobId>().ok()?;
+    (blob_key(id) == key).then_some(id)
+}
+
+fn new_reference(id: BlobId, owner: &[u8; 32], size: u64, limits: BlobLimits) -> BlobInfo {
+    let now = Utc::now();
+    BlobInfo {
+        id,
+        owner: Some(*owner),
+        size,
+        created_at: now,
+        last_accessed_at: now,
+        ttl_secs: limits.ttl.map(|ttl| ttl.as_secs() as i64),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::TryStreamExt;
     use tempfile::TempDir;
 
+    const OWNER: [u8; 32] = [7u8; 32];
+    const UNLIMITED: BlobLimits = BlobLimits {
+        quota_bytes: 0,
+        ttl: None,
+    };
+
+    async fn read(store: &BlobStore, id: BlobId) -> Result<Vec<u8>, ServerError> {
+        let size = store
+            .blob_size(id)
+            .await?
+            .ok_or(ServerError::BlobNotFound(id))?;
+        let chunks: Vec<Bytes> = store
+            .read_blob(id, 0, size)
+            .await?
+            .try_collect()
+            .await
+            .unwrap();
+        Ok(chunks.concat())
+    }
+
     async fn test_store() -> (BlobStore, TempDir) {
         let dir = TempDir::new().unwrap();
         let store = BlobStore::new(dir.path().to_path_buf(), 1024 * 1024)
@@ -210,47 +610,230 @@ mod tests {
         (store, dir)
     }
 
+    #[tokio::test]
+    async fn test_blob_locks_are_per_id() {
+        let locks = KeyedLocks::default();
+        let a = BlobId::for_content(b"a");
+        let held = locks.lock(a).await;
+
+        let other = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            locks.lock(BlobId::for_content(b"b")),
+        );
+        drop(other.await.expect("another blob must not wait"));
+        let same = tokio::time::timeout(std::time::Duration::from_millis(50), locks.lock(a));
+        assert!(same.await.is_err());
+
+        drop(held);
+        drop(locks.lock(a).await);
+        drop(locks.lock(BlobId::for_content(b"c")).await);
+        assert_eq!(locks.0.lock().unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn test_store_and_get() {
         let (store, _dir) = test_store().await;
         let data = b"encrypted-blob-data";
 
-        let id = store.store_blob(data).await.unwrap();
-        let retrieved = store.get_blob(id).await.unwrap();
+        let id = store.store_blob(data, &OWNER, UNLIMITED).await.unwrap();
+        let retrieved = read(&store, id).await.unwrap();
         assert_eq!(retrieved, data);
     }
 
     #[tokio::test]
     async fn test_delete() {
         let (store, _dir) = test_store().await;
-        let id = store.store_blob(b"delete-me").await.unwrap();
+        let id = store
+            .store_blob(b"delete-me", &OWNER, UNLIMITED)
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.release_blob(id, &[8u8; 32]).await,
+            Err(ServerError::Forbidden(_))
+        ));
+
+        store.release_blob(id, &OWNER).await.unwrap();
+        assert!(read(&store, id).await.is_err());
+        assert!(matches!(
+            store.release_blob(id, &OWNER).await,
+            Err(ServerError::BlobNotFound(_))
+        ));
 
+        let id = store.store_blob(b"admin", &OWNER, UNLIMITED).await.unwrap();
         store.delete_blob(id).await.unwrap();
-        assert!(store.get_blob(id).await.is_err());
+        assert_eq!(store.usage().await.unwrap(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_content_addressed_dedup() {
+        let (store, dir) = test_store().await;
+        let other = [8u8; 32];
+        let data = b"same-ciphertext";
+
+        let id = store.store_content(data, &OWNER, UNLIMITED).await.unwrap();
+        assert_eq!(id, BlobId::for_content(data));
+        assert_eq!(id.to_string().parse::<BlobId>().unwrap(), id);
+        let hex = id.to_string();
+        assert!(dir
+            .path()
+            .join(CONTENT_DIR)
+            .join(&hex[..2])
+            .join(&hex[2..4])
+            .join(&hex)
+            .exists());
+
+        assert_eq!(
+            store.store_content(data, &other, UNLIMITED).await.unwrap(),
+            id
+        );
+        assert_eq!(store.usage().await.unwrap(), (1, data.len() as u64));
+        assert_eq!(store.usage_of(&other).await.unwrap(), data.len() as u64);
+        assert_eq!(store.list_blobs().await.unwrap(), vec![id]);
+
+        // The file outlives the first owner's reference
+        store.release_blob(id, &OWNER).await.unwrap();
+        assert_eq!(read(&store, id).await.unwrap(), data);
+        store.release_blob(id, &other).await.unwrap();
+        assert_eq!(store.blob_size(id).await.unwrap(), None);
+
+        assert!(matches!(
+            store.add_reference(id, &OWNER, UNLIMITED).await,
+            Err(ServerError::BlobNotFound(_))
+        ));
+        store.store_content(data, &OWNER, UNLIMITED).await.unwrap();
+        store.add_reference(id, &other, UNLIMITED).await.unwrap();
+        assert_eq!(store.index.ref_count(id).unwrap(), 2);
     }
 
     #[tokio::test]
     async fn test_list() {
         let (store, _dir) = test_store().await;
 
-        let id1 = store.store_blob(b"blob-1").await.unwrap();
-        let id2 = store.store_blob(b"blob-2").await.unwrap();
+        let id1 = store
+            .store_blob(b"blob-1", &OWNER, UNLIMITED)
+            .await
+            .unwrap();
+        let id2 = store
+            .store_blob(b"blob-2", &OWNER, UNLIMITED)
+            .await
+            .unwrap();
 
         let ids = store.list_blobs().await.unwrap();
         assert!(ids.contains(&id1));
         assert!(ids.contains(&id2));
+
+        let info = store.list_blob_info().await.unwrap();
+        assert_eq!(info.len(), 2);
+        assert!(info.iter().all(|blob| blob.size == 6));
+        assert_eq!(store.usage().await.unwrap(), (2, 12));
+    }
+
+    #[tokio::test]
+    async fn test_quota_and_expiry() {
+        let (store, _dir) = test_store().await;
+        let limits = BlobLimits {
+            quota_bytes: 10,
+            ttl: Some(std::time::Duration::ZERO),
+        };
+
+        let expiring = store.store_blob(b"123456", &OWNER, limits).await.unwrap();
+        assert!(matches!(
+            store.store_blob(b"123456", &OWNER, limits).await,
+            Err(ServerError::QuotaExceeded { used: 6, quota: 10 })
+        ));
+        let kept = store.store_blob(b"kept", &OWNER, UNLIMITED).await.unwrap();
+
+        assert_eq!(store.collect_garbage().await.unwrap(), 1);
+        assert!(read(&store, expiring).await.is_err());
+        assert_eq!(read(&store, kept).await.unwrap(), b"kept");
+        assert_eq!(store.usage().await.unwrap(), (1, 4));
+    }
+
+    #[tokio::test]
+    async fn test_adopt_uploaded_file() {
+        let (store, dir) = test_store().await;
+        let data = b"chunked-upload";
+        let id = BlobId::for_content(data);
+
+        let upload = dir.path().join("upload.part");
+        std::fs::write(&upload, data).unwrap();
+        store
+            .adopt_content(&upload, id, data.len() as u64, &OWNER, UNLIMITED)
+            .await
+            .unwrap();
+        assert!(!upload.exists());
+        assert_eq!(read(&store, id).await.unwrap(), data);
+
+        // Same content from someone else: the upload is dropped, not stored twice
+        std::fs::write(&upload, data).unwrap();
+        store
+            .adopt_content(&upload, id, data.len() as u64, &[8u8; 32], UNLIMITED)
+            .await
+            .unwrap();
+        assert!(!upload.exists());
+        assert_eq!(store.index.ref_count(id).unwrap(), 2);
+        assert_eq!(store.usage().await.unwrap(), (1, data.len() as u64));
+    }
+
+    #[tokio::test]
+    async fn test_failed_store_keeps_existing_reference() {
+        let (store, dir) = test_store().await;
+        let data = b"stored-once";
+        let id = store.store_content(data, &OWNER, UNLIMITED).await.unwrap();
+        let before = store.index.refs(id).unwrap();
+
+        // A retried finalize whose upload file is already gone
+        let missing = dir.path().join("gone.part");
+        assert!(store
+            .adopt_content(&missing, id, data.len() as u64, &OWNER, UNLIMITED)
+            .await
+            .is_err());
+        assert_eq!(store.index.refs(id).unwrap(), before);
+        assert_eq!(read(&store, id).await.unwrap(), data);
+
+        // Someone without a reference yet is not left with one
+        assert!(store
+            .adopt_content(&missing, id, data.len() as u64, &[8u8; 32], UNLIMITED)
+            .await
+            .is_err());
+        assert_eq!(store.index.ref_count(id).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_indexes_existing_files() {
+        let dir = TempDir::new().unwrap();
+        let legacy = Uuid::new_v4();
+        std::fs::write(dir.path().join(legacy.to_string()), b"legacy").unwrap();
+
+        let store = BlobStore::new(dir.path().to_path_buf(), 1024)
+            .await
+            .unwrap();
+        let refs = store.index.refs(BlobId::Random(legacy)).unwrap();
+        assert_eq!(refs[0].owner, None);
+        assert_eq!(store.usage().await.unwrap(), (1, 6));
+        // Only an admin can delete a blob without an owner
+        assert!(matches!(
+            store.release_blob(BlobId::Random(legacy), &OWNER).await,
+            Err(ServerError::Forbidden(_))
+        ));
+
+        std::fs::remove_file(dir.path().join(legacy.to_string())).unwrap();
+        let store = BlobStore::new(dir.path().to_path_buf(), 1024)
+            .await
+            .unwrap();
+        assert_eq!(store.usage().await.unwrap(), (0, 0));
     }
 
     #[tokio::test]
     async fn test_not_found() {
         let (store, _dir) = test_store().await;
-        let missing = Uuid::new_v4();
-        assert!(store.get_blob(missing).await.is_err());
+        let missing = BlobId::Random(Uuid::new_v4());
+        assert!(read(&store, missing).await.is_err());
     }
 
     #[tokio::test]
     async fn test_empty_blob_rejected() {
         let (store, _dir) = test_store().await;
-        assert!(store.store_blob(b"").await.is_err());
+        assert!(store.store_blob(b"", &OWNER, UNLIMITED).await.is_err());
     }
 }
@@ -1,18 +1,241 @@
-use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
+use ipnet::IpNet;
+use libp2p::{Multiaddr, PeerId};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest blob TTL accepted, about a century.
+pub const MAX_TTL_DAYS: u32 = 36_500;
+
+/// Largest request body, and so the largest blob uploaded in one request;
+/// bigger blobs go through resumable upload sessions.
+pub const MAX_REQUEST_BODY_SIZE: usize = 50 * 1024 * 1024;
+
+/// Blob storage limits of one account tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobLimits {
+    /// Total bytes one identity may store; 0 for no limit.
+    pub quota_bytes: u64,
+    /// How long a blob may go unread before it is collected; `None` keeps
+    /// it forever.
+    pub ttl: Option<Duration>,
+}
+
+/// Token bucket of one rate-limit class.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Sustained requests per second.
+    pub per_sec: f64,
+    /// Requests allowed in a burst above the sustained rate.
+    pub burst: f64,
+}
+
+/// HTTP rate limits per route class, applied per client IP.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimits {
+    pub default: RateLimit,
+    /// `/health`, `/info` and `/metrics`.
+    pub info: RateLimit,
+    /// Blob writes.
+    pub upload: RateLimit,
+    pub backup: RateLimit,
+    pub admin: RateLimit,
+    /// Also charge signed requests to the signing identity, on top of the
+    /// IP, so one identity cannot spread its requests over many addresses.
+    pub per_identity: bool,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let limit = |per_sec, burst| RateLimit { per_sec, burst };
+        Self {
+            default: limit(10.0, 30.0),
+            info: limit(20.0, 60.0),
+            upload: limit(5.0, 20.0),
+            backup: limit(1.0, 10.0),
+            admin: limit(5.0, 30.0),
+            per_identity: false,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Each class with the prefix of its env vars.
+    fn by_prefix(&self) -> [(&'static str, RateLimit); 5] {
+        [
+            ("RATE_LIMIT", self.default),
+            ("RATE_LIMIT_INFO", self.info),
+            ("RATE_LIMIT_UPLOAD", self.upload),
+            ("RATE_LIMIT_BACKUP", self.backup),
+            ("RATE_LIMIT_ADMIN", self.admin),
+        ]
+    }
+}
+
+/// Where blob contents are stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobBackendConfig {
+    /// Files under `blob_storage_path`.
+    Filesystem,
+    /// An S3-compatible bucket.
+    S3(S3Config),
+}
+
+/// Connection to an S3-compatible object store (AWS, MinIO, Garage…).
+#[derive(Clone, PartialEq, Eq)]
+pub struct S3Config {
+    /// Base URL; empty for AWS in `region`.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Prepended to every object key, e.g. `liberte/`.
+    pub prefix: String,
+    /// `endpoint/bucket/key` rather than `bucket.endpoint/key`; most
+    /// self-hosted servers only support the former.
+    pub path_style: bool,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            region: "us-east-1".to_string(),
+            bucket: String::new(),
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+            prefix: String::new(),
+            path_style: true,
+        }
+    }
+}
+
+impl std::fmt::Debug for S3Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("region", &self.region)
+            .field("bucket", &self.bucket)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field("prefix", &self.prefix)
+            .field("path_style", &self.path_style)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
 pub struct ServerConfig {
     pub listen_addr: String,
     pub http_addr: SocketAddr,
+    /// Blob index and upload staging, and the blobs themselves with the
+    /// filesystem backend.
     pub blob_storage_path: PathBuf,
+    pub blob_backend: BlobBackendConfig,
     pub payment_server_pubkey: [u8; 32],
+    /// Largest blob, uploaded in chunks beyond [`MAX_REQUEST_BODY_SIZE`].
     pub max_blob_size: usize,
+    /// Largest chunk of a resumable upload.
+    pub max_chunk_size: usize,
     pub instance_name: String,
     pub premium_required: bool,
     pub admin_token: Option<String>,
     pub registration_open: bool,
     pub max_peers: usize,
+    /// Pre-shared swarm key file; when set the relay only accepts peers
+    /// holding the same key and must listen on TCP.
+    pub swarm_key_path: Option<PathBuf>,
+    /// Relay keypair file, created on first start so the peer id survives restarts.
+    pub relay_key_path: PathBuf,
+    /// Publicly reachable relay addresses (e.g. behind NAT or a load
+    /// balancer), announced to peers and listed in `/info`.
+    pub external_addrs: Vec<Multiaddr>,
+    /// Peers always admitted to the relay, bypassing registration and premium checks.
+    pub relay_allowlist: Vec<PeerId>,
+    /// Peers never admitted to the relay.
+    pub relay_denylist: Vec<PeerId>,
+    /// IPs refused on the relay and the HTTP API.
+    pub banned_ips: Vec<IpAddr>,
+    pub relay_max_circuits: usize,
+    pub relay_max_circuit_duration: Duration,
+    /// Bytes relayed per circuit (both directions) before it is closed.
+    pub relay_max_circuit_bytes: u64,
+    /// Append-only JSON lines log of admin actions.
+    pub audit_log_path: PathBuf,
+    /// SQLite database of admin grants, revocations and verified premium
+    /// tokens.
+    pub premium_db_path: PathBuf,
+    /// Backup versions kept per user.
+    pub backup_versions: usize,
+    /// Bytes of backups kept per user across all versions (0 for no limit);
+    /// the oldest versions make way for a new one.
+    pub backup_quota_bytes: u64,
+    pub rate_limits: RateLimits,
+    /// Reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers are
+    /// believed; requests from anywhere else are attributed to their peer
+    /// address.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Participants allowed in one SFU room.
+    pub sfu_max_participants: usize,
+    /// Largest encrypted media frame accepted from an SFU participant.
+    pub sfu_max_frame_size: usize,
+    /// SFU rooms without a join or frame for this long are closed.
+    pub sfu_room_idle: Duration,
+    /// Blob limits of identities without a premium subscription.
+    pub blob_limits_free: BlobLimits,
+    /// Blob limits of premium identities.
+    pub blob_limits_premium: BlobLimits,
+}
+
+impl std::fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerConfig")
+            .field("listen_addr", &self.listen_addr)
+            .field("http_addr", &self.http_addr)
+            .field("blob_storage_path", &self.blob_storage_path)
+            .field("blob_backend", &self.blob_backend)
+            .field("payment_server_pubkey", &self.payment_server_pubkey)
+            .field("max_blob_size", &self.max_blob_size)
+            .field("max_chunk_size", &self.max_chunk_size)
+            .field("instance_name", &self.instance_name)
+            .field("premium_required", &self.premium_required)
+            .field(
+                "admin_token",
+                &self.admin_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("registration_open", &self.registration_open)
+            .field("max_peers", &self.max_peers)
+            .field("swarm_key_path", &self.swarm_key_path)
+            .field("relay_key_path", &self.relay_key_path)
+            .field("external_addrs", &self.external_addrs)
+            .field("relay_allowlist", &self.relay_allowlist)
+            .field("relay_denylist", &self.relay_denylist)
+            .field("banned_ips", &self.banned_ips)
+            .field("relay_max_circuits", &self.relay_max_circuits)
+            .field(
+                "relay_max_circuit_duration",
+                &self.relay_max_circuit_duration,
+            )
+            .field("relay_max_circuit_bytes", &self.relay_max_circuit_bytes)
+            .field("audit_log_path", &self.audit_log_path)
+            .field("premium_db_path", &self.premium_db_path)
+            .field("backup_versions", &self.backup_versions)
+            .field("backup_quota_bytes", &self.backup_quota_bytes)
+            .field("rate_limits", &self.rate_limits)
+            .field("trusted_proxies", &self.trusted_proxies)
+            .field("sfu_max_participants", &self.sfu_max_participants)
+            .field("sfu_max_frame_size", &self.sfu_max_frame_size)
+            .field("sfu_room_idle", &self.sfu_room_idle)
+            .field("blob_limits_free", &self.blob_limits_free)
+            .field("blob_limits_premium", &self.blob_limits_premium)
+            .finish()
+    }
 }
 
 impl Default for ServerConfig {
@@ -21,80 +244,570 @@ impl Default for ServerConfig {
             listen_addr: "/ip4/0.0.0.0/udp/4001/quic-v1".to_string(),
             http_addr: ([0, 0, 0, 0], 8080).into(),
             blob_storage_path: PathBuf::from("./blobs"),
+            blob_backend: BlobBackendConfig::Filesystem,
             payment_server_pubkey: [0u8; 32],
-            max_blob_size: 50 * 1024 * 1024,
+            max_blob_size: 1024 * 1024 * 1024,
+            max_chunk_size: 8 * 1024 * 1024,
             instance_name: "Liberte Node".to_string(),
             premium_required: true,
             admin_token: None,
             registration_open: true,
             max_peers: 0,
+            swarm_key_path: None,
+            relay_key_path: PathBuf::from("./relay.key"),
+            external_addrs: Vec::new(),
+            relay_allowlist: Vec::new(),
+            relay_denylist: Vec::new(),
+            banned_ips: Vec::new(),
+            relay_max_circuits: 16,
+            relay_max_circuit_duration: Duration::from_secs(120),
+            relay_max_circuit_bytes: 1 << 17,
+            audit_log_path: PathBuf::from("./audit.log"),
+            premium_db_path: PathBuf::from("./premium.db"),
+            backup_versions: 5,
+            backup_quota_bytes: 64 * 1024 * 1024,
+            rate_limits: RateLimits::default(),
+            trusted_proxies: Vec::new(),
+            sfu_max_participants: 16,
+            sfu_max_frame_size: 256 * 1024,
+            sfu_room_idle: Duration::from_secs(300),
+            blob_limits_free: BlobLimits {
+                quota_bytes: 100 * 1024 * 1024,
+                ttl: Some(30 * DAY),
+            },
+            blob_limits_premium: BlobLimits {
+                quota_bytes: 5 * 1024 * 1024 * 1024,
+                ttl: Some(365 * DAY),
+            },
         }
     }
 }
 
-impl ServerConfig {
-    pub fn from_env() -> Self {
-        let mut config = Self::default();
+/// Every setting: its key in the TOML file (`section.key`) and the env
+/// var overriding it. The file schema is exactly this list.
+const SETTINGS: &[(&str, &str)] = &[
+    ("server.instance_name", "INSTANCE_NAME"),
+    ("server.http_addr", "HTTP_ADDR"),
+    ("server.admin_token", "ADMIN_TOKEN"),
+    ("server.audit_log_path", "AUDIT_LOG_PATH"),
+    ("server.premium_db_path", "PREMIUM_DB_PATH"),
+    ("admission.premium_required", "PREMIUM_REQUIRED"),
+    ("admission.registration_open", "REGISTRATION_OPEN"),
+    ("admission.payment_server_pubkey", "PAYMENT_SERVER_PUBKEY"),
+    ("admission.max_peers", "MAX_PEERS"),
+    ("relay.listen_addr", "LISTEN_ADDR"),
+    ("relay.external_addrs", "EXTERNAL_ADDRS"),
+    ("relay.key_path", "RELAY_KEY_PATH"),
+    ("relay.swarm_key_path", "SWARM_KEY_PATH"),
+    ("relay.allowlist", "RELAY_ALLOWLIST"),
+    ("relay.denylist", "RELAY_DENYLIST"),
+    ("relay.banned_ips", "BANNED_IPS"),
+    ("relay.max_circuits", "RELAY_MAX_CIRCUITS"),
+    (
+        "relay.max_circuit_duration_secs",
+        "RELAY_MAX_CIRCUIT_DURATION_SECS",
+    ),
+    ("relay.max_circuit_bytes", "RELAY_MAX_CIRCUIT_BYTES"),
+    ("rate_limit.requests_per_sec", "RATE_LIMIT_PER_SEC"),
+    ("rate_limit.burst", "RATE_LIMIT_BURST"),
+    ("rate_limit.info_per_sec", "RATE_LIMIT_INFO_PER_SEC"),
+    ("rate_limit.info_burst", "RATE_LIMIT_INFO_BURST"),
+    ("rate_limit.upload_per_sec", "RATE_LIMIT_UPLOAD_PER_SEC"),
+    ("rate_limit.upload_burst", "RATE_LIMIT_UPLOAD_BURST"),
+    ("rate_limit.backup_per_sec", "RATE_LIMIT_BACKUP_PER_SEC"),
+    ("rate_limit.backup_burst", "RATE_LIMIT_BACKUP_BURST"),
+    ("rate_limit.admin_per_sec", "RATE_LIMIT_ADMIN_PER_SEC"),
+    ("rate_limit.admin_burst", "RATE_LIMIT_ADMIN_BURST"),
+    ("rate_limit.per_identity", "RATE_LIMIT_PER_IDENTITY"),
+    ("rate_limit.trusted_proxies", "TRUSTED_PROXIES"),
+    ("sfu.max_participants", "SFU_MAX_PARTICIPANTS"),
+    ("sfu.max_frame_bytes", "SFU_MAX_FRAME_BYTES"),
+    ("sfu.room_idle_secs", "SFU_ROOM_IDLE_SECS"),
+    ("blobs.storage_path", "BLOB_STORAGE_PATH"),
+    ("blobs.backend", "BLOB_BACKEND"),
+    ("blobs.max_blob_size", "MAX_BLOB_SIZE"),
+    ("blobs.upload_chunk_size", "UPLOAD_CHUNK_SIZE"),
+    ("blobs.quota_bytes", "BLOB_QUOTA_BYTES"),
+    ("blobs.ttl_days", "BLOB_TTL_DAYS"),
+    ("blobs.quota_premium_bytes", "BLOB_QUOTA_PREMIUM_BYTES"),
+    ("blobs.ttl_premium_days", "BLOB_TTL_PREMIUM_DAYS"),
+    ("blobs.s3.endpoint", "S3_ENDPOINT"),
+    ("blobs.s3.region", "S3_REGION"),
+    ("blobs.s3.bucket", "S3_BUCKET"),
+    ("blobs.s3.access_key_id", "S3_ACCESS_KEY_ID"),
+    ("blobs.s3.secret_access_key", "S3_SECRET_ACCESS_KEY"),
+    ("blobs.s3.prefix", "S3_PREFIX"),
+    ("blobs.s3.path_style", "S3_PATH_STYLE"),
+    ("backups.versions", "BACKUP_VERSIONS"),
+    ("backups.quota_bytes", "BACKUP_QUOTA_BYTES"),
+];
 
-        if let Ok(addr) = std::env::var("LISTEN_ADDR") {
-            config.listen_addr = addr;
+/// Path of the config file: `--config <path>` on the command line, else
+/// `LIBERTE_CONFIG`. Without either, settings come from env vars alone.
+pub fn config_path() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
         }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    std::env::var("LIBERTE_CONFIG")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
 
-        if let Ok(addr) = std::env::var("HTTP_ADDR") {
-            if let Ok(parsed) = addr.parse::<SocketAddr>() {
-                config.http_addr = parsed;
-            } else {
-                tracing::warn!(
-                    value = %addr,
-                    "Invalid HTTP_ADDR, using default"
-                );
+impl ServerConfig {
+    /// Defaults, overridden by the TOML file at `path`, overridden by env
+    /// vars. Every invalid value is reported, none is silently ignored.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let file = match path {
+            Some(path) => Some((
+                path,
+                std::fs::read_to_string(path).map_err(|e| {
+                    anyhow::anyhow!("Cannot read config file '{}': {e}", path.display())
+                })?,
+            )),
+            None => None,
+        };
+        Self::from_sources(
+            file.as_ref().map(|(path, text)| (*path, text.as_str())),
+            |name| std::env::var(name).ok(),
+        )
+    }
+
+    fn from_sources(
+        file: Option<(&Path, &str)>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Self> {
+        let mut settings = Settings::default();
+        if let Some((path, text)) = file {
+            settings.read_file(path, text)?;
+        }
+        for (_, name) in SETTINGS {
+            // Empty counts as unset, as compose passes `${VAR:-}` through
+            if let Some(value) = env(name).filter(|v| !v.is_empty()) {
+                settings.values.insert(name, (value, name.to_string()));
             }
         }
 
-        if let Ok(path) = std::env::var("BLOB_STORAGE_PATH") {
-            config.blob_storage_path = PathBuf::from(path);
+        let config = settings.build();
+        let mut errors = settings.errors;
+        errors.extend(config.validate());
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            anyhow::bail!("Invalid configuration:\n  - {}", errors.join("\n  - "))
         }
+    }
 
-        if let Ok(hex_key) = std::env::var("PAYMENT_SERVER_PUBKEY") {
-            match parse_hex_pubkey(&hex_key) {
-                Ok(key) => config.payment_server_pubkey = key,
-                Err(e) => {
-                    tracing::warn!(
-                        error = %e,
-                        "Invalid PAYMENT_SERVER_PUBKEY, using default (dev-only)"
-                    );
-                }
+    /// Problems no single value shows on its own.
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.instance_name.trim().is_empty() {
+            errors.push("INSTANCE_NAME must not be empty".to_string());
+        }
+        if self
+            .admin_token
+            .as_ref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            errors.push(
+                "ADMIN_TOKEN must not be empty (leave it unset to disable the admin API)"
+                    .to_string(),
+            );
+        }
+        if let Err(e) = self.listen_addr.parse::<Multiaddr>() {
+            errors.push(format!("LISTEN_ADDR: invalid multiaddr: {e}"));
+        }
+        if self.max_chunk_size == 0 || self.max_chunk_size > MAX_REQUEST_BODY_SIZE {
+            errors.push(format!(
+                "UPLOAD_CHUNK_SIZE must be between 1 and {MAX_REQUEST_BODY_SIZE}"
+            ));
+        }
+        if self.max_blob_size == 0 {
+            errors.push("MAX_BLOB_SIZE must be positive".to_string());
+        }
+        if self.backup_versions == 0 {
+            errors.push("BACKUP_VERSIONS must be at least 1".to_string());
+        }
+        for (prefix, limit) in self.rate_limits.by_prefix() {
+            if !(limit.per_sec > 0.0 && limit.per_sec.is_finite()) {
+                errors.push(format!("{prefix}_PER_SEC must be positive"));
+            }
+            if !(limit.burst >= 1.0 && limit.burst.is_finite()) {
+                errors.push(format!("{prefix}_BURST must be at least 1"));
             }
         }
-
-        if let Ok(name) = std::env::var("INSTANCE_NAME") {
-            config.instance_name = name;
+        if self.sfu_max_participants < 2 {
+            errors.push("SFU_MAX_PARTICIPANTS must be at least 2".to_string());
+        }
+        if self.sfu_max_frame_size == 0 {
+            errors.push("SFU_MAX_FRAME_BYTES must be positive".to_string());
+        }
+        if self.sfu_room_idle.is_zero() {
+            errors.push("SFU_ROOM_IDLE_SECS must be positive".to_string());
+        }
+        if let BlobBackendConfig::S3(s3) = &self.blob_backend {
+            if s3.bucket.is_empty() {
+                errors.push("S3_BUCKET is required with BLOB_BACKEND=s3".to_string());
+            }
+            if s3.access_key_id.is_empty() || s3.secret_access_key.is_empty() {
+                errors.push(
+                    "S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY are required with BLOB_BACKEND=s3"
+                        .to_string(),
+                );
+            }
         }
+        if let Some(peer) = self
+            .relay_allowlist
+            .iter()
+            .find(|peer| self.relay_denylist.contains(peer))
+        {
+            errors.push(format!(
+                "{peer} is in both RELAY_ALLOWLIST and RELAY_DENYLIST"
+            ));
+        }
+        errors
+    }
+
+    /// Settings that differ from `self` in `new` but only take effect on
+    /// restart, by env var name.
+    pub fn restart_required(&self, new: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let mut check = |name, differs: bool| {
+            if differs {
+                changed.push(name);
+            }
+        };
+        check("LISTEN_ADDR", self.listen_addr != new.listen_addr);
+        check("HTTP_ADDR", self.http_addr != new.http_addr);
+        check("EXTERNAL_ADDRS", self.external_addrs != new.external_addrs);
+        check(
+            "BLOB_STORAGE_PATH",
+            self.blob_storage_path != new.blob_storage_path,
+        );
+        check("BLOB_BACKEND", self.blob_backend != new.blob_backend);
+        check(
+            "PAYMENT_SERVER_PUBKEY",
+            self.payment_server_pubkey != new.payment_server_pubkey,
+        );
+        check("MAX_BLOB_SIZE", self.max_blob_size != new.max_blob_size);
+        check(
+            "UPLOAD_CHUNK_SIZE",
+            self.max_chunk_size != new.max_chunk_size,
+        );
+        check("INSTANCE_NAME", self.instance_name != new.instance_name);
+        check("ADMIN_TOKEN", self.admin_token != new.admin_token);
+        check("MAX_PEERS", self.max_peers != new.max_peers);
+        check("SWARM_KEY_PATH", self.swarm_key_path != new.swarm_key_path);
+        check("RELAY_KEY_PATH", self.relay_key_path != new.relay_key_path);
+        check(
+            "RELAY_MAX_CIRCUITS",
+            self.relay_max_circuits != new.relay_max_circuits,
+        );
+        check(
+            "RELAY_MAX_CIRCUIT_DURATION_SECS",
+            self.relay_max_circuit_duration != new.relay_max_circuit_duration,
+        );
+        check(
+            "RELAY_MAX_CIRCUIT_BYTES",
+            self.relay_max_circuit_bytes != new.relay_max_circuit_bytes,
+        );
+        check("AUDIT_LOG_PATH", self.audit_log_path != new.audit_log_path);
+        check(
+            "PREMIUM_DB_PATH",
+            self.premium_db_path != new.premium_db_path,
+        );
+        check(
+            "BACKUP_VERSIONS",
+            self.backup_versions != new.backup_versions,
+        );
+        check(
+            "BACKUP_QUOTA_BYTES",
+            self.backup_quota_bytes != new.backup_quota_bytes,
+        );
+        check(
+            "SFU_MAX_PARTICIPANTS",
+            self.sfu_max_participants != new.sfu_max_participants,
+        );
+        check(
+            "SFU_MAX_FRAME_BYTES",
+            self.sfu_max_frame_size != new.sfu_max_frame_size,
+        );
+        check(
+            "SFU_ROOM_IDLE_SECS",
+            self.sfu_room_idle != new.sfu_room_idle,
+        );
+        check(
+            "BLOB_QUOTA_BYTES / BLOB_TTL_DAYS",
+            self.blob_limits_free != new.blob_limits_free,
+        );
+        check(
+            "BLOB_QUOTA_PREMIUM_BYTES / BLOB_TTL_PREMIUM_DAYS",
+            self.blob_limits_premium != new.blob_limits_premium,
+        );
+        changed
+    }
+}
 
-        if let Ok(val) = std::env::var("PREMIUM_REQUIRED") {
-            config.premium_required = val != "false" && val != "0";
+/// Raw values by env var name, each with where it came from for error
+/// messages, and the errors met while parsing them.
+#[derive(Default)]
+struct Settings {
+    values: HashMap<&'static str, (String, String)>,
+    errors: Vec<String>,
+}
+
+impl Settings {
+    fn read_file(&mut self, path: &Path, text: &str) -> anyhow::Result<()> {
+        let table: toml::Table = toml::from_str(text)
+            .map_err(|e| anyhow::anyhow!("Invalid config file '{}': {e}", path.display()))?;
+        self.read_table(path, "", &table);
+        Ok(())
+    }
+
+    fn read_table(&mut self, path: &Path, prefix: &str, table: &toml::Table) {
+        for (key, value) in table {
+            let key = format!("{prefix}{key}");
+            let origin = format!("{key} in {}", path.display());
+            if let toml::Value::Table(section) = value {
+                if SETTINGS
+                    .iter()
+                    .any(|(k, _)| k.starts_with(&format!("{key}.")))
+                {
+                    self.read_table(path, &format!("{key}."), section);
+                } else {
+                    self.errors.push(format!("{origin}: unknown section"));
+                }
+                continue;
+            }
+            let Some((_, name)) = SETTINGS.iter().find(|(k, _)| *k == key) else {
+                self.errors.push(format!("{origin}: unknown setting"));
+                continue;
+            };
+            match toml_to_string(value) {
+                Some(raw) => {
+                    self.values.insert(name, (raw, origin));
+                }
+                None => self.errors.push(format!(
+                    "{origin}: expected a string, number, boolean or list"
+                )),
+            }
         }
+    }
 
-        if let Ok(token) = std::env::var("ADMIN_TOKEN") {
-            if !token.is_empty() {
-                config.admin_token = Some(token);
+    /// `name` parsed with `parse`, recording an error if it does not parse.
+    fn parse_with<T>(
+        &mut self,
+        name: &str,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        let (raw, origin) = self.values.get(name)?;
+        match parse(raw.trim()) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.errors.push(format!("{origin}: {e}"));
+                None
             }
         }
+    }
+
+    fn get<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.parse_with(name, |raw| {
+            raw.parse()
+                .map_err(|e| format!("invalid value '{raw}': {e}"))
+        })
+    }
+
+    fn string(&mut self, name: &str) -> Option<String> {
+        self.parse_with(name, |raw| Ok(raw.to_string()))
+    }
+
+    fn flag(&mut self, name: &str) -> Option<bool> {
+        self.parse_with(name, |raw| match raw.to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(format!("expected true or false, got '{raw}'")),
+        })
+    }
 
-        if let Ok(val) = std::env::var("REGISTRATION_OPEN") {
-            config.registration_open = val != "false" && val != "0";
+    fn list<T>(&mut self, name: &str) -> Option<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.parse_with(name, parse_list)
+    }
+
+    /// Days, 0 meaning never.
+    fn ttl_days(&mut self, name: &str) -> Option<Option<Duration>> {
+        self.parse_with(name, |raw| match raw.parse::<u32>() {
+            Ok(days) if days <= MAX_TTL_DAYS => Ok((days > 0).then(|| days * DAY)),
+            Ok(_) => Err(format!("must be at most {MAX_TTL_DAYS} days, got {raw}")),
+            Err(e) => Err(format!("invalid value '{raw}': {e}")),
+        })
+    }
+
+    fn build(&mut self) -> ServerConfig {
+        let mut config = ServerConfig::default();
+        let mut s3 = S3Config::default();
+
+        macro_rules! set {
+            ($field:expr, $value:expr) => {
+                if let Some(value) = $value {
+                    $field = value;
+                }
+            };
         }
 
-        if let Ok(val) = std::env::var("MAX_PEERS") {
-            if let Ok(n) = val.parse::<usize>() {
-                config.max_peers = n;
-            }
+        set!(config.instance_name, self.string("INSTANCE_NAME"));
+        set!(config.http_addr, self.get("HTTP_ADDR"));
+        config.admin_token = self.string("ADMIN_TOKEN");
+        set!(config.audit_log_path, self.get("AUDIT_LOG_PATH"));
+        set!(config.premium_db_path, self.get("PREMIUM_DB_PATH"));
+
+        set!(config.premium_required, self.flag("PREMIUM_REQUIRED"));
+        set!(config.registration_open, self.flag("REGISTRATION_OPEN"));
+        set!(
+            config.payment_server_pubkey,
+            self.parse_with("PAYMENT_SERVER_PUBKEY", parse_hex_pubkey)
+        );
+        set!(config.max_peers, self.get("MAX_PEERS"));
+
+        set!(config.listen_addr, self.string("LISTEN_ADDR"));
+        set!(config.external_addrs, self.list("EXTERNAL_ADDRS"));
+        set!(config.relay_key_path, self.get("RELAY_KEY_PATH"));
+        config.swarm_key_path = self.get("SWARM_KEY_PATH");
+        set!(config.relay_allowlist, self.list("RELAY_ALLOWLIST"));
+        set!(config.relay_denylist, self.list("RELAY_DENYLIST"));
+        set!(config.banned_ips, self.list("BANNED_IPS"));
+        set!(config.relay_max_circuits, self.get("RELAY_MAX_CIRCUITS"));
+        set!(
+            config.relay_max_circuit_duration,
+            self.get("RELAY_MAX_CIRCUIT_DURATION_SECS")
+                .map(Duration::from_secs)
+        );
+        set!(
+            config.relay_max_circuit_bytes,
+            self.get("RELAY_MAX_CIRCUIT_BYTES")
+        );
+
+        let limits = &mut config.rate_limits;
+        for (prefix, limit) in [
+            ("RATE_LIMIT", &mut limits.default),
+            ("RATE_LIMIT_INFO", &mut limits.info),
+            ("RATE_LIMIT_UPLOAD", &mut limits.upload),
+            ("RATE_LIMIT_BACKUP", &mut limits.backup),
+            ("RATE_LIMIT_ADMIN", &mut limits.admin),
+        ] {
+            set!(limit.per_sec, self.get(&format!("{prefix}_PER_SEC")));
+            set!(limit.burst, self.get(&format!("{prefix}_BURST")));
         }
+        set!(limits.per_identity, self.flag("RATE_LIMIT_PER_IDENTITY"));
+        set!(
+            config.trusted_proxies,
+            self.parse_with("TRUSTED_PROXIES", parse_cidrs)
+        );
+
+        set!(
+            config.sfu_max_participants,
+            self.get("SFU_MAX_PARTICIPANTS")
+        );
+        set!(config.sfu_max_frame_size, self.get("SFU_MAX_FRAME_BYTES"));
+        set!(
+            config.sfu_room_idle,
+            self.get("SFU_ROOM_IDLE_SECS").map(Duration::from_secs)
+        );
+
+        set!(config.blob_storage_path, self.get("BLOB_STORAGE_PATH"));
+        set!(config.max_blob_size, self.get("MAX_BLOB_SIZE"));
+        set!(config.max_chunk_size, self.get("UPLOAD_CHUNK_SIZE"));
+        set!(
+            config.blob_limits_free.quota_bytes,
+            self.get("BLOB_QUOTA_BYTES")
+        );
+        set!(config.blob_limits_free.ttl, self.ttl_days("BLOB_TTL_DAYS"));
+        set!(
+            config.blob_limits_premium.quota_bytes,
+            self.get("BLOB_QUOTA_PREMIUM_BYTES")
+        );
+        set!(
+            config.blob_limits_premium.ttl,
+            self.ttl_days("BLOB_TTL_PREMIUM_DAYS")
+        );
+        set!(s3.endpoint, self.string("S3_ENDPOINT"));
+        set!(s3.region, self.string("S3_REGION"));
+        set!(s3.bucket, self.string("S3_BUCKET"));
+        set!(s3.access_key_id, self.string("S3_ACCESS_KEY_ID"));
+        set!(s3.secret_access_key, self.string("S3_SECRET_ACCESS_KEY"));
+        set!(s3.prefix, self.string("S3_PREFIX"));
+        set!(s3.path_style, self.flag("S3_PATH_STYLE"));
+        let backend = self.parse_with("BLOB_BACKEND", |raw| match raw {
+            "fs" | "filesystem" => Ok(BlobBackendConfig::Filesystem),
+            "s3" => Ok(BlobBackendConfig::S3(s3)),
+            _ => Err(format!("expected fs or s3, got '{raw}'")),
+        });
+        set!(config.blob_backend, backend);
+
+        set!(config.backup_versions, self.get("BACKUP_VERSIONS"));
+        set!(config.backup_quota_bytes, self.get("BACKUP_QUOTA_BYTES"));
 
         config
     }
 }
 
+/// A TOML value as the string an env var would hold; lists are joined
+/// with commas.
+fn toml_to_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(n) => Some(n.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(toml_to_string)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        _ => None,
+    }
+}
+
+/// Comma-separated values (multiaddrs, peer ids, IPs); any invalid entry
+/// fails the whole list.
+fn parse_list<T>(list: &str) -> Result<Vec<T>, String>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(|e| format!("invalid entry '{s}': {e}")))
+        .collect()
+}
+
+/// Comma-separated networks; a bare address stands for itself alone.
+fn parse_cidrs(list: &str) -> Result<Vec<IpNet>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<IpNet>()
+                .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("invalid network '{s}'"))
+        })
+        .collect()
+}
+
 fn parse_hex_pubkey(hex: &str) -> Result<[u8; 32], String> {
     let hex = hex.trim();
     if hex.len() != 64 {
@@ -141,4 +854,171 @@ mod tests {
     fn test_parse_hex_pubkey_wrong_length() {
         assert!(parse_hex_pubkey("abcd").is_err());
     }
+
+    #[test]
+    fn test_parse_list() {
+        let addrs: Vec<Multiaddr> =
+            parse_list(" /ip4/1.2.3.4/tcp/4001,,/dns4/relay.example.org/udp/4001/quic-v1").unwrap();
+        assert_eq!(
+            addrs,
+            vec![
+                "/ip4/1.2.3.4/tcp/4001".parse::<Multiaddr>().unwrap(),
+                "/dns4/relay.example.org/udp/4001/quic-v1".parse().unwrap(),
+            ]
+        );
+
+        assert!(parse_list::<PeerId>(
+            "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN,12D3Koo"
+        )
+        .is_err());
+    }
+
+    const PEER: &str = "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN";
+
+    fn load(file: &str, env: &[(&str, &str)]) -> anyhow::Result<ServerConfig> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        ServerConfig::from_sources(Some((Path::new("liberte.toml"), file)), |name| {
+            env.get(name).cloned()
+        })
+    }
+
+    #[test]
+    fn test_file_with_env_overrides() {
+        let file = format!(
+            r#"
+            [server]
+            instance_name = "Test relay"
+            http_addr = "127.0.0.1:9000"
+
+            [admission]
+            premium_required = false
+
+            [relay]
+            denylist = ["{PEER}"]
+            banned_ips = ["10.0.0.1", "::1"]
+
+            [rate_limit]
+            requests_per_sec = 2.5
+            burst = 5
+            upload_burst = 2
+            per_identity = true
+            trusted_proxies = ["10.0.0.0/8", "fd00::1"]
+
+            [blobs]
+            backend = "s3"
+            ttl_days = 0
+
+            [blobs.s3]
+            bucket = "liberte"
+            access_key_id = "key"
+            secret_access_key = "secret"
+            "#
+        );
+        let config = load(
+            &file,
+            &[
+                ("RATE_LIMIT_BURST", "8"),
+                ("INSTANCE_NAME", ""),
+                ("ADMIN_TOKEN", "hunter2"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.instance_name, "Test relay");
+        assert_eq!(config.http_addr, ([127, 0, 0, 1], 9000).into());
+        assert!(!config.premium_required);
+        assert_eq!(config.relay_denylist, vec![PEER.parse().unwrap()]);
+        assert_eq!(config.banned_ips.len(), 2);
+        assert_eq!(config.rate_limits.default.per_sec, 2.5);
+        assert_eq!(config.rate_limits.default.burst, 8.0);
+        assert_eq!(config.rate_limits.upload.burst, 2.0);
+        assert_eq!(config.rate_limits.upload.per_sec, 5.0);
+        assert!(config.rate_limits.per_identity);
+        assert_eq!(
+            config.trusted_proxies,
+            vec![
+                "10.0.0.0/8".parse::<IpNet>().unwrap(),
+                "fd00::1/128".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.blob_limits_free.ttl, None);
+        assert_eq!(config.admin_token.as_deref(), Some("hunter2"));
+        let logged = format!("{config:?}");
+        assert!(!logged.contains("hunter2") && !logged.contains("\"secret\""));
+        let BlobBackendConfig::S3(s3) = config.blob_backend else {
+            panic!("expected the S3 backend");
+        };
+        assert_eq!(s3.bucket, "liberte");
+        assert_eq!(s3.region, "us-east-1");
+    }
+
+    #[test]
+    fn test_example_file_matches_defaults() {
+        let example = include_str!("../../../docker/liberte.example.toml");
+        let config = load(example, &[]).unwrap();
+        assert_eq!(
+            format!("{config:?}"),
+            format!("{:?}", ServerConfig::default())
+        );
+    }
+
+    #[test]
+    fn test_invalid_values_are_all_reported() {
+        let err = load(
+            "[server]\nhttp_addr = \"nowhere\"\nshoe_size = 42\n[shop]\nopen = true\n",
+            &[
+                ("PREMIUM_REQUIRED", "yes"),
+                ("RELAY_ALLOWLIST", "12D3Koo"),
+                ("UPLOAD_CHUNK_SIZE", "0"),
+                ("RATE_LIMIT_ADMIN_BURST", "0.5"),
+                ("TRUSTED_PROXIES", "10.0.0.0/33"),
+                ("BLOB_TTL_DAYS", "100000000"),
+            ],
+        )
+        .unwrap_err()
+        .to_string();
+
+        for expected in [
+            "server.http_addr in liberte.toml",
+            "server.shoe_size in liberte.toml: unknown setting",
+            "shop in liberte.toml: unknown section",
+            "PREMIUM_REQUIRED: expected true or false, got 'yes'",
+            "RELAY_ALLOWLIST: invalid entry '12D3Koo'",
+            "UPLOAD_CHUNK_SIZE must be between 1",
+            "RATE_LIMIT_ADMIN_BURST must be at least 1",
+            "TRUSTED_PROXIES: invalid network '10.0.0.0/33'",
+            "BLOB_TTL_DAYS: must be at most 36500 days",
+        ] {
+            assert!(err.contains(expected), "missing {expected:?} in {err}");
+        }
+        assert!(load("[server", &[]).is_err());
+
+        for token in ["", "   "] {
+            let err = load(&format!("[server]\nadmin_token = \"{token}\"\n"), &[])
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("ADMIN_TOKEN must not be empty"), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_restart_required() {
+        let old = ServerConfig::default();
+        let mut new = old.clone();
+        new.registration_open = !old.registration_open;
+        new.rate_limits.backup.burst = 1.0;
+        new.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+        new.relay_denylist = vec![PEER.parse().unwrap()];
+        assert!(old.restart_required(&new).is_empty());
+
+        new.http_addr = ([127, 0, 0, 1], 1).into();
+        new.blob_limits_free.quota_bytes = 1;
+        assert_eq!(
+            old.restart_required(&new),
+            vec!["HTTP_ADDR", "BLOB_QUOTA_BYTES / BLOB_TTL_DAYS"]
+        );
+    }
 }
@@ -1,16 +1,23 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use thiserror::Error;
-use uuid::Uuid;
+
+use crate::blob_store::BlobId;
 
 #[derive(Debug, Error)]
 pub enum ServerError {
     #[error("Blob not found: {0}")]
-    BlobNotFound(Uuid),
+    BlobNotFound(BlobId),
 
     #[error("Blob too large: {size} bytes (max {max})")]
     BlobTooLarge { size: usize, max: usize },
 
+    #[error("Storage quota exceeded: {used} of {quota} bytes used")]
+    QuotaExceeded { used: u64, quota: u64 },
+
+    #[error("Upload offset mismatch: {expected} bytes received so far")]
+    UploadOffsetMismatch { expected: u64 },
+
     #[error("Blob storage error: {0}")]
     BlobStorage(String),
 
@@ -21,12 +28,18 @@ pub enum ServerError {
     #[error("Invalid request: {0}")]
     BadRequest(String),
 
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
     #[error("Forbidden: {0}")]
     Forbidden(String),
 
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Too many requests")]
+    RateLimited,
+
     #[error("Internal error: {0}")]
     #[allow(dead_code)]
     Internal(String),
@@ -37,14 +50,20 @@ impl IntoResponse for ServerError {
         let (status, message) = match &self {
             ServerError::BlobNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             ServerError::BlobTooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            ServerError::QuotaExceeded { .. } => {
+                (StatusCode::INSUFFICIENT_STORAGE, self.to_string())
+            }
+            ServerError::UploadOffsetMismatch { .. } => (StatusCode::CONFLICT, self.to_string()),
             ServerError::BlobStorage(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Blob storage error".to_string(),
             ),
             ServerError::PremiumVerificationFailed => (StatusCode::UNAUTHORIZED, self.to_string()),
             ServerError::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            ServerError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
             ServerError::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
             ServerError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            ServerError::RateLimited => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
             ServerError::Internal(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal server error".to_string(),
@@ -1,11 +1,23 @@
 mod api;
+mod audit;
+mod auth;
+mod backup_store;
+mod blob_index;
 mod blob_store;
 mod config;
 mod error;
+mod metrics;
 mod premium;
+mod premium_store;
+mod range;
 mod rate_limit;
 mod relay;
+mod relay_policy;
+mod reload;
 mod sfu;
+mod sfu_socket;
+mod storage;
+mod upload_session;
 
 use std::sync::Arc;
 
@@ -13,10 +25,16 @@ use tracing::info;
 use tracing_subscriber::EnvFilter;
 
 use crate::api::AppState;
+use crate::audit::AuditLog;
+use crate::backup_store::BackupStore;
 use crate::blob_store::BlobStore;
 use crate::config::ServerConfig;
+use crate::metrics::Metrics;
 use crate::premium::PremiumVerifier;
+use crate::premium_store::PremiumStore;
 use crate::rate_limit::RateLimiter;
+use crate::relay_policy::RelayPolicy;
+use crate::upload_session::UploadSessions;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -32,7 +50,8 @@ async fn main() -> anyhow::Result<()> {
         env!("CARGO_PKG_VERSION")
     );
 
-    let config = ServerConfig::from_env();
+    let config_path = config::config_path();
+    let config = ServerConfig::load(config_path.as_deref())?;
     info!(?config, "Loaded configuration");
     info!(
         instance = %config.instance_name,
@@ -42,23 +61,86 @@ async fn main() -> anyhow::Result<()> {
         "Self-hosted instance settings"
     );
 
-    let blob_store =
-        Arc::new(BlobStore::new(config.blob_storage_path.clone(), config.max_blob_size).await?);
+    let blob_store = Arc::new(
+        BlobStore::with_backend(
+            config.blob_storage_path.clone(),
+            config.max_blob_size,
+            storage::from_config(&config)?,
+        )
+        .await?,
+    );
 
-    let premium_verifier = Arc::new(PremiumVerifier::new(config.payment_server_pubkey));
+    let backup_store = Arc::new(
+        BackupStore::new(
+            config.blob_storage_path.join("backups"),
+            config.backup_versions,
+            config.backup_quota_bytes,
+        )
+        .await?,
+    );
 
-    // 10 req/s sustained, burst of 30
-    let rate_limiter = RateLimiter::default();
+    let uploads = Arc::new(
+        UploadSessions::new(
+            config.blob_storage_path.join("uploads"),
+            config.max_blob_size as u64,
+        )
+        .await?,
+    );
 
-    let _sfu_manager = sfu::SfuManager::new();
+    let premium_verifier = Arc::new(PremiumVerifier::new(
+        config.payment_server_pubkey,
+        PremiumStore::open(&config.premium_db_path)?,
+    )?);
+
+    let rate_limiter = RateLimiter::new(config.rate_limits, config.trusted_proxies.clone());
+
+    let sfu_manager = sfu::SfuManager::new(config.sfu_max_participants);
+
+    let metrics = Arc::new(Metrics::new(&rate_limiter, &premium_verifier));
+
+    let swarm_key = config
+        .swarm_key_path
+        .as_deref()
+        .map(liberte_net::load_swarm_key)
+        .transpose()?;
+    if let Some(ref psk) = swarm_key {
+        info!(fingerprint = %psk.fingerprint(), "Private network mode enabled");
+    }
+
+    let relay_policy = RelayPolicy::new(&config);
+    let relay_keypair = relay::load_or_create_keypair(&config.relay_key_path)?;
+    let relay_info = relay::spawn_relay(
+        &config,
+        relay_keypair,
+        swarm_key,
+        relay_policy.clone(),
+        metrics.relay.clone(),
+    )
+    .await?;
+    info!(
+        peer_id = %relay_info.peer_id,
+        addr = %config.listen_addr,
+        "Relay server running in background"
+    );
 
     let app_state = AppState {
         blob_store,
+        uploads,
+        backup_store,
         premium_verifier,
         rate_limiter: rate_limiter.clone(),
         config: Arc::new(config.clone()),
+        relay: relay_info,
+        relay_policy,
+        sfu: sfu_manager,
+        metrics,
+        audit: AuditLog::new(config.audit_log_path.clone()),
+        seen_signatures: Default::default(),
     };
 
+    #[cfg(unix)]
+    reload::spawn_on_sighup(app_state.clone(), config_path)?;
+
     // Rate limiter cleanup every 5 min, evict buckets idle >10 min
     let rl = rate_limiter.clone();
     tokio::spawn(async move {
@@ -79,15 +161,48 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    let listen_addr = config.listen_addr.clone();
-    let http_addr = config.http_addr;
+    // Expired identity bindings and premium snapshots, same cadence
+    let policy = app_state.relay_policy.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            policy.purge_expired();
+        }
+    });
 
-    let relay_peer_id = relay::spawn_relay(&listen_addr).await?;
-    info!(
-        peer_id = %relay_peer_id,
-        addr = %listen_addr,
-        "Relay server running in background"
-    );
+    // SFU rooms nobody joined or sent a frame to lately, every minute
+    let sfu = app_state.sfu.clone();
+    let room_idle = config.sfu_room_idle;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            sfu.purge_idle(room_idle).await;
+        }
+    });
+
+    // Blobs left unread past their tier's TTL and abandoned uploads, hourly
+    let blobs = app_state.blob_store.clone();
+    let uploads = app_state.uploads.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match blobs.collect_garbage().await {
+                Ok(0) => {}
+                Ok(removed) => info!(removed, "Collected expired blobs"),
+                Err(e) => tracing::warn!(error = %e, "Blob garbage collection failed"),
+            }
+            match uploads.purge_stale().await {
+                Ok(0) => {}
+                Ok(removed) => info!(removed, "Discarded abandoned uploads"),
+                Err(e) => tracing::warn!(error = %e, "Upload cleanup failed"),
+            }
+        }
+    });
+
+    let http_addr = config.http_addr;
 
     tokio::select! {
         result = api::serve(app_state, http_addr) => {
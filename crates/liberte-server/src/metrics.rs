@@ -0,0 +1,281 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use liberte_net::BandwidthTracker;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::{counter::Counter, family::Family, gauge::Gauge};
+use prometheus_client::registry::Registry;
+
+use crate::blob_store::BlobStore;
+use crate::premium::PremiumVerifier;
+use crate::rate_limit::RateLimiter;
+use crate::sfu::SfuManager;
+
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum Outcome {
+    Accepted,
+    Denied,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct OutcomeLabels {
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DirectionLabels {
+    pub direction: Direction,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum BlobOp {
+    Upload,
+    Download,
+    Delete,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct BlobOpLabels {
+    pub op: BlobOp,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum PremiumResult {
+    Valid,
+    Invalid,
+    /// Served from the verification cache without checking the signature.
+    Cached,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PremiumLabels {
+    pub result: PremiumResult,
+}
+
+/// Counters and gauges updated by the relay event loop.
+#[derive(Clone, Default)]
+pub struct RelayMetrics {
+    pub reservations: Gauge,
+    pub reservation_requests: Family<OutcomeLabels, Counter>,
+    pub circuits: Gauge,
+    pub circuit_requests: Family<OutcomeLabels, Counter>,
+    /// Counts every byte on relay connections; read into `bytes` on scrape.
+    pub bandwidth: BandwidthTracker,
+    bytes: Family<DirectionLabels, Counter>,
+    /// Totals already added to `bytes`, as (in, out).
+    bytes_reported: Arc<Mutex<(u64, u64)>>,
+}
+
+impl RelayMetrics {
+    pub fn record_reservation(&self, outcome: Outcome) {
+        self.reservation_requests
+            .get_or_create(&OutcomeLabels { outcome })
+            .inc();
+    }
+
+    pub fn record_circuit(&self, outcome: Outcome) {
+        self.circuit_requests
+            .get_or_create(&OutcomeLabels { outcome })
+            .inc();
+    }
+
+    /// Add the bytes relayed since the last call to the `bytes` counters.
+    fn sync_bytes(&self) {
+        let mut reported = self
+            .bytes_reported
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let (bytes_in, bytes_out) = self.bandwidth.totals();
+        let inc = |direction, delta| {
+            self.bytes
+                .get_or_create(&DirectionLabels { direction })
+                .inc_by(delta);
+        };
+        inc(Direction::In, bytes_in.saturating_sub(reported.0));
+        inc(Direction::Out, bytes_out.saturating_sub(reported.1));
+        *reported = (bytes_in, bytes_out);
+    }
+}
+
+/// Everything `/metrics` exposes. Values that are cheap to read from their
+/// owner (uptime, blob store usage, SFU rooms) are sampled at scrape time.
+pub struct Metrics {
+    registry: Registry,
+    started_at: Instant,
+    pub relay: RelayMetrics,
+    pub blob_requests: Family<BlobOpLabels, Counter>,
+    blobs: Gauge,
+    blob_bytes: Gauge,
+    sfu_rooms: Gauge,
+    sfu_participants: Gauge,
+    uptime: Gauge,
+}
+
+impl Metrics {
+    pub fn new(rate_limiter: &RateLimiter, premium_verifier: &PremiumVerifier) -> Self {
+        let mut metrics = Self {
+            registry: Registry::with_prefix("liberte"),
+            started_at: Instant::now(),
+            relay: RelayMetrics::default(),
+            blob_requests: Family::default(),
+            blobs: Gauge::default(),
+            blob_bytes: Gauge::default(),
+            sfu_rooms: Gauge::default(),
+            sfu_participants: Gauge::default(),
+            uptime: Gauge::default(),
+        };
+        let registry = &mut metrics.registry;
+
+        registry.register(
+            "relay_reservations",
+            "Active relay reservations",
+            metrics.relay.reservations.clone(),
+        );
+        registry.register(
+            "relay_reservation_requests",
+            "Relay reservation requests by outcome",
+            metrics.relay.reservation_requests.clone(),
+        );
+        registry.register(
+            "relay_circuits",
+            "Active relayed circuits",
+            metrics.relay.circuits.clone(),
+        );
+        registry.register(
+            "relay_circuit_requests",
+            "Relay circuit requests by outcome",
+            metrics.relay.circuit_requests.clone(),
+        );
+        registry.register(
+            "relay_bytes",
+            "Bytes moved over relay connections, circuits included",
+            metrics.relay.bytes.clone(),
+        );
+        registry.register(
+            "blob_requests",
+            "Blob API requests by operation",
+            metrics.blob_requests.clone(),
+        );
+        registry.register("blobs", "Blobs stored", metrics.blobs.clone());
+        registry.register(
+            "blob_store_bytes",
+            "Total size of stored blobs",
+            metrics.blob_bytes.clone(),
+        );
+        registry.register(
+            "rate_limited_requests",
+            "HTTP requests rejected by the rate limiter",
+            rate_limiter.rejected().clone(),
+        );
+        registry.register(
+            "premium_verifications",
+            "Premium token verifications by result",
+            premium_verifier.verifications().clone(),
+        );
+        registry.register("sfu_rooms", "Open SFU rooms", metrics.sfu_rooms.clone());
+        registry.register(
+            "sfu_participants",
+            "Participants across all SFU rooms",
+            metrics.sfu_participants.clone(),
+        );
+        registry.register(
+            "uptime_seconds",
+            "Seconds since the server started",
+            metrics.uptime.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn uptime_secs(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+
+    /// Sample the scrape-time values and render the registry.
+    pub async fn render(
+        &self,
+        blob_store: &BlobStore,
+        sfu: &SfuManager,
+    ) -> Result<String, std::fmt::Error> {
+        self.uptime.set(self.uptime_secs() as i64);
+
+        self.relay.sync_bytes();
+
+        match blob_store.usage().await {
+            Ok((count, bytes)) => {
+                self.blobs.set(count as i64);
+                self.blob_bytes.set(bytes as i64);
+            }
+            Err(e) => tracing::warn!(error = %e, "Failed to measure blob store"),
+        }
+
+        let (rooms, participants) = sfu.stats().await;
+        self.sfu_rooms.set(rooms as i64);
+        self.sfu_participants.set(participants as i64);
+
+        let mut out = String::new();
+        prometheus_client::encoding::text::encode(&mut out, &self.registry)?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render_openmetrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let blob_store = BlobStore::new(dir.path().to_path_buf(), 1024)
+            .await
+            .unwrap();
+        blob_store
+            .store_blob(
+                b"hello",
+                &[0u8; 32],
+                crate::config::BlobLimits {
+                    quota_bytes: 0,
+                    ttl: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let rate_limiter = RateLimiter::default();
+        let metrics = Metrics::new(
+            &rate_limiter,
+            &PremiumVerifier::new(
+                [0u8; 32],
+                crate::premium_store::PremiumStore::open_in_memory().unwrap(),
+            )
+            .unwrap(),
+        );
+        metrics.relay.record_reservation(Outcome::Denied);
+        metrics
+            .blob_requests
+            .get_or_create(&BlobOpLabels { op: BlobOp::Upload })
+            .inc();
+        rate_limiter.rejected().inc();
+
+        let text = metrics
+            .render(&blob_store, &SfuManager::default())
+            .await
+            .unwrap();
+        assert!(text.contains("liberte_relay_reservation_requests_total{outcome=\"Denied\"} 1"));
+        assert!(text.contains("liberte_blob_requests_total{op=\"Upload\"} 1"));
+        assert!(text.contains("liberte_blobs 1"));
+        assert!(text.contains("liberte_blob_store_bytes 5"));
+        assert!(text.contains("liberte_rate_limited_requests_total 1"));
+        assert!(text.contains("liberte_relay_bytes_total{direction=\"In\"} 0"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
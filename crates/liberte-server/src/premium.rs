@@ -2,11 +2,19 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use chrono::{DateTime, Duration, Utc};
+use prometheus_client::metrics::{counter::Counter, family::Family};
 use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use liberte_shared::premium::{check_premium_status_with_key, PremiumToken};
 
+use crate::error::ServerError;
+use crate::metrics::{PremiumLabels, PremiumResult};
+use crate::premium_store::{PremiumGrant, PremiumRevocation, PremiumStore};
+
+/// How long an admin grant lasts when no end is given.
+const DEFAULT_GRANT_DAYS: i64 = 36500;
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 struct CachedStatus {
@@ -21,20 +29,84 @@ impl CachedStatus {
     }
 }
 
+/// Verifies premium tokens and answers premium checks from a cache, which
+/// is rebuilt from `store` on startup.
 #[derive(Clone)]
 pub struct PremiumVerifier {
     server_pubkey: [u8; 32],
     cache: Arc<RwLock<HashMap<[u8; 32], CachedStatus>>>,
+    store: PremiumStore,
+    verifications: Family<PremiumLabels, Counter>,
 }
 
 impl PremiumVerifier {
-    pub fn new(server_pubkey: [u8; 32]) -> Self {
-        Self {
-            server_pubkey,
-            cache: Arc::new(RwLock::new(HashMap::new())),
+    /// A verifier whose cache holds the grants still in force in `store`
+    /// and its stored tokens that still verify against `server_pubkey`.
+    pub fn new(server_pubkey: [u8; 32], store: PremiumStore) -> Result<Self, ServerError> {
+        let now = Utc::now();
+        let mut cache = HashMap::new();
+
+        let tokens = store.unexpired_tokens(now)?;
+        let restored_tokens = tokens.len();
+        for stored in tokens {
+            // Re-checked so a rotated payment server key drops old tokens
+            if !check_premium_status_with_key(&stored.token, &server_pubkey) {
+                continue;
+            }
+            cache.insert(
+                stored.token.user_pubkey,
+                CachedStatus {
+                    valid: true,
+                    valid_until: stored.token.valid_until,
+                    verified_at: stored.verified_at,
+                },
+            );
+        }
+
+        let grants = store.active_grants(now)?;
+        let restored_grants = grants.len();
+        for grant in grants {
+            let entry = cache.entry(grant.user_pubkey).or_insert(CachedStatus {
+                valid: true,
+                valid_until: grant.valid_until,
+                verified_at: grant.granted_at,
+            });
+            entry.valid_until = entry.valid_until.max(grant.valid_until);
+        }
+
+        if restored_tokens + restored_grants > 0 {
+            info!(
+                grants = restored_grants,
+                tokens = restored_tokens,
+                "Premium state restored"
+            );
         }
+
+        Ok(Self {
+            server_pubkey,
+            cache: Arc::new(RwLock::new(cache)),
+            store,
+            verifications: Family::default(),
+        })
+    }
+
+    pub fn store(&self) -> &PremiumStore {
+        &self.store
+    }
+
+    pub fn verifications(&self) -> &Family<PremiumLabels, Counter> {
+        &self.verifications
     }
 
+    fn record(&self, result: PremiumResult) {
+        self.verifications
+            .get_or_create(&PremiumLabels { result })
+            .inc();
+    }
+
+    /// Whether `token` makes its holder premium. Tokens of an identity
+    /// revoked since its last admin grant are refused: they carry no issue
+    /// date to tell those already held at revocation from later ones.
     pub async fn verify(&self, token: &PremiumToken) -> bool {
         // Check cache first
         {
@@ -45,12 +117,25 @@ impl PremiumVerifier {
                         user = hex::encode(token.user_pubkey),
                         "Premium status served from cache"
                     );
+                    self.record(PremiumResult::Cached);
                     return true;
                 }
             }
         }
 
-        let valid = check_premium_status_with_key(token, &self.server_pubkey);
+        let valid = check_premium_status_with_key(token, &self.server_pubkey)
+            && match self.store.is_revoked(&token.user_pubkey) {
+                Ok(revoked) => !revoked,
+                Err(e) => {
+                    warn!(error = %e, "Failed to check premium revocations");
+                    false
+                }
+            };
+        self.record(if valid {
+            PremiumResult::Valid
+        } else {
+            PremiumResult::Invalid
+        });
 
         {
             let mut cache = self.cache.write().await;
@@ -70,6 +155,9 @@ impl PremiumVerifier {
                 until = %token.valid_until,
                 "Premium status verified"
             );
+            if let Err(e) = self.store.save_token(token, Utc::now()) {
+                warn!(error = %e, "Failed to persist premium token");
+            }
         } else {
             debug!(
                 user = hex::encode(token.user_pubkey),
@@ -80,7 +168,6 @@ impl PremiumVerifier {
         valid
     }
 
-    #[allow(dead_code)]
     pub async fn is_premium_cached(&self, user_pubkey: &[u8; 32]) -> bool {
         let cache = self.cache.read().await;
         cache
@@ -89,22 +176,71 @@ impl PremiumVerifier {
             .unwrap_or(false)
     }
 
-    /// Admin grant -- inserts a cache entry valid for ~100 years.
-    pub async fn admin_grant(&self, user_pubkey: &[u8; 32]) {
+    /// End of the cached premium period, if the user is premium right now.
+    pub async fn cached_until(&self, user_pubkey: &[u8; 32]) -> Option<DateTime<Utc>> {
+        let cache = self.cache.read().await;
+        cache
+            .get(user_pubkey)
+            .filter(|entry| entry.is_fresh())
+            .map(|entry| entry.valid_until)
+    }
+
+    /// Admin grant until `valid_until` (~100 years if `None`), persisted
+    /// before it takes effect.
+    pub async fn admin_grant(
+        &self,
+        user_pubkey: &[u8; 32],
+        valid_until: Option<DateTime<Utc>>,
+        granted_by: Option<String>,
+        note: Option<String>,
+    ) -> Result<PremiumGrant, ServerError> {
+        let now = Utc::now();
+        let grant = PremiumGrant {
+            user_pubkey: *user_pubkey,
+            granted_at: now,
+            granted_by,
+            valid_until: valid_until.unwrap_or(now + Duration::days(DEFAULT_GRANT_DAYS)),
+            note,
+        };
+        self.store.grant(&grant)?;
+
+        // A paid period outlasting the grant still counts, as on restore
         let mut cache = self.cache.write().await;
+        let valid_until = cache
+            .get(user_pubkey)
+            .filter(|entry| entry.is_fresh())
+            .map_or(grant.valid_until, |entry| {
+                entry.valid_until.max(grant.valid_until)
+            });
         cache.insert(
             *user_pubkey,
             CachedStatus {
                 valid: true,
-                valid_until: Utc::now() + Duration::days(36500),
-                verified_at: Utc::now(),
+                valid_until,
+                verified_at: now,
             },
         );
+        Ok(grant)
     }
 
-    pub async fn admin_revoke(&self, user_pubkey: &[u8; 32]) {
+    /// Revoke premium from `user_pubkey`, dropping its grant and stored
+    /// token; its payment tokens are refused until the next admin grant.
+    /// Returns whether it had either.
+    pub async fn admin_revoke(
+        &self,
+        user_pubkey: &[u8; 32],
+        revoked_by: Option<String>,
+        reason: Option<String>,
+    ) -> Result<bool, ServerError> {
+        let revoked = self.store.revoke(&PremiumRevocation {
+            user_pubkey: *user_pubkey,
+            revoked_at: Utc::now(),
+            revoked_by,
+            reason,
+        })?;
+
         let mut cache = self.cache.write().await;
-        cache.remove(user_pubkey);
+        Ok(cache.remove(user_pubkey).is_some() || revoked)
     }
 
     pub async fn purge_expired(&self) {
@@ -115,6 +251,9 @@ impl PremiumVerifier {
         if removed > 0 {
             debug!(removed, "Purged expired premium cache entries");
         }
+        if let Err(e) = self.store.purge_expired_tokens(Utc::now()) {
+            warn!(error = %e, "Failed to purge expired premium tokens");
+        }
     }
 }
 
@@ -126,6 +265,10 @@ mod tests {
     use liberte_shared::premium::create_premium_token;
     use rand::rngs::OsRng;
 
+    fn verifier(server_pubkey: [u8; 32]) -> PremiumVerifier {
+        PremiumVerifier::new(server_pubkey, PremiumStore::open_in_memory().unwrap()).unwrap()
+    }
+
     #[tokio::test]
     async fn test_verify_valid_token() {
         let server_key = SigningKey::generate(&mut OsRng);
@@ -135,7 +278,7 @@ mod tests {
         let token =
             create_premium_token(&user_pubkey, Utc::now() + Duration::days(30), &server_key);
 
-        let verifier = PremiumVerifier::new(server_pubkey);
+        let verifier = verifier(server_pubkey);
         assert!(verifier.verify(&token).await);
         assert!(verifier.is_premium_cached(&user_pubkey).await);
     }
@@ -148,7 +291,7 @@ mod tests {
 
         let token = create_premium_token(&user_pubkey, Utc::now() - Duration::days(1), &server_key);
 
-        let verifier = PremiumVerifier::new(server_pubkey);
+        let verifier = verifier(server_pubkey);
         assert!(!verifier.verify(&token).await);
     }
 
@@ -162,7 +305,77 @@ mod tests {
         let token =
             create_premium_token(&user_pubkey, Utc::now() + Duration::days(30), &server_key);
 
-        let verifier = PremiumVerifier::new(wrong_pubkey);
+        let verifier = verifier(wrong_pubkey);
+        assert!(!verifier.verify(&token).await);
+    }
+
+    #[tokio::test]
+    async fn test_state_restored_from_store() {
+        let server_key = SigningKey::generate(&mut OsRng);
+        let server_pubkey = server_key.verifying_key().to_bytes();
+        let paying = [1u8; 32];
+        let granted = [2u8; 32];
+        let revoked = [3u8; 32];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("premium.db");
+
+        {
+            let verifier =
+                PremiumVerifier::new(server_pubkey, PremiumStore::open(&path).unwrap()).unwrap();
+            let token = create_premium_token(&paying, Utc::now() + Duration::days(30), &server_key);
+            assert!(verifier.verify(&token).await);
+            verifier
+                .admin_grant(&granted, None, None, Some("Tester".into()))
+                .await
+                .unwrap();
+            verifier
+                .admin_grant(&revoked, None, None, None)
+                .await
+                .unwrap();
+            assert!(verifier
+                .admin_revoke(&revoked, None, Some("Abuse".into()))
+                .await
+                .unwrap());
+        }
+
+        let verifier =
+            PremiumVerifier::new(server_pubkey, PremiumStore::open(&path).unwrap()).unwrap();
+        assert!(verifier.is_premium_cached(&paying).await);
+        assert!(verifier.is_premium_cached(&granted).await);
+        assert!(!verifier.is_premium_cached(&revoked).await);
+
+        // Tokens signed by a previous payment server key are not restored
+        let rotated = SigningKey::generate(&mut OsRng).verifying_key().to_bytes();
+        let verifier = PremiumVerifier::new(rotated, PremiumStore::open(&path).unwrap()).unwrap();
+        assert!(!verifier.is_premium_cached(&paying).await);
+        assert!(verifier.is_premium_cached(&granted).await);
+    }
+
+    #[tokio::test]
+    async fn test_revoked_token_refused_until_granted() {
+        let server_key = SigningKey::generate(&mut OsRng);
+        let verifier = verifier(server_key.verifying_key().to_bytes());
+        let user_pubkey = [42u8; 32];
+        let token =
+            create_premium_token(&user_pubkey, Utc::now() + Duration::days(30), &server_key);
+
+        assert!(verifier.verify(&token).await);
+        assert!(verifier
+            .admin_revoke(&user_pubkey, None, Some("Chargeback".into()))
+            .await
+            .unwrap());
         assert!(!verifier.verify(&token).await);
+        assert!(!verifier.is_premium_cached(&user_pubkey).await);
+
+        verifier
+            .admin_grant(
+                &user_pubkey,
+                Some(Utc::now() - Duration::days(1)),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(verifier.verify(&token).await);
     }
 }
@@ -0,0 +1,434 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use liberte_shared::premium::PremiumToken;
+
+use crate::error::ServerError;
+
+const V001_SQL: &str = "
+BEGIN;
+CREATE TABLE grants (
+    pubkey      TEXT PRIMARY KEY NOT NULL,
+    granted_at  INTEGER NOT NULL,
+    granted_by  TEXT,
+    valid_until INTEGER NOT NULL,
+    note        TEXT
+);
+CREATE TABLE revocations (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    pubkey     TEXT NOT NULL,
+    revoked_at INTEGER NOT NULL,
+    revoked_by TEXT,
+    reason     TEXT
+);
+CREATE INDEX idx_revocations_pubkey ON revocations(pubkey);
+CREATE TABLE tokens (
+    pubkey      TEXT PRIMARY KEY NOT NULL,
+    token       TEXT NOT NULL,
+    valid_until INTEGER NOT NULL,
+    verified_at INTEGER NOT NULL
+);
+PRAGMA user_version = 1;
+COMMIT;
+";
+
+/// Premium granted by an admin, independently of any payment token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PremiumGrant {
+    pub user_pubkey: [u8; 32],
+    pub granted_at: DateTime<Utc>,
+    /// IP of the admin who granted it, as in the audit log.
+    pub granted_by: Option<String>,
+    pub valid_until: DateTime<Utc>,
+    pub note: Option<String>,
+}
+
+/// A revoked grant or token, kept as history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PremiumRevocation {
+    pub user_pubkey: [u8; 32],
+    pub revoked_at: DateTime<Utc>,
+    pub revoked_by: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// The latest valid payment token presented by an identity.
+#[derive(Debug, Clone)]
+pub struct StoredToken {
+    pub token: PremiumToken,
+    pub verified_at: DateTime<Utc>,
+}
+
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
+
+fn store_error(e: rusqlite::Error) -> ServerError {
+    ServerError::Internal(format!("Premium store error: {e}"))
+}
+
+fn pubkey(hex: String) -> [u8; 32] {
+    hex::decode(hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .unwrap_or_default()
+}
+
+impl PremiumGrant {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            user_pubkey: pubkey(row.get(0)?),
+            granted_at: timestamp(row.get(1)?),
+            granted_by: row.get(2)?,
+            valid_until: timestamp(row.get(3)?),
+            note: row.get(4)?,
+        })
+    }
+}
+
+impl PremiumRevocation {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            user_pubkey: pubkey(row.get(0)?),
+            revoked_at: timestamp(row.get(1)?),
+            revoked_by: row.get(2)?,
+            reason: row.get(3)?,
+        })
+    }
+}
+
+/// Matches a pubkey hex prefix, or a substring of the note or reason
+/// (case-insensitive). An empty search matches everything.
+const SEARCH_FILTER: &str = "(?1 = '' OR instr(pubkey, lower(?1)) = 1
+     OR instr(lower(COALESCE({text}, '')), lower(?1)) > 0)";
+
+/// SQLite record of premium state, so admin grants and verified tokens
+/// survive restarts. Like the blob index, a plain mutex around the
+/// connection does.
+#[derive(Debug, Clone)]
+pub struct PremiumStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl PremiumStore {
+    pub fn open(path: &Path) -> Result<Self, ServerError> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                ServerError::Internal(format!("Failed to create '{}': {e}", parent.display()))
+            })?;
+        }
+        Self::init(Connection::open(path).map_err(store_error)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, ServerError> {
+        Self::init(Connection::open_in_memory().map_err(store_error)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, ServerError> {
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(store_error)?;
+        let current: u32 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(store_error)?;
+        if current < 1 {
+            conn.execute_batch(V001_SQL).map_err(store_error)?;
+        }
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record `grant`, replacing any earlier grant to the same identity.
+    pub fn grant(&self, grant: &PremiumGrant) -> Result<(), ServerError> {
+        self.conn()
+            .execute(
+                "INSERT OR REPLACE INTO grants (pubkey, granted_at, granted_by, valid_until, note)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    hex::encode(grant.user_pubkey),
+                    grant.granted_at.timestamp(),
+                    grant.granted_by,
+                    grant.valid_until.timestamp(),
+                    grant.note,
+                ],
+            )
+            .map_err(store_error)?;
+        Ok(())
+    }
+
+    /// Drop the grant and stored token of `revocation.user_pubkey`, and log
+    /// the revocation. Returns whether there was either to drop.
+    pub fn revoke(&self, revocation: &PremiumRevocation) -> Result<bool, ServerError> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(store_error)?;
+        let key = hex::encode(revocation.user_pubkey);
+        let removed = tx
+            .execute("DELETE FROM grants WHERE pubkey = ?1", params![key])
+            .map_err(store_error)?
+            + tx.execute("DELETE FROM tokens WHERE pubkey = ?1", params![key])
+                .map_err(store_error)?;
+        tx.execute(
+            "INSERT INTO revocations (pubkey, revoked_at, revoked_by, reason)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                key,
+                revocation.revoked_at.timestamp(),
+                revocation.revoked_by,
+                revocation.reason,
+            ],
+        )
+        .map_err(store_error)?;
+        tx.commit().map_err(store_error)?;
+        Ok(removed > 0)
+    }
+
+    /// Whether `user_pubkey` was revoked and not granted premium since.
+    /// Revoking drops the grant, so any grant left is a later one.
+    pub fn is_revoked(&self, user_pubkey: &[u8; 32]) -> Result<bool, ServerError> {
+        self.conn()
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM revocations WHERE pubkey = ?1)
+                     AND NOT EXISTS(SELECT 1 FROM grants WHERE pubkey = ?1)",
+                params![hex::encode(user_pubkey)],
+                |row| row.get(0),
+            )
+            .map_err(store_error)
+    }
+
+    /// Keep `token` as the latest verified one of its identity, unless a
+    /// token valid for longer is already stored.
+    pub fn save_token(
+        &self,
+        token: &PremiumToken,
+        verified_at: DateTime<Utc>,
+    ) -> Result<(), ServerError> {
+        let json = serde_json::to_string(token)
+            .map_err(|e| ServerError::Internal(format!("Failed to encode token: {e}")))?;
+        self.conn()
+            .execute(
+                "INSERT INTO tokens (pubkey, token, valid_until, verified_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(pubkey) DO UPDATE SET
+                     token = excluded.token,
+                     valid_until = excluded.valid_until,
+                     verified_at = excluded.verified_at
+                 WHERE excluded.valid_until >= tokens.valid_until",
+                params![
+                    hex::encode(token.user_pubkey),
+                    json,
+                    token.valid_until.timestamp(),
+                    verified_at.timestamp(),
+                ],
+            )
+            .map_err(store_error)?;
+        Ok(())
+    }
+
+    /// Grants still in force at `now`.
+    pub fn active_grants(&self, now: DateTime<Utc>) -> Result<Vec<PremiumGrant>, ServerError> {
+        self.query_grants(
+            "SELECT pubkey, granted_at, granted_by, valid_until, note FROM grants
+             WHERE valid_until > ?1",
+            params![now.timestamp()],
+        )
+    }
+
+    /// Stored tokens that have not expired at `now`. Tokens that no longer
+    /// parse are skipped.
+    pub fn unexpired_tokens(&self, now: DateTime<Utc>) -> Result<Vec<StoredToken>, ServerError> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT token, verified_at FROM tokens WHERE valid_until > ?1")
+            .map_err(store_error)?;
+        let rows = stmt
+            .query_map(params![now.timestamp()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(store_error)?;
+
+        let mut tokens = Vec::new();
+        for row in rows {
+            let (json, verified_at) = row.map_err(store_error)?;
+            if let Ok(token) = serde_json::from_str(&json) {
+                tokens.push(StoredToken {
+                    token,
+                    verified_at: timestamp(verified_at),
+                });
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Latest stored token of `user_pubkey`, expired or not.
+    pub fn token_of(&self, user_pubkey: &[u8; 32]) -> Result<Option<StoredToken>, ServerError> {
+        let row = self
+            .conn()
+            .query_row(
+                "SELECT token, verified_at FROM tokens WHERE pubkey = ?1",
+                params![hex::encode(user_pubkey)],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()
+            .map_err(store_error)?;
+        Ok(row.and_then(|(json, verified_at)| {
+            Some(StoredToken {
+                token: serde_json::from_str(&json).ok()?,
+                verified_at: timestamp(verified_at),
+            })
+        }))
+    }
+
+    /// Grants whose pubkey starts with `search` or whose note contains it,
+    /// most recent first.
+    pub fn search_grants(
+        &self,
+        search: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<PremiumGrant>, ServerError> {
+        self.query_grants(
+            &format!(
+                "SELECT pubkey, granted_at, granted_by, valid_until, note FROM grants
+                 WHERE {} ORDER BY granted_at DESC, pubkey LIMIT ?2 OFFSET ?3",
+                SEARCH_FILTER.replace("{text}", "note")
+            ),
+            params![search, limit, offset],
+        )
+    }
+
+    /// Revocations whose pubkey starts with `search` or whose reason
+    /// contains it, most recent first.
+    pub fn search_revocations(
+        &self,
+        search: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<PremiumRevocation>, ServerError> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT pubkey, revoked_at, revoked_by, reason FROM revocations
+                 WHERE {} ORDER BY revoked_at DESC, id DESC LIMIT ?2 OFFSET ?3",
+                SEARCH_FILTER.replace("{text}", "reason")
+            ))
+            .map_err(store_error)?;
+        let rows = stmt
+            .query_map(params![search, limit, offset], PremiumRevocation::from_row)
+            .map_err(store_error)?;
+        rows.collect::<Result<_, _>>().map_err(store_error)
+    }
+
+    /// Forget tokens expired at `now`. Returns how many were removed.
+    pub fn purge_expired_tokens(&self, now: DateTime<Utc>) -> Result<usize, ServerError> {
+        self.conn()
+            .execute(
+                "DELETE FROM tokens WHERE valid_until <= ?1",
+                params![now.timestamp()],
+            )
+            .map_err(store_error)
+    }
+
+    fn query_grants(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<PremiumGrant>, ServerError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(sql).map_err(store_error)?;
+        let rows = stmt
+            .query_map(params, PremiumGrant::from_row)
+            .map_err(store_error)?;
+        rows.collect::<Result<_, _>>().map_err(store_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn grant(user_pubkey: [u8; 32], days: i64, note: Option<&str>) -> PremiumGrant {
+        let now = Utc::now();
+        PremiumGrant {
+            user_pubkey,
+            granted_at: now,
+            granted_by: Some("127.0.0.1".into()),
+            valid_until: now + Duration::days(days),
+            note: note.map(str::to_string),
+        }
+    }
+
+    fn revocation(user_pubkey: [u8; 32], reason: &str) -> PremiumRevocation {
+        PremiumRevocation {
+            user_pubkey,
+            revoked_at: Utc::now(),
+            revoked_by: None,
+            reason: Some(reason.into()),
+        }
+    }
+
+    #[test]
+    fn test_grants_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("premium.db");
+        {
+            let store = PremiumStore::open(&path).unwrap();
+            store
+                .grant(&grant([1; 32], 30, Some("Beta tester")))
+                .unwrap();
+            store.grant(&grant([2; 32], -1, None)).unwrap();
+        }
+
+        let store = PremiumStore::open(&path).unwrap();
+        let active = store.active_grants(Utc::now()).unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].user_pubkey, [1; 32]);
+        assert_eq!(active[0].note.as_deref(), Some("Beta tester"));
+    }
+
+    #[test]
+    fn test_revocation_drops_grant_and_is_logged() {
+        let store = PremiumStore::open_in_memory().unwrap();
+        store.grant(&grant([1; 32], 30, None)).unwrap();
+
+        assert!(store.revoke(&revocation([1; 32], "Chargeback")).unwrap());
+        assert!(!store.revoke(&revocation([1; 32], "Again")).unwrap());
+        assert!(store.active_grants(Utc::now()).unwrap().is_empty());
+
+        let revoked = store.search_revocations("charge", 10, 0).unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].reason.as_deref(), Some("Chargeback"));
+        assert_eq!(store.search_revocations("", 10, 0).unwrap().len(), 2);
+
+        assert!(store.is_revoked(&[1; 32]).unwrap());
+        assert!(!store.is_revoked(&[2; 32]).unwrap());
+        store.grant(&grant([1; 32], 30, None)).unwrap();
+        assert!(!store.is_revoked(&[1; 32]).unwrap());
+    }
+
+    #[test]
+    fn test_search_grants() {
+        let store = PremiumStore::open_in_memory().unwrap();
+        store.grant(&grant([0xab; 32], 30, Some("Press"))).unwrap();
+        store.grant(&grant([0xcd; 32], 30, Some("Friend"))).unwrap();
+
+        let by_key = store.search_grants("ABAB", 10, 0).unwrap();
+        assert_eq!(by_key.len(), 1);
+        assert_eq!(by_key[0].user_pubkey, [0xab; 32]);
+        assert_eq!(
+            store.search_grants("friend", 10, 0).unwrap()[0].user_pubkey,
+            [0xcd; 32]
+        );
+        assert_eq!(store.search_grants("", 10, 0).unwrap().len(), 2);
+        assert_eq!(store.search_grants("", 1, 1).unwrap().len(), 1);
+        assert!(store.search_grants("%", 10, 0).unwrap().is_empty());
+    }
+}
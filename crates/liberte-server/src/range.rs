@@ -0,0 +1,106 @@
+/// Inclusive byte range of a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// What a `Range` header asks of a body of a given size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No header, or one we ignore (malformed, several ranges): send it all.
+    Full,
+    Partial(ByteRange),
+    /// A well-formed range starting past the end: 416.
+    Unsatisfiable,
+}
+
+/// Resolve `bytes=a-b`, `bytes=a-` or `bytes=-n` against `size` bytes.
+/// Only single ranges are served; clients resuming a download never need
+/// more.
+pub fn parse_range(header: Option<&str>, size: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // Suffix: the last `n` bytes
+        (Err(_), Ok(n)) if start.is_empty() => {
+            if n == 0 || size == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange {
+                start: size.saturating_sub(n),
+                end: size - 1,
+            }
+        }
+        (Ok(start), Err(_)) if end.is_empty() => {
+            if start >= size {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange {
+                start,
+                end: size - 1,
+            }
+        }
+        (Ok(start), Ok(end)) if start <= end => {
+            if start >= size {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange {
+                start,
+                end: end.min(size - 1),
+            }
+        }
+        _ => return RangeRequest::Full,
+    };
+    RangeRequest::Partial(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(start: u64, end: u64) -> RangeRequest {
+        RangeRequest::Partial(ByteRange { start, end })
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(None, 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=0-9"), 100), partial(0, 9));
+        assert_eq!(parse_range(Some("bytes=90-"), 100), partial(90, 99));
+        assert_eq!(parse_range(Some("bytes=-10"), 100), partial(90, 99));
+        assert_eq!(parse_range(Some("bytes=-500"), 100), partial(0, 99));
+        assert_eq!(parse_range(Some("bytes=50-500"), 100), partial(50, 99));
+        assert_eq!(partial(50, 99), parse_range(Some(" bytes=50-99 "), 100));
+    }
+
+    #[test]
+    fn test_parse_range_ignored_or_unsatisfiable() {
+        assert_eq!(parse_range(Some("items=0-9"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=9-0"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=x-"), 100), RangeRequest::Full);
+        assert_eq!(
+            parse_range(Some("bytes=100-"), 100),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-0"), 100),
+            RangeRequest::Unsatisfiable
+        );
+    }
+}
@@ -1,17 +1,23 @@
 use std::collections::HashMap;
-use std::net::IpAddr;
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
 use std::time::Instant;
 
+use prometheus_client::metrics::counter::Counter;
+
 use axum::{
-    extract::ConnectInfo,
-    http::{Request, StatusCode},
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, Method},
     middleware::Next,
     response::Response,
 };
+use ipnet::IpNet;
 use tokio::sync::Mutex;
 use tracing::warn;
 
+use crate::config::{RateLimit, RateLimits};
+use crate::error::ServerError;
+
 #[derive(Debug, Clone)]
 struct TokenBucket {
     tokens: f64,
@@ -42,28 +48,128 @@ impl TokenBucket {
     }
 }
 
+/// Group of routes sharing a limit; each class has its own buckets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateClass {
+    /// Everything not listed below.
+    Default,
+    /// `/health`, `/info` and `/metrics`, polled by clients and monitoring.
+    Info,
+    /// Blob writes: uploads, references, resumable chunks and deletions.
+    Upload,
+    /// `/backup/*`.
+    Backup,
+    /// `/admin/*`.
+    Admin,
+}
+
+impl RateClass {
+    pub fn of(method: &Method, path: &str) -> Self {
+        if path.starts_with("/admin/") {
+            RateClass::Admin
+        } else if path.starts_with("/backup/") {
+            RateClass::Backup
+        } else if matches!(path, "/health" | "/info" | "/metrics") {
+            RateClass::Info
+        } else if path.starts_with("/blob/") && !matches!(*method, Method::GET | Method::HEAD) {
+            RateClass::Upload
+        } else {
+            RateClass::Default
+        }
+    }
+
+    fn limit(self, limits: &RateLimits) -> RateLimit {
+        match self {
+            RateClass::Default => limits.default,
+            RateClass::Info => limits.info,
+            RateClass::Upload => limits.upload,
+            RateClass::Backup => limits.backup,
+            RateClass::Admin => limits.admin,
+        }
+    }
+}
+
+/// Who a bucket belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Client {
+    Ip(IpAddr),
+    /// Identity key of a signed request, charged on top of its IP with
+    /// per-identity limits on.
+    Identity([u8; 32]),
+}
+
+/// Address a request is attributed to, set by [`client_ip_middleware`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+#[derive(Debug, Default)]
+struct Settings {
+    limits: RateLimits,
+    trusted_proxies: Vec<IpNet>,
+}
+
 #[derive(Clone)]
 pub struct RateLimiter {
-    buckets: Arc<Mutex<HashMap<IpAddr, TokenBucket>>>,
-    rate: f64,
-    capacity: f64,
+    buckets: Arc<Mutex<HashMap<(Client, RateClass), TokenBucket>>>,
+    /// Shared so a config reload reaches every clone.
+    settings: Arc<RwLock<Settings>>,
+    rejected: Counter,
 }
 
 impl RateLimiter {
-    pub fn new(rate: f64, capacity: f64) -> Self {
+    pub fn new(limits: RateLimits, trusted_proxies: Vec<IpNet>) -> Self {
         Self {
             buckets: Arc::new(Mutex::new(HashMap::new())),
-            rate,
-            capacity,
+            settings: Arc::new(RwLock::new(Settings {
+                limits,
+                trusted_proxies,
+            })),
+            rejected: Counter::default(),
         }
     }
 
-    pub async fn check(&self, ip: IpAddr) -> bool {
+    /// Change the limits; existing buckets keep their tokens, capped to
+    /// the new capacity on their next request.
+    pub fn set_limits(&self, limits: RateLimits) {
+        self.write().limits = limits;
+    }
+
+    pub fn set_trusted_proxies(&self, trusted_proxies: Vec<IpNet>) {
+        self.write().trusted_proxies = trusted_proxies;
+    }
+
+    /// Requests refused since start.
+    pub fn rejected(&self) -> &Counter {
+        &self.rejected
+    }
+
+    pub async fn check(&self, client: Client, class: RateClass) -> bool {
+        let limit = class.limit(&self.read().limits);
         let mut buckets = self.buckets.lock().await;
         let bucket = buckets
-            .entry(ip)
-            .or_insert_with(|| TokenBucket::new(self.capacity));
-        bucket.try_consume(self.rate, self.capacity)
+            .entry((client, class))
+            .or_insert_with(|| TokenBucket::new(limit.burst));
+        bucket.try_consume(limit.per_sec, limit.burst)
+    }
+
+    /// [`check`](Self::check), logging and counting refusals.
+    pub async fn admit(&self, client: Client, class: RateClass) -> Result<(), ServerError> {
+        if self.check(client, class).await {
+            return Ok(());
+        }
+        warn!(?client, ?class, "Rate limit exceeded");
+        self.rejected.inc();
+        Err(ServerError::RateLimited)
+    }
+
+    /// Whether signed requests are also charged to their signer.
+    pub fn per_identity(&self) -> bool {
+        self.read().limits.per_identity
+    }
+
+    /// Address of the client behind a connection from `peer`.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        forwarded_client(peer, headers, &self.read().trusted_proxies)
     }
 
     pub async fn purge_stale(&self, max_idle_secs: f64) {
@@ -73,96 +179,229 @@ impl RateLimiter {
             now.duration_since(bucket.last_refill).as_secs_f64() < max_idle_secs
         });
     }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Settings> {
+        self.settings.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Settings> {
+        self.settings.write().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
 impl Default for RateLimiter {
     fn default() -> Self {
-        Self::new(10.0, 30.0)
+        Self::new(RateLimits::default(), Vec::new())
     }
 }
 
-pub async fn rate_limit_middleware(
-    axum::extract::State(limiter): axum::extract::State<RateLimiter>,
-    req: Request<axum::body::Body>,
+/// Resolve the client address once, for the bans, the limits and the
+/// audit log.
+pub async fn client_ip_middleware(
+    State(limiter): State<RateLimiter>,
+    mut req: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let ip = extract_client_ip(&req);
-
-    if let Some(ip) = ip {
-        if !limiter.check(ip).await {
-            warn!(ip = %ip, "Rate limit exceeded");
-            return Err(StatusCode::TOO_MANY_REQUESTS);
-        }
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    if let Some(peer) = peer {
+        let ip = limiter.client_ip(peer, req.headers());
+        req.extensions_mut().insert(ClientIp(ip));
     }
+    next.run(req).await
+}
 
+/// Charge the request to its client IP, in the class of its route.
+pub async fn rate_limit_middleware(
+    State(limiter): State<RateLimiter>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ServerError> {
+    if let Some((client, class)) = ip_bucket(&req) {
+        limiter.admit(client, class).await?;
+    }
     Ok(next.run(req).await)
 }
 
-/// Try ConnectInfo first, then X-Forwarded-For, then X-Real-IP.
-fn extract_client_ip<B>(req: &Request<B>) -> Option<IpAddr> {
-    if let Some(connect_info) = req.extensions().get::<ConnectInfo<std::net::SocketAddr>>() {
-        return Some(connect_info.0.ip());
-    }
+fn ip_bucket(req: &Request) -> Option<(Client, RateClass)> {
+    let ClientIp(ip) = req.extensions().get()?;
+    Some((
+        Client::Ip(*ip),
+        RateClass::of(req.method(), req.uri().path()),
+    ))
+}
 
-    if let Some(forwarded) = req.headers().get("x-forwarded-for") {
-        if let Ok(value) = forwarded.to_str() {
-            if let Some(first) = value.split(',').next() {
-                if let Ok(ip) = first.trim().parse::<IpAddr>() {
-                    return Some(ip);
-                }
-            }
-        }
+/// Client behind `peer`. Forwarded headers are only believed when `peer`
+/// is a trusted proxy; `X-Forwarded-For` is then read from the right,
+/// skipping trusted hops, since entries further left come from the client
+/// and may be forged. `X-Real-IP` is the fallback.
+pub fn forwarded_client(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNet]) -> IpAddr {
+    let peer = peer.to_canonical();
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return peer;
     }
 
-    if let Some(real_ip) = req.headers().get("x-real-ip") {
-        if let Ok(value) = real_ip.to_str() {
-            if let Ok(ip) = value.trim().parse::<IpAddr>() {
-                return Some(ip);
-            }
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for hop in hops.iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) if is_trusted(&ip) => continue,
+            Ok(ip) => return ip,
+            Err(_) => return peer,
         }
     }
 
-    None
+    headers
+        .get("x-real-ip")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(peer)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn limiter(per_sec: f64, burst: f64) -> RateLimiter {
+        let limit = RateLimit { per_sec, burst };
+        RateLimiter::new(
+            RateLimits {
+                default: limit,
+                info: limit,
+                upload: limit,
+                backup: limit,
+                admin: limit,
+                per_identity: false,
+            },
+            Vec::new(),
+        )
+    }
+
+    fn ip(addr: &str) -> Client {
+        Client::Ip(addr.parse().unwrap())
+    }
+
     #[tokio::test]
     async fn test_rate_limiter_allows_burst() {
-        let limiter = RateLimiter::new(10.0, 5.0);
-        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let limiter = limiter(10.0, 5.0);
+        let ip = ip("127.0.0.1");
 
         for _ in 0..5 {
-            assert!(limiter.check(ip).await);
+            assert!(limiter.check(ip, RateClass::Default).await);
         }
 
-        assert!(!limiter.check(ip).await);
+        assert!(!limiter.check(ip, RateClass::Default).await);
     }
 
     #[tokio::test]
     async fn test_rate_limiter_different_ips() {
-        let limiter = RateLimiter::new(10.0, 2.0);
-        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
-        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+        let limiter = limiter(10.0, 2.0);
+        let ip1 = ip("10.0.0.1");
+        let ip2 = ip("10.0.0.2");
 
-        assert!(limiter.check(ip1).await);
-        assert!(limiter.check(ip1).await);
-        assert!(!limiter.check(ip1).await);
+        assert!(limiter.check(ip1, RateClass::Default).await);
+        assert!(limiter.check(ip1, RateClass::Default).await);
+        assert!(!limiter.check(ip1, RateClass::Default).await);
 
-        assert!(limiter.check(ip2).await);
+        assert!(limiter.check(ip2, RateClass::Default).await);
+    }
+
+    #[tokio::test]
+    async fn test_classes_and_identities_have_their_own_buckets() {
+        let limiter = limiter(0.001, 1.0);
+        let ip = ip("10.0.0.4");
+        let user = Client::Identity([1u8; 32]);
+
+        assert!(limiter.check(ip, RateClass::Upload).await);
+        assert!(!limiter.check(ip, RateClass::Upload).await);
+        assert!(limiter.check(ip, RateClass::Backup).await);
+        assert!(limiter.check(user, RateClass::Upload).await);
+        assert!(!limiter.check(user, RateClass::Upload).await);
+        assert!(matches!(
+            limiter.admit(ip, RateClass::Upload).await,
+            Err(ServerError::RateLimited)
+        ));
+        assert_eq!(limiter.rejected().get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_set_limits() {
+        let limiter = limiter(10.0, 5.0);
+        let ip = ip("10.0.0.3");
+        assert!(limiter.check(ip, RateClass::Default).await);
+
+        limiter.set_limits(RateLimits {
+            default: RateLimit {
+                per_sec: 0.001,
+                burst: 1.0,
+            },
+            ..Default::default()
+        });
+        assert!(limiter.check(ip, RateClass::Default).await);
+        assert!(!limiter.check(ip, RateClass::Default).await);
     }
 
     #[tokio::test]
     async fn test_purge_stale() {
-        let limiter = RateLimiter::new(10.0, 5.0);
-        let ip: IpAddr = "192.168.1.1".parse().unwrap();
-        assert!(limiter.check(ip).await);
+        let limiter = limiter(10.0, 5.0);
+        assert!(limiter.check(ip("192.168.1.1"), RateClass::Default).await);
 
         limiter.purge_stale(0.0).await;
 
         let buckets = limiter.buckets.lock().await;
         assert!(buckets.is_empty());
     }
+
+    #[test]
+    fn test_route_classes() {
+        let class = |method: Method, path: &str| RateClass::of(&method, path);
+        assert_eq!(class(Method::GET, "/admin/peers"), RateClass::Admin);
+        assert_eq!(class(Method::POST, "/backup/sync"), RateClass::Backup);
+        assert_eq!(class(Method::GET, "/info"), RateClass::Info);
+        assert_eq!(class(Method::PUT, "/blob/uploads/abc"), RateClass::Upload);
+        assert_eq!(class(Method::DELETE, "/blob/abc"), RateClass::Upload);
+        assert_eq!(class(Method::GET, "/blob/abc"), RateClass::Default);
+        assert_eq!(class(Method::POST, "/relay/bind"), RateClass::Default);
+    }
+
+    #[test]
+    fn test_forwarded_headers_need_a_trusted_proxy() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "6.6.6.6, 203.0.113.9, 10.1.2.3".parse().unwrap(),
+        );
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let direct: IpAddr = "198.51.100.1".parse().unwrap();
+
+        assert_eq!(forwarded_client(direct, &headers, &trusted), direct);
+        assert_eq!(
+            forwarded_client(proxy, &headers, &trusted),
+            "203.0.113.9".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(forwarded_client(proxy, &headers, &[]), proxy);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "203.0.113.10".parse().unwrap());
+        assert_eq!(
+            forwarded_client(proxy, &headers, &trusted),
+            "203.0.113.10".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            forwarded_client(
+                "::ffff:10.0.0.1".parse().unwrap(),
+                &HeaderMap::new(),
+                &trusted
+            ),
+            proxy
+        );
+    }
 }
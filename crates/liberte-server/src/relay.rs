@@ -1,26 +1,45 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use libp2p::{
-    identify, relay,
+    core::{muxing::StreamMuxerBox, transport::Transport},
+    identify,
+    identity::Keypair,
+    multiaddr::Protocol,
+    quic, relay, rendezvous,
     swarm::{NetworkBehaviour, SwarmEvent},
-    Multiaddr, PeerId, SwarmBuilder,
+    tcp, Multiaddr, PeerId, SwarmBuilder,
 };
+use tokio::sync::mpsc;
 use tracing::{debug, info, warn};
 
+use liberte_net::private_network::{secure_stream_transport, PreSharedKey};
 use liberte_shared::constants::PROTOCOL_VERSION;
 
+use crate::config::ServerConfig;
+use crate::metrics::{Outcome, RelayMetrics};
+use crate::relay_policy::RelayPolicy;
+
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "RelayServerEvent")]
 pub struct RelayServerBehaviour {
     pub relay: relay::Behaviour,
     pub identify: identify::Behaviour,
+    /// Lets clients register under opaque channel namespaces and find
+    /// each other without exchanging addresses out of band.
+    pub rendezvous: rendezvous::server::Behaviour,
 }
 
 #[derive(Debug)]
 pub enum RelayServerEvent {
     Relay(relay::Event),
     Identify(Box<identify::Event>),
+    Rendezvous(Box<rendezvous::server::Event>),
 }
 
 impl From<relay::Event> for RelayServerEvent {
@@ -35,20 +54,279 @@ impl From<identify::Event> for RelayServerEvent {
     }
 }
 
-pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
-    // TODO: persist keypair to disk for production
-    let keypair = libp2p::identity::Keypair::generate_ed25519();
+impl From<rendezvous::server::Event> for RelayServerEvent {
+    fn from(event: rendezvous::server::Event) -> Self {
+        RelayServerEvent::Rendezvous(Box::new(event))
+    }
+}
+
+/// Load the relay keypair from `path`, generating and saving a new one on
+/// first start. The file holds the protobuf encoding and is only readable
+/// by the owner; it is written to a temporary file first, so a crash
+/// mid-write can't leave a truncated key behind.
+pub fn load_or_create_keypair(path: &Path) -> anyhow::Result<Keypair> {
+    match std::fs::read(path) {
+        Ok(bytes) => Keypair::from_protobuf_encoding(&bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid relay key file {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let keypair = Keypair::generate_ed25519();
+            let bytes = keypair.to_protobuf_encoding()?;
+
+            let dir = key_dir(path);
+            std::fs::create_dir_all(dir)?;
+            let tmp = path.with_extension("tmp");
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            let mut file = options.open(&tmp)?;
+            std::io::Write::write_all(&mut file, &bytes)?;
+            file.sync_all()?;
+            std::fs::rename(&tmp, path)?;
+            // Make the rename itself durable
+            #[cfg(unix)]
+            std::fs::File::open(dir)?.sync_all()?;
+
+            info!(path = %path.display(), "Generated new relay keypair");
+            Ok(keypair)
+        }
+        Err(e) => Err(anyhow::anyhow!(
+            "Failed to read relay key file {}: {}",
+            path.display(),
+            e
+        )),
+    }
+}
+
+/// Directory holding the key file; a bare file name has an empty parent.
+fn key_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// A peer connected to the relay, as listed by the admin API.
+#[derive(Debug, Clone)]
+pub struct ConnectedPeer {
+    pub peer_id: PeerId,
+    pub addr: Multiaddr,
+    pub connected_at: DateTime<Utc>,
+    pub reserved: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveCircuit {
+    pub src: PeerId,
+    pub dst: PeerId,
+    pub opened_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+enum RelayCommand {
+    Disconnect(PeerId),
+}
+
+/// Relay identity, addresses and live state, shared with the HTTP API.
+#[derive(Clone)]
+pub struct RelayInfo {
+    pub peer_id: PeerId,
+    external_addrs: Vec<Multiaddr>,
+    listen_addrs: Arc<RwLock<Vec<Multiaddr>>>,
+    peers: Arc<RwLock<HashMap<PeerId, ConnectedPeer>>>,
+    circuits: Arc<RwLock<Vec<ActiveCircuit>>>,
+    commands: mpsc::UnboundedSender<RelayCommand>,
+}
+
+impl RelayInfo {
+    fn new(
+        peer_id: PeerId,
+        external_addrs: Vec<Multiaddr>,
+    ) -> (Self, mpsc::UnboundedReceiver<RelayCommand>) {
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        let info = Self {
+            peer_id,
+            external_addrs,
+            listen_addrs: Default::default(),
+            peers: Default::default(),
+            circuits: Default::default(),
+            commands,
+        };
+        (info, command_rx)
+    }
+
+    /// Info for a relay that isn't running; commands sent to it are dropped.
+    #[cfg(test)]
+    pub fn detached(peer_id: PeerId) -> Self {
+        Self::new(peer_id, Vec::new()).0
+    }
+
+    pub fn peers(&self) -> Vec<ConnectedPeer> {
+        read(&self.peers).values().cloned().collect()
+    }
+
+    pub fn circuits(&self) -> Vec<ActiveCircuit> {
+        read(&self.circuits).clone()
+    }
+
+    /// Close every connection to `peer`. Returns false if the relay task
+    /// has stopped.
+    pub fn disconnect(&self, peer: PeerId) -> bool {
+        self.commands.send(RelayCommand::Disconnect(peer)).is_ok()
+    }
+
+    /// Close every connection coming from `ip`.
+    pub fn disconnect_ip(&self, ip: IpAddr) {
+        for peer in self.peers() {
+            if crate::relay_policy::addr_ip(&peer.addr) == Some(ip) {
+                self.disconnect(peer.peer_id);
+            }
+        }
+    }
+
+    /// Addresses clients should dial, ending in `/p2p/<peer id>`: the
+    /// configured external addresses, or the listen addresses without them.
+    pub fn multiaddrs(&self) -> Vec<Multiaddr> {
+        let addrs = if self.external_addrs.is_empty() {
+            read(&self.listen_addrs).clone()
+        } else {
+            self.external_addrs.clone()
+        };
+        addrs
+            .into_iter()
+            .filter_map(|addr| addr.with_p2p(self.peer_id).ok())
+            .collect()
+    }
+
+    fn set_listen_addr(&self, addr: &Multiaddr, present: bool) {
+        let mut addrs = write(&self.listen_addrs);
+        addrs.retain(|a| a != addr);
+        if present {
+            addrs.push(addr.clone());
+        }
+    }
+
+    fn peer_connected(&self, peer_id: PeerId, addr: &Multiaddr) {
+        write(&self.peers)
+            .entry(peer_id)
+            .or_insert_with(|| ConnectedPeer {
+                peer_id,
+                addr: addr.clone(),
+                connected_at: Utc::now(),
+                reserved: false,
+            });
+    }
+
+    fn peer_disconnected(&self, peer_id: &PeerId) {
+        write(&self.peers).remove(peer_id);
+    }
+
+    /// Mark `peer_id` as holding a reservation (or not) and return the
+    /// number of reservations.
+    fn set_reserved(&self, peer_id: &PeerId, reserved: bool) -> usize {
+        let mut peers = write(&self.peers);
+        if let Some(peer) = peers.get_mut(peer_id) {
+            peer.reserved = reserved;
+        }
+        peers.values().filter(|p| p.reserved).count()
+    }
+
+    fn circuit_opened(&self, src: PeerId, dst: PeerId) {
+        write(&self.circuits).push(ActiveCircuit {
+            src,
+            dst,
+            opened_at: Utc::now(),
+        });
+    }
+
+    fn circuit_closed(&self, src: &PeerId, dst: &PeerId) {
+        let mut circuits = write(&self.circuits);
+        if let Some(pos) = circuits.iter().position(|c| c.src == *src && c.dst == *dst) {
+            circuits.remove(pos);
+        }
+    }
+}
+
+fn read<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Start the relay. With a swarm key the relay runs as a private network
+/// node: TCP only, and peers without the key are refused during the
+/// transport handshake. Reservations and circuits are admitted by `policy`
+/// and bounded by the limits in `config`; their activity is recorded in
+/// `metrics`.
+pub async fn spawn_relay(
+    config: &ServerConfig,
+    keypair: Keypair,
+    swarm_key: Option<PreSharedKey>,
+    policy: RelayPolicy,
+    metrics: RelayMetrics,
+) -> anyhow::Result<RelayInfo> {
+    let listen_addr = &config.listen_addr;
+    let multiaddr: Multiaddr = listen_addr
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid listen multiaddr '{}': {}", listen_addr, e))?;
+
+    if swarm_key.is_some() && multiaddr.iter().any(|p| matches!(p, Protocol::QuicV1)) {
+        anyhow::bail!(
+            "Private network mode needs a TCP listen address (e.g. /ip4/0.0.0.0/tcp/4001), \
+             QUIC cannot be protected by a swarm key"
+        );
+    }
+
     let local_peer_id = keypair.public().to_peer_id();
 
-    info!(peer_id = %local_peer_id, "Starting relay server");
+    info!(
+        peer_id = %local_peer_id,
+        private = swarm_key.is_some(),
+        "Starting relay server"
+    );
 
-    let mut swarm = SwarmBuilder::with_existing_identity(keypair.clone())
+    let mut swarm = SwarmBuilder::with_existing_identity(keypair)
         .with_tokio()
-        .with_quic()
+        .with_other_transport(
+            |key| -> Result<_, Box<dyn std::error::Error + Send + Sync>> {
+                let bandwidth = metrics.bandwidth.clone();
+                let transport = match swarm_key {
+                    Some(psk) => secure_stream_transport(
+                        tcp::tokio::Transport::new(tcp::Config::default().nodelay(true)),
+                        key,
+                        Some(psk),
+                    )?,
+                    None => quic::tokio::Transport::new(quic::Config::new(key))
+                        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)))
+                        .boxed(),
+                };
+                Ok(Transport::map(transport, move |(peer_id, muxer), _| {
+                    (peer_id, bandwidth.instrument(peer_id, muxer))
+                }))
+            },
+        )?
         .with_behaviour(|key| {
             let peer_id = key.public().to_peer_id();
 
-            let relay_config = relay::Config::default();
+            let mut relay_config = relay::Config {
+                max_circuits: config.relay_max_circuits,
+                max_circuit_duration: config.relay_max_circuit_duration,
+                max_circuit_bytes: config.relay_max_circuit_bytes,
+                ..Default::default()
+            };
+            if config.max_peers > 0 {
+                relay_config.max_reservations = config.max_peers;
+            }
+            relay_config
+                .reservation_rate_limiters
+                .push(policy.reservation_limiter());
+            relay_config
+                .circuit_src_rate_limiters
+                .push(policy.circuit_limiter());
             let relay_behaviour = relay::Behaviour::new(peer_id, relay_config);
 
             let identify_config = identify::Config::new(PROTOCOL_VERSION.to_string(), key.public())
@@ -56,32 +334,66 @@ pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
                 .with_interval(Duration::from_secs(60));
             let identify_behaviour = identify::Behaviour::new(identify_config);
 
+            let rendezvous_behaviour =
+                rendezvous::server::Behaviour::new(rendezvous::server::Config::default());
+
             Ok(RelayServerBehaviour {
                 relay: relay_behaviour,
                 identify: identify_behaviour,
+                rendezvous: rendezvous_behaviour,
             })
         })?
         .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(120)))
         .build();
 
-    let multiaddr: Multiaddr = listen_addr
-        .parse()
-        .map_err(|e| anyhow::anyhow!("Invalid listen multiaddr '{}': {}", listen_addr, e))?;
-
     swarm.listen_on(multiaddr.clone())?;
     info!(addr = %multiaddr, "Relay server listening");
 
+    // Announced through identify and in relay reservations
+    for addr in &config.external_addrs {
+        info!(addr = %addr, "Announcing external address");
+        swarm.add_external_address(addr.clone());
+    }
+
+    let (relay_info, mut command_rx) = RelayInfo::new(local_peer_id, config.external_addrs.clone());
+    let loop_info = relay_info.clone();
+
     tokio::spawn(async move {
         loop {
-            match swarm.select_next_some().await {
+            let event = tokio::select! {
+                event = swarm.select_next_some() => event,
+                Some(command) = command_rx.recv() => {
+                    match command {
+                        RelayCommand::Disconnect(peer_id) => {
+                            if swarm.disconnect_peer_id(peer_id).is_ok() {
+                                info!(peer = %peer_id, "Disconnecting peer on admin request");
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            // Reservations end silently when the holder disconnects, so the
+            // gauge is driven from the peer list rather than from events alone
+            match event {
                 SwarmEvent::Behaviour(RelayServerEvent::Relay(event)) => match &event {
                     relay::Event::ReservationReqAccepted { src_peer_id, .. } => {
                         info!(
                             peer = %src_peer_id,
                             "Relay reservation accepted"
                         );
+                        metrics.record_reservation(Outcome::Accepted);
+                        let reserved = loop_info.set_reserved(src_peer_id, true);
+                        metrics.reservations.set(reserved as i64);
+                    }
+                    relay::Event::ReservationReqDenied { src_peer_id, .. } => {
+                        info!(peer = %src_peer_id, "Relay reservation denied");
+                        metrics.record_reservation(Outcome::Denied);
                     }
                     relay::Event::ReservationTimedOut { src_peer_id, .. } => {
+                        let reserved = loop_info.set_reserved(src_peer_id, false);
+                        metrics.reservations.set(reserved as i64);
                         debug!(
                             peer = %src_peer_id,
                             "Relay reservation timed out"
@@ -97,6 +409,7 @@ pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
                             dst = %dst_peer_id,
                             "Circuit request denied"
                         );
+                        metrics.record_circuit(Outcome::Denied);
                     }
                     relay::Event::CircuitReqAccepted {
                         src_peer_id,
@@ -108,6 +421,9 @@ pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
                             dst = %dst_peer_id,
                             "Circuit relay established"
                         );
+                        metrics.record_circuit(Outcome::Accepted);
+                        metrics.circuits.inc();
+                        loop_info.circuit_opened(*src_peer_id, *dst_peer_id);
                     }
                     relay::Event::CircuitClosed {
                         src_peer_id,
@@ -119,6 +435,8 @@ pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
                             dst = %dst_peer_id,
                             "Circuit relay closed"
                         );
+                        metrics.circuits.dec();
+                        loop_info.circuit_closed(src_peer_id, dst_peer_id);
                     }
                     _ => {
                         debug!(event = ?event, "Relay event");
@@ -135,18 +453,71 @@ pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
                     }
                 }
 
+                SwarmEvent::Behaviour(RelayServerEvent::Rendezvous(event)) => match *event {
+                    rendezvous::server::Event::PeerRegistered { peer, registration } => {
+                        debug!(
+                            peer = %peer,
+                            namespace = %registration.namespace,
+                            ttl = registration.ttl,
+                            "Rendezvous registration"
+                        );
+                    }
+                    rendezvous::server::Event::PeerNotRegistered {
+                        peer,
+                        namespace,
+                        error,
+                    } => {
+                        debug!(
+                            peer = %peer,
+                            namespace = %namespace,
+                            error = ?error,
+                            "Rendezvous registration rejected"
+                        );
+                    }
+                    rendezvous::server::Event::DiscoverServed {
+                        enquirer,
+                        registrations,
+                    } => {
+                        debug!(
+                            peer = %enquirer,
+                            count = registrations.len(),
+                            "Rendezvous discovery served"
+                        );
+                    }
+                    other => {
+                        debug!(event = ?other, "Rendezvous event");
+                    }
+                },
+
                 SwarmEvent::NewListenAddr { address, .. } => {
                     info!(addr = %address, "Relay server listening on new address");
+                    loop_info.set_listen_addr(&address, true);
+                }
+
+                SwarmEvent::ExpiredListenAddr { address, .. } => {
+                    debug!(addr = %address, "Relay listen address expired");
+                    loop_info.set_listen_addr(&address, false);
                 }
 
                 SwarmEvent::ConnectionEstablished {
                     peer_id, endpoint, ..
                 } => {
+                    let addr = endpoint.get_remote_address();
                     debug!(
                         peer = %peer_id,
-                        addr = %endpoint.get_remote_address(),
+                        addr = %addr,
                         "Peer connected to relay"
                     );
+                    // Bans also cover identify and rendezvous, not just the relay
+                    if let Err(reason) = policy
+                        .admit_addr(addr)
+                        .and_then(|()| policy.admit_circuit(&peer_id))
+                    {
+                        debug!(peer = %peer_id, ?reason, "Dropping banned peer");
+                        let _ = swarm.disconnect_peer_id(peer_id);
+                        continue;
+                    }
+                    loop_info.peer_connected(peer_id, addr);
                 }
 
                 SwarmEvent::ConnectionClosed {
@@ -156,6 +527,10 @@ pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
                 } => {
                     if num_established == 0 {
                         debug!(peer = %peer_id, "Peer fully disconnected from relay");
+                        metrics.bandwidth.remove(&peer_id);
+                        let reserved = loop_info.set_reserved(&peer_id, false);
+                        metrics.reservations.set(reserved as i64);
+                        loop_info.peer_disconnected(&peer_id);
                     }
                 }
 
@@ -172,5 +547,57 @@ pub async fn spawn_relay(listen_addr: &str) -> anyhow::Result<PeerId> {
         }
     });
 
-    Ok(local_peer_id)
+    Ok(relay_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keypair_persists_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys").join("relay.key");
+
+        // Left over by a crash while writing a previous key
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path.with_extension("tmp"), b"trunc").unwrap();
+
+        let first = load_or_create_keypair(&path).unwrap();
+        let second = load_or_create_keypair(&path).unwrap();
+        assert_eq!(first.public().to_peer_id(), second.public().to_peer_id());
+        assert!(!path.with_extension("tmp").exists());
+
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(load_or_create_keypair(&path).is_err());
+    }
+
+    #[test]
+    fn test_key_dir_of_bare_file_name() {
+        assert_eq!(key_dir(Path::new("relay.key")), Path::new("."));
+        assert_eq!(key_dir(Path::new("./relay.key")), Path::new("."));
+        assert_eq!(key_dir(Path::new("/data/relay.key")), Path::new("/data"));
+    }
+
+    #[test]
+    fn test_multiaddrs_prefer_external() {
+        let peer_id = PeerId::random();
+        let listen: Multiaddr = "/ip4/10.0.0.2/udp/4001/quic-v1".parse().unwrap();
+        let (info, _) = RelayInfo::new(peer_id, Vec::new());
+        info.set_listen_addr(&listen, true);
+        assert_eq!(
+            info.multiaddrs(),
+            vec![listen.clone().with(Protocol::P2p(peer_id))]
+        );
+
+        let external: Multiaddr = "/dns4/relay.example.org/udp/4001/quic-v1".parse().unwrap();
+        let info = RelayInfo {
+            external_addrs: vec![external.clone()],
+            ..info
+        };
+        assert_eq!(
+            info.multiaddrs(),
+            vec![external.with(Protocol::P2p(peer_id))]
+        );
+    }
 }
@@ -0,0 +1,411 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use libp2p::{multiaddr::Protocol, relay, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::ServerConfig;
+
+/// Why a reservation or circuit was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenyReason {
+    Denylisted,
+    IpBanned,
+    RegistrationClosed,
+    /// No identity binding for the peer, or it expired.
+    Unbound,
+    NotPremium,
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    user_pubkey: [u8; 32],
+    expires_at: DateTime<Utc>,
+}
+
+/// Admission settings an admin can change at runtime.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdmissionSettings {
+    pub premium_required: bool,
+    pub registration_open: bool,
+}
+
+#[derive(Debug, Default)]
+struct PolicyState {
+    settings: AdmissionSettings,
+    allowlist: HashSet<PeerId>,
+    denylist: HashSet<PeerId>,
+    banned_ips: HashSet<IpAddr>,
+    /// Relay peer id -> identity that vouched for it (via a presence record).
+    bindings: HashMap<PeerId, Binding>,
+    /// Identity -> end of its verified premium period.
+    premium_until: HashMap<[u8; 32], DateTime<Utc>>,
+}
+
+/// Admission policy for relay reservations and circuits.
+///
+/// The relay only sees libp2p peer ids while premium is tied to identity
+/// keys, so clients first bind their peer id to their identity over HTTP.
+/// Checks run inside the relay's synchronous rate limiter hook, hence the
+/// std lock and the premium snapshot taken when a binding is made.
+#[derive(Clone)]
+pub struct RelayPolicy {
+    state: Arc<RwLock<PolicyState>>,
+}
+
+impl RelayPolicy {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(PolicyState {
+                settings: AdmissionSettings {
+                    premium_required: config.premium_required,
+                    registration_open: config.registration_open,
+                },
+                allowlist: config.relay_allowlist.iter().copied().collect(),
+                denylist: config.relay_denylist.iter().copied().collect(),
+                banned_ips: config.banned_ips.iter().copied().collect(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Decide whether `peer` may hold a reservation. Allowlisted peers skip
+    /// the registration and premium checks; the denylist always wins.
+    pub fn admit_reservation(&self, peer: &PeerId) -> Result<(), DenyReason> {
+        let state = self.read();
+        if state.denylist.contains(peer) {
+            return Err(DenyReason::Denylisted);
+        }
+        if state.allowlist.contains(peer) {
+            return Ok(());
+        }
+        if !state.settings.registration_open {
+            return Err(DenyReason::RegistrationClosed);
+        }
+        if !state.settings.premium_required {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let binding = state
+            .bindings
+            .get(peer)
+            .filter(|b| b.expires_at > now)
+            .ok_or(DenyReason::Unbound)?;
+        match state.premium_until.get(&binding.user_pubkey) {
+            Some(until) if *until > now => Ok(()),
+            _ => Err(DenyReason::NotPremium),
+        }
+    }
+
+    /// Circuits only need the source not to be denylisted: the destination
+    /// already passed the reservation checks, and its contacts need not be
+    /// premium to reach it.
+    pub fn admit_circuit(&self, src: &PeerId) -> Result<(), DenyReason> {
+        if self.read().denylist.contains(src) {
+            return Err(DenyReason::Denylisted);
+        }
+        Ok(())
+    }
+
+    /// Refuse connections coming from a banned IP, whatever the peer id.
+    pub fn admit_addr(&self, addr: &Multiaddr) -> Result<(), DenyReason> {
+        match addr_ip(addr) {
+            Some(ip) if self.is_ip_banned(&ip) => Err(DenyReason::IpBanned),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_ip_banned(&self, ip: &IpAddr) -> bool {
+        self.read().banned_ips.contains(ip)
+    }
+
+    pub fn settings(&self) -> AdmissionSettings {
+        self.read().settings
+    }
+
+    pub fn set_settings(&self, settings: AdmissionSettings) {
+        self.write().settings = settings;
+    }
+
+    /// Add `peer` to the denylist. Returns false if it was already there.
+    pub fn ban_peer(&self, peer: PeerId) -> bool {
+        self.write().denylist.insert(peer)
+    }
+
+    /// Remove `peer` from the denylist, including entries from the config
+    /// (until the next restart or reload).
+    pub fn unban_peer(&self, peer: &PeerId) -> bool {
+        self.write().denylist.remove(peer)
+    }
+
+    pub fn ban_ip(&self, ip: IpAddr) -> bool {
+        self.write().banned_ips.insert(ip)
+    }
+
+    pub fn unban_ip(&self, ip: &IpAddr) -> bool {
+        self.write().banned_ips.remove(ip)
+    }
+
+    /// Apply the lists of a reloaded config. The allowlist is replaced;
+    /// for the denylist and IP bans only the config's own entries change,
+    /// so bans added at runtime survive. Returns what is newly banned.
+    pub fn reload_lists(
+        &self,
+        old: &ServerConfig,
+        new: &ServerConfig,
+    ) -> (Vec<PeerId>, Vec<IpAddr>) {
+        let mut state = self.write();
+        state.allowlist = new.relay_allowlist.iter().copied().collect();
+
+        for peer in old
+            .relay_denylist
+            .iter()
+            .filter(|p| !new.relay_denylist.contains(p))
+        {
+            state.denylist.remove(peer);
+        }
+        let peers = new
+            .relay_denylist
+            .iter()
+            .filter(|p| state.denylist.insert(**p))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for ip in old
+            .banned_ips
+            .iter()
+            .filter(|ip| !new.banned_ips.contains(ip))
+        {
+            state.banned_ips.remove(ip);
+        }
+        let ips = new
+            .banned_ips
+            .iter()
+            .filter(|ip| state.banned_ips.insert(**ip))
+            .copied()
+            .collect();
+        (peers, ips)
+    }
+
+    pub fn banned_peers(&self) -> Vec<PeerId> {
+        self.read().denylist.iter().copied().collect()
+    }
+
+    pub fn banned_ips(&self) -> Vec<IpAddr> {
+        self.read().banned_ips.iter().copied().collect()
+    }
+
+    /// Record that `user_pubkey` vouched for `peer_id` until `expires_at`,
+    /// along with its premium status (if any) as verified by the caller.
+    pub fn bind(
+        &self,
+        peer_id: PeerId,
+        user_pubkey: [u8; 32],
+        expires_at: DateTime<Utc>,
+        premium_until: Option<DateTime<Utc>>,
+    ) {
+        let mut state = self.write();
+        state.bindings.insert(
+            peer_id,
+            Binding {
+                user_pubkey,
+                expires_at,
+            },
+        );
+        match premium_until {
+            Some(until) => state.premium_until.insert(user_pubkey, until),
+            None => state.premium_until.remove(&user_pubkey),
+        };
+    }
+
+    pub fn set_premium(&self, user_pubkey: [u8; 32], until: DateTime<Utc>) {
+        self.write().premium_until.insert(user_pubkey, until);
+    }
+
+    pub fn revoke_premium(&self, user_pubkey: &[u8; 32]) {
+        self.write().premium_until.remove(user_pubkey);
+    }
+
+    pub fn purge_expired(&self) {
+        let now = Utc::now();
+        let mut state = self.write();
+        state.bindings.retain(|_, b| b.expires_at > now);
+        state.premium_until.retain(|_, until| *until > now);
+    }
+
+    /// Hook for `relay::Config::reservation_rate_limiters`.
+    pub fn reservation_limiter(&self) -> Box<dyn relay::RateLimiter> {
+        let policy = self.clone();
+        Box::new(move |peer: PeerId, addr: &Multiaddr, _now| {
+            match policy
+                .admit_addr(addr)
+                .and_then(|()| policy.admit_reservation(&peer))
+            {
+                Ok(()) => true,
+                Err(reason) => {
+                    debug!(peer = %peer, ?reason, "Relay reservation refused by policy");
+                    false
+                }
+            }
+        })
+    }
+
+    /// Hook for `relay::Config::circuit_src_rate_limiters`.
+    pub fn circuit_limiter(&self) -> Box<dyn relay::RateLimiter> {
+        let policy = self.clone();
+        Box::new(move |peer: PeerId, addr: &Multiaddr, _now| {
+            match policy
+                .admit_addr(addr)
+                .and_then(|()| policy.admit_circuit(&peer))
+            {
+                Ok(()) => true,
+                Err(reason) => {
+                    debug!(peer = %peer, ?reason, "Relay circuit refused by policy");
+                    false
+                }
+            }
+        })
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, PolicyState> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, PolicyState> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// IP a connection comes from, if its address starts with one.
+pub fn addr_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    match addr.iter().next()? {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn policy(premium_required: bool, registration_open: bool) -> (RelayPolicy, PeerId, PeerId) {
+        let allowed = PeerId::random();
+        let denied = PeerId::random();
+        let config = ServerConfig {
+            premium_required,
+            registration_open,
+            relay_allowlist: vec![allowed],
+            relay_denylist: vec![denied],
+            ..Default::default()
+        };
+        (RelayPolicy::new(&config), allowed, denied)
+    }
+
+    #[test]
+    fn test_lists_and_registration() {
+        let (policy, allowed, denied) = policy(false, false);
+        assert_eq!(policy.admit_reservation(&allowed), Ok(()));
+        assert_eq!(
+            policy.admit_reservation(&denied),
+            Err(DenyReason::Denylisted)
+        );
+        assert_eq!(
+            policy.admit_reservation(&PeerId::random()),
+            Err(DenyReason::RegistrationClosed)
+        );
+        assert_eq!(policy.admit_circuit(&PeerId::random()), Ok(()));
+        assert_eq!(policy.admit_circuit(&denied), Err(DenyReason::Denylisted));
+    }
+
+    #[test]
+    fn test_runtime_bans_and_settings() {
+        let (policy, allowed, _) = policy(false, true);
+        let peer = PeerId::random();
+        assert_eq!(policy.admit_reservation(&peer), Ok(()));
+
+        policy.set_settings(AdmissionSettings {
+            premium_required: false,
+            registration_open: false,
+        });
+        assert_eq!(
+            policy.admit_reservation(&peer),
+            Err(DenyReason::RegistrationClosed)
+        );
+
+        assert!(policy.ban_peer(allowed));
+        assert!(!policy.ban_peer(allowed));
+        assert_eq!(
+            policy.admit_reservation(&allowed),
+            Err(DenyReason::Denylisted)
+        );
+        assert!(policy.unban_peer(&allowed));
+        assert_eq!(policy.admit_reservation(&allowed), Ok(()));
+
+        let addr: Multiaddr = "/ip4/203.0.113.7/udp/4001/quic-v1".parse().unwrap();
+        assert_eq!(policy.admit_addr(&addr), Ok(()));
+        policy.ban_ip("203.0.113.7".parse().unwrap());
+        assert_eq!(policy.admit_addr(&addr), Err(DenyReason::IpBanned));
+        assert_eq!(
+            policy.admit_addr(&"/dns4/example.org/tcp/1".parse().unwrap()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_reload_lists() {
+        let (policy, allowed, denied) = policy(false, false);
+        let old = ServerConfig {
+            relay_allowlist: vec![allowed],
+            relay_denylist: vec![denied],
+            ..Default::default()
+        };
+        let runtime = PeerId::random();
+        policy.ban_peer(runtime);
+
+        let peer = PeerId::random();
+        let ip: IpAddr = "198.51.100.4".parse().unwrap();
+        let new = ServerConfig {
+            relay_denylist: vec![peer],
+            banned_ips: vec![ip],
+            ..Default::default()
+        };
+        assert_eq!(policy.reload_lists(&old, &new), (vec![peer], vec![ip]));
+        assert_eq!(
+            policy.admit_reservation(&allowed),
+            Err(DenyReason::RegistrationClosed)
+        );
+        assert_eq!(policy.admit_circuit(&denied), Ok(()));
+        assert_eq!(policy.admit_circuit(&runtime), Err(DenyReason::Denylisted));
+        assert!(policy.is_ip_banned(&ip));
+
+        assert_eq!(policy.reload_lists(&new, &new), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_premium_follows_binding() {
+        let (policy, _, _) = policy(true, true);
+        let peer = PeerId::random();
+        let user = [7u8; 32];
+        let later = Utc::now() + Duration::hours(1);
+
+        assert_eq!(policy.admit_reservation(&peer), Err(DenyReason::Unbound));
+
+        policy.bind(peer, user, later, None);
+        assert_eq!(policy.admit_reservation(&peer), Err(DenyReason::NotPremium));
+
+        policy.set_premium(user, later);
+        assert_eq!(policy.admit_reservation(&peer), Ok(()));
+
+        policy.revoke_premium(&user);
+        assert_eq!(policy.admit_reservation(&peer), Err(DenyReason::NotPremium));
+
+        policy.bind(peer, user, Utc::now() - Duration::seconds(1), Some(later));
+        assert_eq!(policy.admit_reservation(&peer), Err(DenyReason::Unbound));
+    }
+}
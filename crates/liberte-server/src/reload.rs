@@ -0,0 +1,82 @@
+use tracing::{info, warn};
+
+use crate::api::AppState;
+use crate::config::ServerConfig;
+
+/// Apply the settings of a reloaded config that are safe to change live.
+///
+/// Only what differs between `old` and `new` is touched, so changes made at
+/// runtime through the admin API stay in effect unless the file changed the
+/// same setting.
+pub fn apply(state: &AppState, old: &ServerConfig, new: &ServerConfig) {
+    if old.rate_limits != new.rate_limits {
+        state.rate_limiter.set_limits(new.rate_limits);
+        info!(limits = ?new.rate_limits, "Rate limits updated");
+    }
+    if old.trusted_proxies != new.trusted_proxies {
+        state
+            .rate_limiter
+            .set_trusted_proxies(new.trusted_proxies.clone());
+        info!(proxies = ?new.trusted_proxies, "Trusted proxies updated");
+    }
+
+    let mut settings = state.relay_policy.settings();
+    if old.premium_required != new.premium_required {
+        settings.premium_required = new.premium_required;
+    }
+    if old.registration_open != new.registration_open {
+        settings.registration_open = new.registration_open;
+    }
+    if settings != state.relay_policy.settings() {
+        state.relay_policy.set_settings(settings);
+        info!(
+            premium_required = settings.premium_required,
+            registration_open = settings.registration_open,
+            "Admission settings updated"
+        );
+    }
+
+    let (peers, ips) = state.relay_policy.reload_lists(old, new);
+    for peer in &peers {
+        state.relay.disconnect(*peer);
+    }
+    for ip in &ips {
+        state.relay.disconnect_ip(*ip);
+    }
+    if !peers.is_empty() || !ips.is_empty() {
+        info!(peers = peers.len(), ips = ips.len(), "New bans applied");
+    }
+
+    let pending = old.restart_required(new);
+    if !pending.is_empty() {
+        warn!(
+            ?pending,
+            "Some changed settings only take effect after a restart"
+        );
+    }
+}
+
+/// Reload the config on SIGHUP, keeping the current one if the file became
+/// invalid.
+#[cfg(unix)]
+pub fn spawn_on_sighup(state: AppState, path: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        let mut current = (*state.config).clone();
+        while hangup.recv().await.is_some() {
+            match ServerConfig::load(path.as_deref()) {
+                Ok(new) => {
+                    apply(&state, &current, &new);
+                    info!("Configuration reloaded");
+                    current = new;
+                }
+                Err(e) => {
+                    warn!(error = %e, "Configuration reload failed, keeping current settings")
+                }
+            }
+        }
+    });
+    Ok(())
+}
@@ -1,135 +1,219 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use libp2p::PeerId;
+use bytes::Bytes;
+use thiserror::Error;
 use tokio::sync::{mpsc, RwLock};
-use tracing::{debug, info, warn};
+use tracing::{debug, info};
 use uuid::Uuid;
 
+/// Identity key of a participant.
+pub type Participant = [u8; 32];
+
 /// Opaque encrypted media frame -- the SFU never decrypts this.
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct EncryptedFrame {
-    pub sender: PeerId,
-    pub payload: Vec<u8>,
+    pub room_id: Uuid,
+    pub sender: Participant,
+    pub payload: Bytes,
+}
+
+/// What a participant's session receives from the rooms it joined.
+#[derive(Debug, Clone)]
+pub enum RoomEvent {
+    Frame(EncryptedFrame),
+    Joined {
+        room_id: Uuid,
+        participant: Participant,
+    },
+    Left {
+        room_id: Uuid,
+        participant: Participant,
+    },
+    /// The room was closed for inactivity.
+    Closed {
+        room_id: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum SfuError {
+    #[error("Room is full")]
+    RoomFull,
+    #[error("Already in this room")]
+    AlreadyJoined,
+    #[error("Not in this room")]
+    NotInRoom,
 }
 
-#[allow(dead_code)]
 pub struct SfuRoom {
     pub room_id: Uuid,
-    participants: HashSet<PeerId>,
-    senders: HashMap<PeerId, mpsc::Sender<EncryptedFrame>>,
+    senders: HashMap<Participant, mpsc::Sender<RoomEvent>>,
+    /// Last join or frame; rooms silent for too long are closed.
+    last_activity: std::sync::Mutex<Instant>,
 }
 
-#[allow(dead_code)]
 impl SfuRoom {
     pub fn new(room_id: Uuid) -> Self {
         Self {
             room_id,
-            participants: HashSet::new(),
             senders: HashMap::new(),
+            last_activity: std::sync::Mutex::new(Instant::now()),
         }
     }
 
-    pub fn join(&mut self, peer_id: PeerId) -> mpsc::Receiver<EncryptedFrame> {
-        let (tx, rx) = mpsc::channel::<EncryptedFrame>(256);
-        self.participants.insert(peer_id);
-        self.senders.insert(peer_id, tx);
+    /// Add `participant`, whose events go to `events`, and tell the others.
+    pub fn join(
+        &mut self,
+        participant: Participant,
+        events: mpsc::Sender<RoomEvent>,
+        max_participants: usize,
+    ) -> Result<(), SfuError> {
+        if self.senders.contains_key(&participant) {
+            return Err(SfuError::AlreadyJoined);
+        }
+        if self.senders.len() >= max_participants {
+            return Err(SfuError::RoomFull);
+        }
+        self.broadcast(
+            &participant,
+            RoomEvent::Joined {
+                room_id: self.room_id,
+                participant,
+            },
+        );
+        self.senders.insert(participant, events);
+        self.touch();
 
         info!(
             room = %self.room_id,
-            peer = %peer_id,
-            participants = self.participants.len(),
+            participant = %hex::encode(participant),
+            participants = self.senders.len(),
             "Participant joined SFU room"
         );
-
-        rx
+        Ok(())
     }
 
-    pub fn leave(&mut self, peer_id: &PeerId) {
-        self.participants.remove(peer_id);
-        self.senders.remove(peer_id);
+    pub fn leave(&mut self, participant: &Participant) {
+        if self.senders.remove(participant).is_none() {
+            return;
+        }
+        self.broadcast(
+            participant,
+            RoomEvent::Left {
+                room_id: self.room_id,
+                participant: *participant,
+            },
+        );
 
         info!(
             room = %self.room_id,
-            peer = %peer_id,
-            participants = self.participants.len(),
+            participant = %hex::encode(participant),
+            participants = self.senders.len(),
             "Participant left SFU room"
         );
     }
 
-    /// Forward an encrypted frame to everyone except the sender.
-    pub async fn route_frame(&self, frame: EncryptedFrame) {
+    /// Forward an encrypted frame to everyone except the sender, who must
+    /// have joined.
+    pub fn route_frame(&self, frame: EncryptedFrame) -> Result<(), SfuError> {
+        if !self.senders.contains_key(&frame.sender) {
+            return Err(SfuError::NotInRoom);
+        }
+        self.touch();
         let sender = frame.sender;
+        self.broadcast(&sender, RoomEvent::Frame(frame));
+        Ok(())
+    }
 
-        for (peer_id, tx) in &self.senders {
-            if *peer_id == sender {
+    /// Send `event` to everyone but `from`, dropping it for participants
+    /// too slow to keep up.
+    fn broadcast(&self, from: &Participant, event: RoomEvent) {
+        for (participant, tx) in &self.senders {
+            if participant == from {
                 continue;
             }
 
-            if tx.try_send(frame.clone()).is_err() {
+            if tx.try_send(event.clone()).is_err() {
                 debug!(
                     room = %self.room_id,
-                    target = %peer_id,
-                    "Dropping frame for slow participant"
+                    target = %hex::encode(participant),
+                    "Dropping event for slow participant"
                 );
             }
         }
     }
 
-    pub fn participants(&self) -> &HashSet<PeerId> {
-        &self.participants
+    fn touch(&self) {
+        *self.last_activity.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_activity
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed()
+    }
+
+    pub fn participants(&self) -> Vec<Participant> {
+        self.senders.keys().copied().collect()
     }
 
     pub fn participant_count(&self) -> usize {
-        self.participants.len()
+        self.senders.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.participants.is_empty()
+        self.senders.is_empty()
     }
 }
 
+/// Rooms are created by their first participant and identified by a UUID
+/// the clients pick; knowing it is enough to join, the media itself being
+/// end-to-end encrypted.
 #[derive(Clone)]
-#[allow(dead_code)]
 pub struct SfuManager {
     rooms: Arc<RwLock<HashMap<Uuid, SfuRoom>>>,
+    max_participants: usize,
 }
 
-#[allow(dead_code)]
 impl SfuManager {
-    pub fn new() -> Self {
+    pub fn new(max_participants: usize) -> Self {
         Self {
             rooms: Arc::new(RwLock::new(HashMap::new())),
+            max_participants,
         }
     }
 
-    pub async fn create_room(&self) -> Uuid {
-        let room_id = Uuid::new_v4();
-        let room = SfuRoom::new(room_id);
-        self.rooms.write().await.insert(room_id, room);
-        info!(room = %room_id, "Created SFU room");
-        room_id
-    }
-
-    /// Join a room (creates it if missing). Returns the frame receiver.
+    /// Join a room (creates it if missing). Returns who was already there.
     pub async fn join_room(
         &self,
         room_id: Uuid,
-        peer_id: PeerId,
-    ) -> mpsc::Receiver<EncryptedFrame> {
+        participant: Participant,
+        events: mpsc::Sender<RoomEvent>,
+    ) -> Result<Vec<Participant>, SfuError> {
         let mut rooms = self.rooms.write().await;
         let room = rooms
             .entry(room_id)
             .or_insert_with(|| SfuRoom::new(room_id));
-        room.join(peer_id)
+        let others = room.participants();
+        match room.join(participant, events, self.max_participants) {
+            Ok(()) => Ok(others),
+            Err(e) => {
+                if room.is_empty() {
+                    rooms.remove(&room_id);
+                }
+                Err(e)
+            }
+        }
     }
 
     /// Leave a room. Auto-deletes the room if it becomes empty.
-    pub async fn leave_room(&self, room_id: &Uuid, peer_id: &PeerId) {
+    pub async fn leave_room(&self, room_id: &Uuid, participant: &Participant) {
         let mut rooms = self.rooms.write().await;
         let should_remove = if let Some(room) = rooms.get_mut(room_id) {
-            room.leave(peer_id);
+            room.leave(participant);
             room.is_empty()
         } else {
             false
@@ -141,19 +225,61 @@ impl SfuManager {
         }
     }
 
-    pub async fn route_frame(&self, room_id: &Uuid, frame: EncryptedFrame) {
+    /// Whether `participant` is in the room through `events`, which fails
+    /// once the room was closed even if its [`RoomEvent::Closed`] was lost.
+    pub async fn is_joined(
+        &self,
+        room_id: &Uuid,
+        participant: &Participant,
+        events: &mpsc::Sender<RoomEvent>,
+    ) -> bool {
+        self.rooms
+            .read()
+            .await
+            .get(room_id)
+            .and_then(|room| room.senders.get(participant))
+            .is_some_and(|tx| tx.same_channel(events))
+    }
+
+    pub async fn route_frame(&self, frame: EncryptedFrame) -> Result<(), SfuError> {
         let rooms = self.rooms.read().await;
-        if let Some(room) = rooms.get(room_id) {
-            room.route_frame(frame).await;
-        } else {
-            warn!(room = %room_id, "Attempted to route frame in non-existent room");
+        match rooms.get(&frame.room_id) {
+            Some(room) => room.route_frame(frame),
+            None => Err(SfuError::NotInRoom),
         }
     }
 
+    /// Close rooms without a join or frame for `max_idle`, telling their
+    /// participants. Returns how many were closed.
+    pub async fn purge_idle(&self, max_idle: Duration) -> usize {
+        let mut rooms = self.rooms.write().await;
+        let before = rooms.len();
+        rooms.retain(|room_id, room| {
+            if room.idle_for() < max_idle {
+                return true;
+            }
+            for tx in room.senders.values() {
+                let _ = tx.try_send(RoomEvent::Closed { room_id: *room_id });
+            }
+            info!(room = %room_id, "Closed idle SFU room");
+            false
+        });
+        before - rooms.len()
+    }
+
+    #[cfg(test)]
     pub async fn list_rooms(&self) -> Vec<Uuid> {
         self.rooms.read().await.keys().copied().collect()
     }
 
+    /// Open rooms and participants across all of them.
+    pub async fn stats(&self) -> (usize, usize) {
+        let rooms = self.rooms.read().await;
+        let participants = rooms.values().map(|r| r.participant_count()).sum();
+        (rooms.len(), participants)
+    }
+
+    #[cfg(test)]
     pub async fn participant_count(&self, room_id: &Uuid) -> usize {
         self.rooms
             .read()
@@ -166,7 +292,7 @@ impl SfuManager {
 
 impl Default for SfuManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(16)
     }
 }
 
@@ -176,36 +302,88 @@ mod tests {
 
     #[tokio::test]
     async fn test_room_join_leave() {
-        let manager = SfuManager::new();
-        let room_id = manager.create_room().await;
-        let peer = PeerId::random();
+        let manager = SfuManager::default();
+        let room_id = Uuid::new_v4();
+        let (tx, _rx) = mpsc::channel(8);
 
-        let _rx = manager.join_room(room_id, peer).await;
+        assert!(manager
+            .join_room(room_id, [1; 32], tx.clone())
+            .await
+            .unwrap()
+            .is_empty());
         assert_eq!(manager.participant_count(&room_id).await, 1);
+        assert_eq!(
+            manager.join_room(room_id, [1; 32], tx).await,
+            Err(SfuError::AlreadyJoined)
+        );
 
-        manager.leave_room(&room_id, &peer).await;
+        manager.leave_room(&room_id, &[1; 32]).await;
         assert_eq!(manager.list_rooms().await.len(), 0);
     }
 
     #[tokio::test]
     async fn test_frame_routing() {
-        let manager = SfuManager::new();
-        let room_id = manager.create_room().await;
+        let manager = SfuManager::default();
+        let room_id = Uuid::new_v4();
 
-        let sender = PeerId::random();
-        let receiver = PeerId::random();
+        let sender = [1; 32];
+        let (sender_tx, mut sender_rx) = mpsc::channel(8);
+        let (receiver_tx, mut receiver_rx) = mpsc::channel(8);
 
-        let _sender_rx = manager.join_room(room_id, sender).await;
-        let mut receiver_rx = manager.join_room(room_id, receiver).await;
+        manager.join_room(room_id, sender, sender_tx).await.unwrap();
+        assert_eq!(
+            manager.join_room(room_id, [2; 32], receiver_tx).await,
+            Ok(vec![sender])
+        );
+        assert!(matches!(
+            sender_rx.try_recv(),
+            Ok(RoomEvent::Joined { participant, .. }) if participant == [2; 32]
+        ));
 
         let frame = EncryptedFrame {
+            room_id,
             sender,
-            payload: vec![0xDE, 0xAD, 0xBE, 0xEF],
+            payload: Bytes::from_static(&[0xDE, 0xAD, 0xBE, 0xEF]),
         };
+        manager.route_frame(frame.clone()).await.unwrap();
 
-        manager.route_frame(&room_id, frame.clone()).await;
+        let Ok(RoomEvent::Frame(received)) = receiver_rx.try_recv() else {
+            panic!("expected a frame");
+        };
+        assert_eq!(received.payload, frame.payload);
+        assert!(sender_rx.try_recv().is_err());
+
+        let outsider = EncryptedFrame {
+            sender: [3; 32],
+            ..frame
+        };
+        assert_eq!(
+            manager.route_frame(outsider).await,
+            Err(SfuError::NotInRoom)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_room_limit_and_idle_cleanup() {
+        let manager = SfuManager::new(1);
+        let room_id = Uuid::new_v4();
+        let (tx, mut rx) = mpsc::channel(8);
+
+        manager
+            .join_room(room_id, [1; 32], tx.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.join_room(room_id, [2; 32], tx).await,
+            Err(SfuError::RoomFull)
+        );
 
-        let received = receiver_rx.try_recv().unwrap();
-        assert_eq!(received.payload, vec![0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(manager.purge_idle(Duration::from_secs(60)).await, 0);
+        assert_eq!(manager.purge_idle(Duration::ZERO).await, 1);
+        assert!(manager.list_rooms().await.is_empty());
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(RoomEvent::Closed { room_id: id }) if id == room_id
+        ));
     }
 }
@@ -0,0 +1,560 @@
+//! WebSocket transport of the SFU, at `GET /sfu`.
+//!
+//! The upgrade request is signed like the other identity routes, so a
+//! session belongs to one identity key. Control messages are JSON text
+//! ([`ClientMessage`], [`ServerMessage`]); media frames are binary: the
+//! 16-byte room id then the encrypted payload from the client, and the
+//! room id, the 32-byte sender key then the payload towards it.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    Extension,
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::api::AppState;
+use crate::auth::Caller;
+use crate::error::ServerError;
+use crate::sfu::{EncryptedFrame, Participant, RoomEvent};
+
+/// Rooms one session may be in at once.
+const MAX_ROOMS_PER_SESSION: usize = 4;
+
+/// Events queued for a session before new ones are dropped.
+const EVENT_QUEUE: usize = 256;
+
+/// Sessions are pinged this often, and closed when nothing came back for
+/// three intervals.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+const ROOM_ID_LEN: usize = 16;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Join { room_id: Uuid },
+    Leave { room_id: Uuid },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    /// Answer to a join, with who was already in the room.
+    Joined {
+        room_id: Uuid,
+        participants: Vec<String>,
+    },
+    Left {
+        room_id: Uuid,
+    },
+    ParticipantJoined {
+        room_id: Uuid,
+        pubkey: String,
+    },
+    ParticipantLeft {
+        room_id: Uuid,
+        pubkey: String,
+    },
+    /// Closed for inactivity; join again to resume.
+    RoomClosed {
+        room_id: Uuid,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        room_id: Option<Uuid>,
+        message: String,
+    },
+}
+
+/// Open an SFU session for the signing identity, premium if the instance
+/// requires it.
+pub async fn sfu_connect(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ServerError> {
+    let Caller::User(pubkey) = caller else {
+        return Err(ServerError::Forbidden(
+            "SFU sessions must be signed with an identity key".into(),
+        ));
+    };
+    if !may_join(&state, &pubkey).await {
+        return Err(ServerError::Forbidden(
+            "Premium required for SFU calls".into(),
+        ));
+    }
+
+    let max_message = ROOM_ID_LEN + state.config.sfu_max_frame_size;
+    Ok(ws
+        .max_message_size(max_message)
+        .on_upgrade(move |socket| session(state, pubkey, socket)))
+}
+
+async fn may_join(state: &AppState, pubkey: &Participant) -> bool {
+    !state.relay_policy.settings().premium_required
+        || state.premium_verifier.is_premium_cached(pubkey).await
+}
+
+async fn session(state: AppState, pubkey: Participant, socket: WebSocket) {
+    let participant = hex::encode(pubkey);
+    info!(%participant, "SFU session opened");
+
+    let (mut sink, mut stream) = socket.split();
+    let (events_tx, mut events) = mpsc::channel(EVENT_QUEUE);
+    let mut session = Session {
+        state: &state,
+        pubkey,
+        events: events_tx,
+        rooms: HashSet::new(),
+    };
+    let mut ping =
+        tokio::time::interval_at(tokio::time::Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    let mut last_seen = Instant::now();
+
+    loop {
+        let reply = tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(message)) => {
+                    last_seen = Instant::now();
+                    session.handle(message).await
+                }
+            },
+            Some(event) = events.recv() => Some(session.event(event)),
+            _ = ping.tick() => {
+                if last_seen.elapsed() > 3 * PING_INTERVAL {
+                    debug!(%participant, "SFU session timed out");
+                    break;
+                }
+                Some(Message::Ping(Vec::new()))
+            }
+        };
+        if let Some(reply) = reply {
+            if sink.send(reply).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    for room_id in std::mem::take(&mut session.rooms) {
+        session.leave(&room_id).await;
+    }
+    info!(%participant, "SFU session closed");
+}
+
+struct Session<'a> {
+    state: &'a AppState,
+    pubkey: Participant,
+    /// Handed to every joined room; all of them feed the same socket.
+    events: mpsc::Sender<RoomEvent>,
+    rooms: HashSet<Uuid>,
+}
+
+impl Session<'_> {
+    /// Act on a message from the client, returning the answer if any.
+    async fn handle(&mut self, message: Message) -> Option<Message> {
+        let reply = match message {
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(ClientMessage::Join { room_id }) => self.join(room_id).await,
+                Ok(ClientMessage::Leave { room_id }) => {
+                    if !self.rooms.remove(&room_id) {
+                        return Some(error(Some(room_id), "Not in this room"));
+                    }
+                    self.leave(&room_id).await;
+                    ServerMessage::Left { room_id }
+                }
+                Err(e) => ServerMessage::Error {
+                    room_id: None,
+                    message: format!("Invalid message: {e}"),
+                },
+            },
+            Message::Binary(data) => return self.frame(data).await,
+            // Pings are answered by axum, pongs only count as activity
+            _ => return None,
+        };
+        Some(json(&reply))
+    }
+
+    async fn join(&mut self, room_id: Uuid) -> ServerMessage {
+        if self.rooms.len() >= MAX_ROOMS_PER_SESSION {
+            self.forget_closed_rooms().await;
+        }
+        if self.rooms.len() >= MAX_ROOMS_PER_SESSION && !self.rooms.contains(&room_id) {
+            return ServerMessage::Error {
+                room_id: Some(room_id),
+                message: format!("At most {MAX_ROOMS_PER_SESSION} rooms per session"),
+            };
+        }
+        // Premium may have been revoked since the session opened
+        if !may_join(self.state, &self.pubkey).await {
+            return ServerMessage::Error {
+                room_id: Some(room_id),
+                message: "Premium required for SFU calls".into(),
+            };
+        }
+        match self
+            .state
+            .sfu
+            .join_room(room_id, self.pubkey, self.events.clone())
+            .await
+        {
+            Ok(participants) => {
+                self.rooms.insert(room_id);
+                ServerMessage::Joined {
+                    room_id,
+                    participants: participants.iter().map(hex::encode).collect(),
+                }
+            }
+            Err(e) => ServerMessage::Error {
+                room_id: Some(room_id),
+                message: e.to_string(),
+            },
+        }
+    }
+
+    /// Leave `room_id` unless it was closed meanwhile, possibly reopened
+    /// by others under the same id.
+    async fn leave(&self, room_id: &Uuid) {
+        let sfu = &self.state.sfu;
+        if sfu.is_joined(room_id, &self.pubkey, &self.events).await {
+            sfu.leave_room(room_id, &self.pubkey).await;
+        }
+    }
+
+    /// Drop the rooms closed without their [`RoomEvent::Closed`] reaching
+    /// us, so they no longer count toward [`MAX_ROOMS_PER_SESSION`].
+    async fn forget_closed_rooms(&mut self) {
+        let mut closed = Vec::new();
+        for room_id in &self.rooms {
+            if !self
+                .state
+                .sfu
+                .is_joined(room_id, &self.pubkey, &self.events)
+                .await
+            {
+                closed.push(*room_id);
+            }
+        }
+        for room_id in closed {
+            self.rooms.remove(&room_id);
+        }
+    }
+
+    async fn frame(&mut self, data: Vec<u8>) -> Option<Message> {
+        if data.len() < ROOM_ID_LEN {
+            return Some(error(None, "Frame too short"));
+        }
+        let mut payload = Bytes::from(data);
+        let room_id = Uuid::from_slice(&payload.split_to(ROOM_ID_LEN)).ok()?;
+        let frame = EncryptedFrame {
+            room_id,
+            sender: self.pubkey,
+            payload,
+        };
+        match self.state.sfu.route_frame(frame).await {
+            Ok(()) => None,
+            Err(e) => Some(error(Some(room_id), &e.to_string())),
+        }
+    }
+
+    /// A room event as sent to the client.
+    fn event(&mut self, event: RoomEvent) -> Message {
+        let message = match event {
+            RoomEvent::Frame(frame) => {
+                let mut data = Vec::with_capacity(ROOM_ID_LEN + 32 + frame.payload.len());
+                data.extend_from_slice(frame.room_id.as_bytes());
+                data.extend_from_slice(&frame.sender);
+                data.extend_from_slice(&frame.payload);
+                return Message::Binary(data);
+            }
+            RoomEvent::Joined {
+                room_id,
+                participant,
+            } => ServerMessage::ParticipantJoined {
+                room_id,
+                pubkey: hex::encode(participant),
+            },
+            RoomEvent::Left {
+                room_id,
+                participant,
+            } => ServerMessage::ParticipantLeft {
+                room_id,
+                pubkey: hex::encode(participant),
+            },
+            RoomEvent::Closed { room_id } => {
+                self.rooms.remove(&room_id);
+                ServerMessage::RoomClosed { room_id }
+            }
+        };
+        json(&message)
+    }
+}
+
+fn json(message: &ServerMessage) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap_or_default())
+}
+
+fn error(room_id: Option<Uuid>, message: &str) -> Message {
+    json(&ServerMessage::Error {
+        room_id,
+        message: message.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use liberte_shared::identity::Identity;
+    use liberte_shared::request_auth::{
+        RequestSignature, HEADER_NONCE, HEADER_PUBKEY, HEADER_SIGNATURE, HEADER_TIMESTAMP,
+    };
+    use serde_json::json;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+
+    use super::*;
+    use crate::relay_policy::AdmissionSettings;
+
+    type Client = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn serve(state: AppState) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::api::build_router(state);
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+        addr
+    }
+
+    /// Open `/sfu`, signed by `identity` if any, or return the refusal status.
+    async fn connect(addr: SocketAddr, identity: Option<&Identity>) -> Result<Client, u16> {
+        let mut request = format!("ws://{addr}/sfu").into_client_request().unwrap();
+        if let Some(identity) = identity {
+            let signature = RequestSignature::sign(
+                identity,
+                "GET",
+                "/sfu",
+                b"",
+                chrono::Utc::now().timestamp(),
+            );
+            let headers = request.headers_mut();
+            for (name, value) in [
+                HEADER_PUBKEY,
+                HEADER_TIMESTAMP,
+                HEADER_NONCE,
+                HEADER_SIGNATURE,
+            ]
+            .into_iter()
+            .zip(signature.header_values())
+            {
+                headers.insert(name, value.parse().unwrap());
+            }
+        }
+        match tokio_tungstenite::connect_async(request).await {
+            Ok((socket, _)) => Ok(socket),
+            Err(tungstenite::Error::Http(response)) => Err(response.status().as_u16()),
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    async fn send(socket: &mut Client, message: serde_json::Value) {
+        socket
+            .send(tungstenite::Message::Text(message.to_string()))
+            .await
+            .unwrap();
+    }
+
+    /// Next text or binary message, skipping pings.
+    async fn next(socket: &mut Client) -> tungstenite::Message {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            if message.is_text() || message.is_binary() {
+                return message;
+            }
+        }
+    }
+
+    async fn next_json(socket: &mut Client) -> serde_json::Value {
+        match next(socket).await {
+            tungstenite::Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("expected text, got {message:?}"),
+        }
+    }
+
+    #[test]
+    fn test_message_format() {
+        let room_id = Uuid::new_v4();
+        let join: ClientMessage =
+            serde_json::from_str(&format!(r#"{{"type":"join","room_id":"{room_id}"}}"#)).unwrap();
+        assert!(matches!(join, ClientMessage::Join { room_id: id } if id == room_id));
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type":"dance"}"#).is_err());
+
+        let Message::Text(text) = json(&ServerMessage::ParticipantJoined {
+            room_id,
+            pubkey: hex::encode([0xab; 32]),
+        }) else {
+            panic!("expected text");
+        };
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["type"], "participant_joined");
+        assert_eq!(value["room_id"], room_id.to_string());
+
+        let Message::Text(text) = error(None, "Frame too short") else {
+            panic!("expected text");
+        };
+        assert_eq!(text, r#"{"type":"error","message":"Frame too short"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::for_tests(dir.path()).await;
+        state.relay_policy.set_settings(AdmissionSettings {
+            premium_required: true,
+            registration_open: true,
+        });
+        let (alice, bob, carol) = (
+            Identity::generate(),
+            Identity::generate(),
+            Identity::generate(),
+        );
+        for identity in [&alice, &bob] {
+            state
+                .premium_verifier
+                .admin_grant(&identity.public_key_bytes(), None, None, None)
+                .await
+                .unwrap();
+        }
+        let addr = serve(state.clone()).await;
+
+        assert_eq!(connect(addr, None).await.err(), Some(401));
+        assert_eq!(connect(addr, Some(&carol)).await.err(), Some(403));
+
+        let mut a = connect(addr, Some(&alice)).await.unwrap();
+        let mut b = connect(addr, Some(&bob)).await.unwrap();
+        let room_id = Uuid::new_v4();
+        send(&mut a, json!({"type": "join", "room_id": room_id})).await;
+        let joined = next_json(&mut a).await;
+        assert_eq!(joined["type"], "joined");
+        assert_eq!(joined["participants"], json!([]));
+        send(&mut b, json!({"type": "join", "room_id": room_id})).await;
+        let joined = next_json(&mut b).await;
+        assert_eq!(
+            joined["participants"],
+            json!([hex::encode(alice.public_key_bytes())])
+        );
+        let event = next_json(&mut a).await;
+        assert_eq!(event["type"], "participant_joined");
+        assert_eq!(event["pubkey"], hex::encode(bob.public_key_bytes()));
+
+        // Bob's frame reaches Alice behind the room id and his key
+        let frame = [room_id.as_bytes().as_slice(), b"media"].concat();
+        b.send(tungstenite::Message::Binary(frame)).await.unwrap();
+        let expected = [
+            room_id.as_bytes().as_slice(),
+            &bob.public_key_bytes(),
+            b"media",
+        ]
+        .concat();
+        assert_eq!(next(&mut a).await, tungstenite::Message::Binary(expected));
+
+        for _ in 1..MAX_ROOMS_PER_SESSION {
+            send(&mut a, json!({"type": "join", "room_id": Uuid::new_v4()})).await;
+            assert_eq!(next_json(&mut a).await["type"], "joined");
+        }
+        send(&mut a, json!({"type": "join", "room_id": Uuid::new_v4()})).await;
+        let refused = next_json(&mut a).await;
+        assert_eq!(refused["type"], "error");
+        assert_eq!(refused["message"], "At most 4 rooms per session");
+
+        // Closed rooms stop counting
+        assert_eq!(
+            state.sfu.purge_idle(Duration::ZERO).await,
+            MAX_ROOMS_PER_SESSION
+        );
+        for _ in 0..MAX_ROOMS_PER_SESSION {
+            assert_eq!(next_json(&mut a).await["type"], "room_closed");
+        }
+        send(&mut a, json!({"type": "join", "room_id": room_id})).await;
+        assert_eq!(next_json(&mut a).await["type"], "joined");
+
+        // Premium is checked again on each join
+        state
+            .premium_verifier
+            .admin_revoke(&alice.public_key_bytes(), None, None)
+            .await
+            .unwrap();
+        send(&mut a, json!({"type": "join", "room_id": Uuid::new_v4()})).await;
+        let refused = next_json(&mut a).await;
+        assert_eq!(refused["message"], "Premium required for SFU calls");
+    }
+
+    #[tokio::test]
+    async fn test_lost_room_closed_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::for_tests(dir.path()).await;
+        state
+            .relay_policy
+            .set_settings(AdmissionSettings::default());
+        // Nobody reads the queue, so the closing events are lost
+        let (events, queue) = mpsc::channel(EVENT_QUEUE);
+        drop(queue);
+        let mut session = Session {
+            state: &state,
+            pubkey: [1; 32],
+            events,
+            rooms: HashSet::new(),
+        };
+        let room_ids: Vec<Uuid> = (0..MAX_ROOMS_PER_SESSION).map(|_| Uuid::new_v4()).collect();
+        for room_id in &room_ids {
+            assert!(matches!(
+                session.join(*room_id).await,
+                ServerMessage::Joined { .. }
+            ));
+        }
+        state.sfu.purge_idle(Duration::ZERO).await;
+        assert_eq!(session.rooms.len(), MAX_ROOMS_PER_SESSION);
+
+        assert!(matches!(
+            session.join(Uuid::new_v4()).await,
+            ServerMessage::Joined { .. }
+        ));
+        assert_eq!(session.rooms.len(), 1);
+
+        // Leaving a room reopened meanwhile leaves its new members alone
+        state.sfu.purge_idle(Duration::ZERO).await;
+        let (other, _other_queue) = mpsc::channel(EVENT_QUEUE);
+        let room_id = *session.rooms.iter().next().unwrap();
+        state.sfu.join_room(room_id, [2; 32], other).await.unwrap();
+        let leave = json!({"type": "leave", "room_id": room_id}).to_string();
+        let Some(Message::Text(reply)) = session.handle(Message::Text(leave)).await else {
+            panic!("expected text");
+        };
+        assert!(reply.contains(r#""type":"left""#));
+        assert_eq!(state.sfu.participant_count(&room_id).await, 1);
+        assert!(session.rooms.is_empty());
+    }
+}
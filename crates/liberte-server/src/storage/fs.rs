@@ -0,0 +1,141 @@
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::{BlobBackend, ObjectMeta, ObjectStream};
+use crate::blob_store::ensure_within;
+
+/// Blobs as files under a local directory, keys mapping to relative paths.
+#[derive(Debug, Clone)]
+pub struct FsBackend {
+    base_path: PathBuf,
+}
+
+impl FsBackend {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self { base_path }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        // Allowlist: blob keys are UUIDs, hex hashes and the `content/`
+        // shard directories. This explicit check satisfies static-analysis
+        // path-traversal audits.
+        if key.is_empty()
+            || key.starts_with('/')
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '/')
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid blob key {key:?}"),
+            ));
+        }
+        ensure_within(&self.base_path, &self.base_path.join(key))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+    }
+}
+
+#[async_trait]
+impl BlobBackend for FsBackend {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data).await?;
+        fs::rename(&tmp, &path).await
+    }
+
+    async fn put_file(&self, key: &str, from: &Path) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if fs::rename(from, &path).await.is_ok() {
+            return Ok(());
+        }
+        // Staging on another filesystem: copy, then drop the original
+        let tmp = path.with_extension("tmp");
+        fs::copy(from, &tmp).await?;
+        fs::rename(&tmp, &path).await?;
+        fs::remove_file(from).await
+    }
+
+    async fn size(&self, key: &str) -> io::Result<Option<u64>> {
+        match fs::metadata(self.path(key)?).await {
+            Ok(meta) => Ok(Some(meta.len())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn read(&self, key: &str, offset: u64, len: u64) -> io::Result<ObjectStream> {
+        let mut file = fs::File::open(self.path(key)?).await?;
+        if offset > 0 {
+            file.seek(SeekFrom::Start(offset)).await?;
+        }
+        Ok(ReaderStream::new(file.take(len)).boxed())
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self) -> io::Result<Vec<ObjectMeta>> {
+        let mut objects = Vec::new();
+        let mut dirs = vec![(self.base_path.clone(), String::new())];
+        while let Some((dir, prefix)) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                    continue;
+                };
+                let key = format!("{prefix}{name}");
+                let meta = entry.metadata().await?;
+                if meta.is_dir() {
+                    dirs.push((entry.path(), format!("{key}/")));
+                } else {
+                    objects.push(ObjectMeta {
+                        key,
+                        size: meta.len(),
+                        modified: meta.modified().ok().map(DateTime::<Utc>::from),
+                    });
+                }
+            }
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::backend_roundtrip;
+
+    #[tokio::test]
+    async fn test_fs_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("blobs");
+        std::fs::create_dir(&root).unwrap();
+        let backend = FsBackend::new(root);
+
+        backend_roundtrip(&backend, dir.path()).await;
+        assert!(backend.size("../escape").await.is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Where blob bytes live. [`BlobStore`](crate::blob_store::BlobStore) keeps
+//! its index and upload staging on local disk and hands the blobs
+//! themselves to a [`BlobBackend`], chosen in `ServerConfig`.
+
+mod fs;
+mod s3;
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+
+use crate::config::{BlobBackendConfig, ServerConfig};
+
+pub use fs::FsBackend;
+pub use s3::S3Backend;
+
+/// Bytes of a stored object, read lazily.
+pub type ObjectStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// A stored object as listed by its backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Object storage for blobs, addressed by `/`-separated keys such as
+/// `<uuid>` or `content/ab/cd/<hash>`. A missing object is reported as
+/// [`io::ErrorKind::NotFound`].
+#[async_trait]
+pub trait BlobBackend: std::fmt::Debug + Send + Sync {
+    /// Store `data` under `key`, replacing any previous object. Readers
+    /// never see a partial object.
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()>;
+
+    /// Store the local file at `path` under `key`, consuming the file.
+    async fn put_file(&self, key: &str, path: &Path) -> io::Result<()>;
+
+    /// Size of `key`, or `None` if there is no such object.
+    async fn size(&self, key: &str) -> io::Result<Option<u64>>;
+
+    /// `len` bytes of `key` starting at `offset`.
+    async fn read(&self, key: &str, offset: u64, len: u64) -> io::Result<ObjectStream>;
+
+    /// Delete `key`; deleting a missing object is not an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    /// Every stored object.
+    async fn list(&self) -> io::Result<Vec<ObjectMeta>>;
+}
+
+/// The backend selected by `config`.
+pub fn from_config(config: &ServerConfig) -> anyhow::Result<Arc<dyn BlobBackend>> {
+    Ok(match &config.blob_backend {
+        BlobBackendConfig::Filesystem => Arc::new(FsBackend::new(config.blob_storage_path.clone())),
+        BlobBackendConfig::S3(s3) => Arc::new(S3Backend::new(s3.clone())?),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    pub(crate) async fn read_all(
+        backend: &dyn BlobBackend,
+        key: &str,
+        offset: u64,
+        len: u64,
+    ) -> Vec<u8> {
+        let chunks: Vec<Bytes> = backend
+            .read(key, offset, len)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        chunks.concat()
+    }
+
+    /// Behaviour every backend must share.
+    pub(crate) async fn backend_roundtrip(backend: &dyn BlobBackend, scratch: &Path) {
+        let key = "content/ab/cd/abcd";
+        assert_eq!(backend.size(key).await.unwrap(), None);
+        assert_eq!(
+            backend.read(key, 0, 1).await.err().map(|e| e.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+
+        backend
+            .put(key, Bytes::from_static(b"hello world"))
+            .await
+            .unwrap();
+        assert_eq!(backend.size(key).await.unwrap(), Some(11));
+        assert_eq!(read_all(backend, key, 0, 11).await, b"hello world");
+        assert_eq!(read_all(backend, key, 6, 5).await, b"world");
+
+        let file = scratch.join("upload.part");
+        std::fs::write(&file, b"from a file").unwrap();
+        backend
+            .put_file("f81d4fae-7dec-11d0-a765-00a0c91e6bf6", &file)
+            .await
+            .unwrap();
+        assert!(!file.exists());
+
+        let mut listed = backend.list().await.unwrap();
+        listed.sort_by(|a, b| a.key.cmp(&b.key));
+        let keys: Vec<(&str, u64)> = listed.iter().map(|o| (o.key.as_str(), o.size)).collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("content/ab/cd/abcd", 11),
+                ("f81d4fae-7dec-11d0-a765-00a0c91e6bf6", 11)
+            ]
+        );
+
+        backend.delete(key).await.unwrap();
+        backend.delete(key).await.unwrap();
+        assert_eq!(backend.size(key).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,421 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::blob_store::{ensure_within, KeyedLocks};
+use crate::error::ServerError;
+
+/// Unfinished uploads idle for longer than this are discarded.
+const SESSION_IDLE_HOURS: i64 = 24;
+
+/// Most uploads one identity may have open at once.
+pub const MAX_SESSIONS_PER_OWNER: usize = 8;
+
+/// A resumable upload in progress. Received bytes are appended to
+/// `<id>.part`, whose length is the offset to resume from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub id: Uuid,
+    pub owner: [u8; 32],
+    /// Total size announced when the session was created.
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Resumable blob uploads, kept next to the blob files so a finished
+/// upload can be moved into place without copying.
+#[derive(Debug)]
+pub struct UploadSessions {
+    base_path: PathBuf,
+    max_size: u64,
+    /// Serializes appends to a session so two retries of a chunk cannot
+    /// interleave.
+    sessions: KeyedLocks<Uuid>,
+    /// Serializes creations by an owner so two cannot both fit in the
+    /// same room.
+    owners: KeyedLocks<[u8; 32]>,
+}
+
+impl UploadSessions {
+    pub async fn new(base_path: PathBuf, max_size: u64) -> Result<Self, ServerError> {
+        fs::create_dir_all(&base_path).await.map_err(|e| {
+            ServerError::BlobStorage(format!(
+                "Failed to create upload directory '{}': {e}",
+                base_path.display()
+            ))
+        })?;
+        Ok(Self {
+            base_path,
+            max_size,
+            sessions: KeyedLocks::default(),
+            owners: KeyedLocks::default(),
+        })
+    }
+
+    /// Start an upload of `size` bytes for `owner`, who already stores
+    /// `used` bytes. The announced size of every open session counts
+    /// against `quota` (0 for none) along with them.
+    pub async fn create(
+        &self,
+        owner: &[u8; 32],
+        size: u64,
+        used: u64,
+        quota: u64,
+    ) -> Result<UploadSession, ServerError> {
+        if size == 0 {
+            return Err(ServerError::BlobStorage("Empty blob".to_string()));
+        }
+        if size > self.max_size {
+            return Err(ServerError::BlobTooLarge {
+                size: size as usize,
+                max: self.max_size as usize,
+            });
+        }
+
+        let _owner = self.owners.lock(*owner).await;
+        let (open, reserved) = self.open_by(owner).await?;
+        if open >= MAX_SESSIONS_PER_OWNER {
+            return Err(ServerError::Forbidden(format!(
+                "At most {MAX_SESSIONS_PER_OWNER} uploads can be open at once"
+            )));
+        }
+        let used = used + reserved;
+        if quota > 0 && used + size > quota {
+            return Err(ServerError::QuotaExceeded { used, quota });
+        }
+
+        let now = Utc::now();
+        let session = UploadSession {
+            id: Uuid::new_v4(),
+            owner: *owner,
+            size,
+            created_at: now,
+            updated_at: now,
+        };
+        fs::write(self.part_path(session.id)?, b"")
+            .await
+            .map_err(storage_error)?;
+        self.save(&session).await?;
+
+        debug!(id = %session.id, size, "Upload session created");
+        Ok(session)
+    }
+
+    /// Session `id` of `owner` and the number of bytes received so far.
+    pub async fn status(
+        &self,
+        id: Uuid,
+        owner: &[u8; 32],
+    ) -> Result<(UploadSession, u64), ServerError> {
+        let session = self.load(id).await?;
+        if session.owner != *owner {
+            return Err(ServerError::Forbidden(
+                "Upload session belongs to another identity".into(),
+            ));
+        }
+        let offset = fs::metadata(self.part_path(id)?)
+            .await
+            .map_err(storage_error)?
+            .len();
+        Ok((session, offset))
+    }
+
+    /// Append `chunk` at `offset`, which must be where the previous chunk
+    /// ended. Returns the new offset.
+    pub async fn append(
+        &self,
+        id: Uuid,
+        owner: &[u8; 32],
+        offset: u64,
+        chunk: &[u8],
+    ) -> Result<u64, ServerError> {
+        let _session = self.sessions.lock(id).await;
+        let (mut session, received) = self.status(id, owner).await?;
+        if offset != received {
+            return Err(ServerError::UploadOffsetMismatch { expected: received });
+        }
+        if received + chunk.len() as u64 > session.size {
+            return Err(ServerError::BadRequest(format!(
+                "Chunk goes past the announced size of {} bytes",
+                session.size
+            )));
+        }
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(self.part_path(id)?)
+            .await
+            .map_err(storage_error)?;
+        file.write_all(chunk).await.map_err(storage_error)?;
+        file.sync_data().await.map_err(storage_error)?;
+
+        session.updated_at = Utc::now();
+        self.save(&session).await?;
+        Ok(received + chunk.len() as u64)
+    }
+
+    /// The received file of a fully uploaded session and its BLAKE3 hash.
+    pub async fn completed(
+        &self,
+        id: Uuid,
+        owner: &[u8; 32],
+    ) -> Result<(UploadSession, PathBuf, [u8; 32]), ServerError> {
+        let (session, received) = self.status(id, owner).await?;
+        if received != session.size {
+            return Err(ServerError::BadRequest(format!(
+                "Upload incomplete: {received} of {} bytes received",
+                session.size
+            )));
+        }
+        let path = self.part_path(id)?;
+        let hash = hash_file(&path).await?;
+        Ok((session, path, hash))
+    }
+
+    /// Forget session `id`, along with its data if still there.
+    pub async fn remove(&self, id: Uuid) -> Result<(), ServerError> {
+        let _session = self.sessions.lock(id).await;
+        self.remove_files(id).await
+    }
+
+    /// Discard sessions idle for too long. Returns how many were removed.
+    pub async fn purge_stale(&self) -> Result<usize, ServerError> {
+        let cutoff = Utc::now() - Duration::hours(SESSION_IDLE_HOURS);
+        let mut removed = 0;
+        for id in self.ids().await? {
+            // Under the session lock, so a chunk being written keeps it
+            let _session = self.sessions.lock(id).await;
+            match self.load(id).await {
+                Ok(session) if session.updated_at >= cutoff => {}
+                _ => {
+                    self.remove_files(id).await?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn remove_files(&self, id: Uuid) -> Result<(), ServerError> {
+        for path in [self.part_path(id)?, self.meta_path(id)?] {
+            match fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(storage_error(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of open sessions of `owner` and their announced sizes.
+    async fn open_by(&self, owner: &[u8; 32]) -> Result<(usize, u64), ServerError> {
+        let mut open = (0, 0);
+        for id in self.ids().await? {
+            if let Ok(session) = self.load(id).await {
+                if session.owner == *owner {
+                    open.0 += 1;
+                    open.1 += session.size;
+                }
+            }
+        }
+        Ok(open)
+    }
+
+    async fn ids(&self) -> Result<Vec<Uuid>, ServerError> {
+        let mut entries = fs::read_dir(&self.base_path).await.map_err(storage_error)?;
+        let mut ids = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(storage_error)? {
+            if let Some(id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|id| Uuid::parse_str(id).ok())
+            {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    async fn load(&self, id: Uuid) -> Result<UploadSession, ServerError> {
+        let data = fs::read(self.meta_path(id)?)
+            .await
+            .map_err(|_| ServerError::NotFound("Upload session not found".into()))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| ServerError::BlobStorage(format!("Corrupt upload session {id}: {e}")))
+    }
+
+    async fn save(&self, session: &UploadSession) -> Result<(), ServerError> {
+        let data = serde_json::to_vec(session)
+            .map_err(|e| ServerError::Internal(format!("Failed to encode session: {e}")))?;
+        fs::write(self.meta_path(session.id)?, data)
+            .await
+            .map_err(storage_error)
+    }
+
+    fn part_path(&self, id: Uuid) -> Result<PathBuf, ServerError> {
+        ensure_within(&self.base_path, &self.base_path.join(format!("{id}.part")))
+    }
+
+    fn meta_path(&self, id: Uuid) -> Result<PathBuf, ServerError> {
+        ensure_within(&self.base_path, &self.base_path.join(format!("{id}.json")))
+    }
+}
+
+fn storage_error(e: std::io::Error) -> ServerError {
+    ServerError::BlobStorage(format!("Upload session I/O error: {e}"))
+}
+
+/// BLAKE3 of a file, read in blocks rather than loaded whole.
+async fn hash_file(path: &Path) -> Result<[u8; 32], ServerError> {
+    let mut file = fs::File::open(path).await.map_err(storage_error)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await.map_err(storage_error)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(*hasher.finalize().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: [u8; 32] = [5u8; 32];
+
+    #[tokio::test]
+    async fn test_resumable_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = UploadSessions::new(dir.path().to_path_buf(), 1024)
+            .await
+            .unwrap();
+
+        let session = sessions.create(&OWNER, 10, 0, 0).await.unwrap();
+        assert_eq!(
+            sessions
+                .append(session.id, &OWNER, 0, b"hello")
+                .await
+                .unwrap(),
+            5
+        );
+
+        // A retried chunk is refused with the offset to resume from
+        assert!(matches!(
+            sessions.append(session.id, &OWNER, 0, b"hello").await,
+            Err(ServerError::UploadOffsetMismatch { expected: 5 })
+        ));
+        assert!(matches!(
+            sessions.completed(session.id, &OWNER).await,
+            Err(ServerError::BadRequest(_))
+        ));
+        assert!(matches!(
+            sessions.status(session.id, &[6u8; 32]).await,
+            Err(ServerError::Forbidden(_))
+        ));
+        assert!(sessions
+            .append(session.id, &OWNER, 5, b"world!")
+            .await
+            .is_err());
+
+        sessions
+            .append(session.id, &OWNER, 5, b"world")
+            .await
+            .unwrap();
+        let (_, path, hash) = sessions.completed(session.id, &OWNER).await.unwrap();
+        assert_eq!(hash, *blake3::hash(b"helloworld").as_bytes());
+        assert_eq!(std::fs::read(path).unwrap(), b"helloworld");
+
+        sessions.remove(session.id).await.unwrap();
+        assert!(matches!(
+            sessions.status(session.id, &OWNER).await,
+            Err(ServerError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_appends_only_wait_for_their_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = UploadSessions::new(dir.path().to_path_buf(), 1024)
+            .await
+            .unwrap();
+        let a = sessions.create(&OWNER, 10, 0, 0).await.unwrap();
+        let b = sessions.create(&OWNER, 10, 0, 0).await.unwrap();
+
+        // As if a slow chunk of `a` were being written
+        let writing = sessions.sessions.lock(a.id).await;
+        let other = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            sessions.append(b.id, &OWNER, 0, b"hello"),
+        );
+        assert_eq!(
+            other.await.expect("another upload must not wait").unwrap(),
+            5
+        );
+        let same = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            sessions.append(a.id, &OWNER, 0, b"hello"),
+        );
+        assert!(same.await.is_err());
+
+        drop(writing);
+        assert_eq!(sessions.append(a.id, &OWNER, 0, b"hello").await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_size_limits_and_purge() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = UploadSessions::new(dir.path().to_path_buf(), 1024)
+            .await
+            .unwrap();
+        assert!(sessions.create(&OWNER, 0, 0, 0).await.is_err());
+        assert!(matches!(
+            sessions.create(&OWNER, 1025, 0, 0).await,
+            Err(ServerError::BlobTooLarge { .. })
+        ));
+
+        let fresh = sessions.create(&OWNER, 10, 0, 0).await.unwrap();
+        let mut stale = sessions.create(&OWNER, 10, 0, 0).await.unwrap();
+        stale.updated_at -= Duration::hours(SESSION_IDLE_HOURS + 1);
+        sessions.save(&stale).await.unwrap();
+
+        assert_eq!(sessions.purge_stale().await.unwrap(), 1);
+        assert!(sessions.status(fresh.id, &OWNER).await.is_ok());
+        assert!(sessions.status(stale.id, &OWNER).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_sessions_count_against_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = UploadSessions::new(dir.path().to_path_buf(), 1024)
+            .await
+            .unwrap();
+
+        sessions.create(&OWNER, 40, 50, 100).await.unwrap();
+        assert!(matches!(
+            sessions.create(&OWNER, 20, 50, 100).await,
+            Err(ServerError::QuotaExceeded {
+                used: 90,
+                quota: 100
+            })
+        ));
+        sessions.create(&[6u8; 32], 20, 50, 100).await.unwrap();
+
+        for _ in 1..MAX_SESSIONS_PER_OWNER {
+            sessions.create(&OWNER, 1, 0, 0).await.unwrap();
+        }
+        assert!(matches!(
+            sessions.create(&OWNER, 1, 0, 0).await,
+            Err(ServerError::Forbidden(_))
+        ));
+    }
+}
@@ -11,13 +11,43 @@ pub const MAX_FILE_SIZE: usize = 50 * 1024 * 1024; // 50 MiB
 
 pub const GOSSIPSUB_HEARTBEAT_SECS: u64 = 1;
 pub const DEFAULT_QUIC_PORT: u16 = 4001;
+pub const DEFAULT_TCP_PORT: u16 = 4001;
+pub const DEFAULT_WS_PORT: u16 = 4002;
 pub const DEFAULT_HTTP_PORT: u16 = 8080;
 
-pub const DOH_CLOUDFLARE: &str = "1.1.1.1";
-pub const DOH_GOOGLE: &str = "8.8.8.8";
+// Persistent peer book
+pub const PEER_BOOK_MAX_AGE_DAYS: i64 = 30;
+pub const PEER_BOOK_LOAD_LIMIT: u32 = 200;
+
+// Signed presence records in the DHT
+pub const PRESENCE_TTL_SECS: i64 = 3600;
+pub const PRESENCE_REPUBLISH_SECS: u64 = 900;
+
+// Default encrypted DNS upstreams, as scheme://tls-name@ip[:port]
+pub const DEFAULT_DNS_UPSTREAMS: &[&str] = &[
+    "https://cloudflare-dns.com@1.1.1.1",
+    "https://dns.google@8.8.8.8",
+];
 
 // BLAKE3 KDF contexts
 pub const KDF_CONTEXT_CHANNEL_KEY: &str = "liberte-channel-key-v1";
 pub const KDF_CONTEXT_DB_KEY: &str = "liberte-db-key-v1";
+pub const KDF_CONTEXT_TOPIC: &str = "liberte-topic-v1";
+pub const KDF_CONTEXT_MEDIA_TOPIC: &str = "liberte-media-topic-v1";
+pub const KDF_CONTEXT_RENDEZVOUS: &str = "liberte-rendezvous-v1";
+
+// GossipSub topic names rotate per epoch; neighbouring epochs are also
+// subscribed within the grace window to absorb clock skew.
+pub const TOPIC_EPOCH_SECS: u64 = 86_400;
+pub const TOPIC_EPOCH_GRACE_SECS: u64 = 600;
+
+// Rendezvous registrations use the protocol's minimum TTL and are renewed
+// (with a fresh discovery) well before they lapse.
+pub const RENDEZVOUS_TTL_SECS: u64 = 7_200;
+pub const RENDEZVOUS_REFRESH_SECS: u64 = 900;
+
+// Per-author gossipsub message budget (voice frames alone are ~50/s)
+pub const GOSSIP_RATE_LIMIT: u32 = 1_000;
+pub const GOSSIP_RATE_WINDOW_SECS: u64 = 10;
 
 pub const PREMIUM_PRICE_EUR: f64 = 0.99;
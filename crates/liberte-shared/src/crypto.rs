@@ -4,7 +4,10 @@ use chacha20poly1305::{
 };
 use rand::RngCore;
 
-use crate::constants::{KDF_CONTEXT_CHANNEL_KEY, NONCE_SIZE};
+use crate::constants::{
+    KDF_CONTEXT_CHANNEL_KEY, KDF_CONTEXT_MEDIA_TOPIC, KDF_CONTEXT_RENDEZVOUS, KDF_CONTEXT_TOPIC,
+    NONCE_SIZE,
+};
 use crate::error::CryptoError;
 
 pub type SymmetricKey = [u8; 32];
@@ -62,6 +65,30 @@ pub fn derive_channel_key(shared_secret: &[u8], channel_id: &[u8]) -> SymmetricK
     key
 }
 
+// GossipSub topic for a channel epoch: keyed hash of the channel key, so
+// observers can neither read the channel id nor link topics across epochs.
+pub fn derive_channel_topic(channel_key: &SymmetricKey, epoch: u64) -> String {
+    derive_topic(KDF_CONTEXT_TOPIC, channel_key, epoch)
+}
+
+// Separate topic for realtime media, so voice frames can be queued and
+// dropped independently of chat.
+pub fn derive_media_topic(channel_key: &SymmetricKey, epoch: u64) -> String {
+    derive_topic(KDF_CONTEXT_MEDIA_TOPIC, channel_key, epoch)
+}
+
+// Rendezvous namespace for a channel epoch; the rendezvous node sees which
+// peers share a namespace but not which channel it stands for.
+pub fn derive_rendezvous_namespace(channel_key: &SymmetricKey, epoch: u64) -> String {
+    derive_topic(KDF_CONTEXT_RENDEZVOUS, channel_key, epoch)
+}
+
+fn derive_topic(context: &str, channel_key: &SymmetricKey, epoch: u64) -> String {
+    let topic_key = blake3::derive_key(context, channel_key);
+    let hash = blake3::keyed_hash(&topic_key, &epoch.to_le_bytes());
+    hex::encode(&hash.as_bytes()[..16])
+}
+
 pub fn derive_key_from_passphrase(passphrase: &[u8], context: &str) -> SymmetricKey {
     let mut hasher = blake3::Hasher::new_derive_key(context);
     hasher.update(passphrase);
@@ -134,6 +161,22 @@ mod tests {
         assert_ne!(key1, key2);
     }
 
+    #[test]
+    fn test_channel_topic_rotates_per_epoch() {
+        let key = generate_symmetric_key();
+        assert_eq!(derive_channel_topic(&key, 7), derive_channel_topic(&key, 7));
+        assert_ne!(derive_channel_topic(&key, 7), derive_channel_topic(&key, 8));
+        assert_ne!(
+            derive_channel_topic(&key, 7),
+            derive_channel_topic(&generate_symmetric_key(), 7)
+        );
+        assert_ne!(derive_channel_topic(&key, 7), derive_media_topic(&key, 7));
+        assert_ne!(
+            derive_channel_topic(&key, 7),
+            derive_rendezvous_namespace(&key, 7)
+        );
+    }
+
     #[test]
     fn test_nonce_prepended() {
         let key = generate_symmetric_key();
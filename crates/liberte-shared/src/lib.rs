@@ -8,5 +8,7 @@ pub mod identity;
 pub mod invite;
 pub mod noise;
 pub mod premium;
+pub mod presence;
 pub mod protocol;
+pub mod request_auth;
 pub mod types;
@@ -0,0 +1,178 @@
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::Signature;
+use serde::{Deserialize, Serialize};
+
+use crate::identity::{verify_signature, Identity};
+use crate::types::UserId;
+
+const PRESENCE_DOMAIN: &[u8] = b"liberte-presence-v1";
+const PRESENCE_KEY_PREFIX: &[u8] = b"/liberte/presence/";
+
+/// Maps a user identity to the libp2p peer currently serving it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PresenceRecord {
+    pub user_id: UserId,
+    /// Protobuf-encoded libp2p public key; its PeerId is derived from this.
+    pub peer_public_key: Vec<u8>,
+    /// Listen, external and relay circuit multiaddrs.
+    pub addresses: Vec<String>,
+    pub published_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A presence record signed by both the identity key and the libp2p key,
+/// which proves each key holder vouches for the other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPresence {
+    pub record: PresenceRecord,
+    pub identity_signature: Vec<u8>,
+    pub peer_signature: Vec<u8>,
+}
+
+impl PresenceRecord {
+    pub fn new(
+        user_id: UserId,
+        peer_public_key: Vec<u8>,
+        addresses: Vec<String>,
+        ttl: Duration,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            user_id,
+            peer_public_key,
+            addresses,
+            published_at: now,
+            expires_at: now + ttl,
+        }
+    }
+
+    /// Domain-separated bytes covered by both signatures.
+    pub fn signing_bytes(&self) -> Result<Vec<u8>, PresenceError> {
+        let body = bincode::serialize(self).map_err(|_| PresenceError::InvalidFormat)?;
+        let mut bytes = Vec::with_capacity(PRESENCE_DOMAIN.len() + body.len());
+        bytes.extend_from_slice(PRESENCE_DOMAIN);
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Utc::now() > self.expires_at
+    }
+}
+
+impl SignedPresence {
+    /// Sign with the identity key. The peer signature is produced by the
+    /// caller, which owns the libp2p keypair.
+    pub fn sign(
+        record: PresenceRecord,
+        identity: &Identity,
+        peer_signature: Vec<u8>,
+    ) -> Result<Self, PresenceError> {
+        if record.user_id != identity.user_id() {
+            return Err(PresenceError::IdentityMismatch);
+        }
+        let bytes = record.signing_bytes()?;
+        let identity_signature = identity.sign(&bytes).to_bytes().to_vec();
+        Ok(Self {
+            record,
+            identity_signature,
+            peer_signature,
+        })
+    }
+
+    /// Check expiry and the identity signature. The peer signature must be
+    /// checked separately against `peer_public_key`.
+    pub fn verify_identity(&self) -> Result<(), PresenceError> {
+        if self.record.is_expired() {
+            return Err(PresenceError::Expired);
+        }
+        let bytes = self.record.signing_bytes()?;
+        let signature = Signature::from_slice(&self.identity_signature)
+            .map_err(|_| PresenceError::InvalidSignature)?;
+        verify_signature(&self.record.user_id.0, &bytes, &signature)
+            .map_err(|_| PresenceError::InvalidSignature)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// DHT key under which a user's presence record is stored.
+pub fn presence_dht_key(user_id: &UserId) -> Vec<u8> {
+    let mut key = Vec::with_capacity(PRESENCE_KEY_PREFIX.len() + 32);
+    key.extend_from_slice(PRESENCE_KEY_PREFIX);
+    key.extend_from_slice(&user_id.0);
+    key
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PresenceError {
+    #[error("Invalid presence record format")]
+    InvalidFormat,
+
+    #[error("Presence record has expired")]
+    Expired,
+
+    #[error("Invalid presence signature")]
+    InvalidSignature,
+
+    #[error("Presence record does not belong to this identity")]
+    IdentityMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(identity: &Identity, ttl: Duration) -> SignedPresence {
+        let record = PresenceRecord::new(
+            identity.user_id(),
+            vec![1, 2, 3],
+            vec!["/ip4/127.0.0.1/udp/4001/quic-v1".to_string()],
+            ttl,
+        );
+        SignedPresence::sign(record, identity, vec![]).unwrap()
+    }
+
+    #[test]
+    fn test_presence_roundtrip() {
+        let identity = Identity::generate();
+        let presence = signed(&identity, Duration::hours(1));
+
+        let bytes = presence.to_bytes().unwrap();
+        let restored = SignedPresence::from_bytes(&bytes).unwrap();
+        restored.verify_identity().expect("should verify");
+        assert_eq!(restored.record, presence.record);
+    }
+
+    #[test]
+    fn test_presence_tampered_fails() {
+        let identity = Identity::generate();
+        let mut presence = signed(&identity, Duration::hours(1));
+        presence.record.addresses = vec!["/ip4/6.6.6.6/udp/4001/quic-v1".to_string()];
+        assert!(presence.verify_identity().is_err());
+    }
+
+    #[test]
+    fn test_presence_expired_fails() {
+        let identity = Identity::generate();
+        let presence = signed(&identity, Duration::seconds(-1));
+        assert!(matches!(
+            presence.verify_identity(),
+            Err(PresenceError::Expired)
+        ));
+    }
+
+    #[test]
+    fn test_sign_rejects_foreign_record() {
+        let identity = Identity::generate();
+        let other = Identity::generate();
+        let record = PresenceRecord::new(other.user_id(), vec![], vec![], Duration::hours(1));
+        assert!(SignedPresence::sign(record, &identity, vec![]).is_err());
+    }
+}
@@ -156,6 +156,25 @@ impl WireMessage {
     pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
         bincode::deserialize(data)
     }
+
+    /// Channel the message claims to belong to, for the kinds tied to one.
+    pub fn channel_id(&self) -> Option<&ChannelId> {
+        match self {
+            WireMessage::ChatMessage(m) => Some(&m.channel_id),
+            WireMessage::FileOffer(m) => Some(&m.channel_id),
+            WireMessage::Signal(m) => Some(&m.channel_id),
+            WireMessage::ChannelInvite(m) => Some(&m.channel_id),
+            WireMessage::TypingIndicator(m) => Some(&m.channel_id),
+            WireMessage::MessageReaction(m) => Some(&m.channel_id),
+            WireMessage::VoiceFrame(m) => Some(&m.channel_id),
+            WireMessage::VoiceEvent(m) => Some(&m.channel_id),
+            WireMessage::FileAccept(_)
+            | WireMessage::FileChunk(_)
+            | WireMessage::PeerStatus(_)
+            | WireMessage::PremiumAuth(_)
+            | WireMessage::StatusUpdate(_) => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -183,4 +202,23 @@ mod tests {
             panic!("Message type mismatch");
         }
     }
+
+    #[test]
+    fn test_channel_id() {
+        let channel_id = ChannelId(uuid::Uuid::new_v4());
+        let typing = WireMessage::TypingIndicator(TypingIndicator {
+            sender: UserId([1u8; 32]),
+            channel_id: channel_id.clone(),
+            sender_display_name: None,
+            timestamp: Utc::now(),
+        });
+        assert_eq!(typing.channel_id(), Some(&channel_id));
+
+        let status = WireMessage::StatusUpdate(StatusUpdate {
+            user_id: UserId([1u8; 32]),
+            status: "online".into(),
+            timestamp: Utc::now(),
+        });
+        assert_eq!(status.channel_id(), None);
+    }
 }
@@ -0,0 +1,333 @@
+use std::collections::{BTreeSet, HashMap};
+
+use ed25519_dalek::Signature;
+use rand::RngCore;
+
+use crate::identity::{verify_signature, Identity};
+
+const REQUEST_DOMAIN: &[u8] = b"liberte-request-v1";
+
+/// Headers carrying a request signature over HTTP.
+pub const HEADER_PUBKEY: &str = "x-liberte-pubkey";
+pub const HEADER_TIMESTAMP: &str = "x-liberte-timestamp";
+pub const HEADER_NONCE: &str = "x-liberte-nonce";
+pub const HEADER_SIGNATURE: &str = "x-liberte-signature";
+
+/// How far a request timestamp may drift from the server clock.
+pub const REQUEST_MAX_SKEW_SECS: i64 = 300;
+
+/// Most signatures [`SeenSignatures`] remembers for one identity.
+pub const MAX_SEEN_SIGNATURES_PER_SIGNER: usize = 1_024;
+
+/// An Ed25519 signature by an identity key over an HTTP request: method,
+/// path (with query), BLAKE3 hash of the body, a unix timestamp and a
+/// random nonce. Ed25519 is deterministic, so without the nonce two
+/// identical requests sent within a second would carry the same signature
+/// and the second would be refused as a replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestSignature {
+    pub pubkey: [u8; 32],
+    pub timestamp: i64,
+    pub nonce: [u8; 16],
+    pub signature: [u8; 64],
+}
+
+/// Domain-separated bytes covered by the signature.
+pub fn request_signing_bytes(
+    method: &str,
+    path: &str,
+    body: &[u8],
+    timestamp: i64,
+    nonce: &[u8; 16],
+) -> Vec<u8> {
+    let body_hash = blake3::hash(body);
+    let mut bytes = Vec::with_capacity(REQUEST_DOMAIN.len() + method.len() + path.len() + 128);
+    bytes.extend_from_slice(REQUEST_DOMAIN);
+    bytes.push(b'\n');
+    bytes.extend_from_slice(method.to_ascii_uppercase().as_bytes());
+    bytes.push(b'\n');
+    bytes.extend_from_slice(path.as_bytes());
+    bytes.push(b'\n');
+    bytes.extend_from_slice(body_hash.to_hex().as_bytes());
+    bytes.push(b'\n');
+    bytes.extend_from_slice(timestamp.to_string().as_bytes());
+    bytes.push(b'\n');
+    bytes.extend_from_slice(hex::encode(nonce).as_bytes());
+    bytes
+}
+
+impl RequestSignature {
+    pub fn sign(
+        identity: &Identity,
+        method: &str,
+        path: &str,
+        body: &[u8],
+        timestamp: i64,
+    ) -> Self {
+        let mut nonce = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let bytes = request_signing_bytes(method, path, body, timestamp, &nonce);
+        Self {
+            pubkey: identity.public_key_bytes(),
+            timestamp,
+            nonce,
+            signature: identity.sign(&bytes).to_bytes(),
+        }
+    }
+
+    /// Parse the four header values (hex pubkey, decimal timestamp, hex
+    /// nonce, hex signature).
+    pub fn from_headers(
+        pubkey: &str,
+        timestamp: &str,
+        nonce: &str,
+        signature: &str,
+    ) -> Result<Self, RequestAuthError> {
+        let pubkey = hex::decode(pubkey.trim())
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or(RequestAuthError::InvalidFormat)?;
+        let timestamp = timestamp
+            .trim()
+            .parse()
+            .map_err(|_| RequestAuthError::InvalidFormat)?;
+        let nonce = hex::decode(nonce.trim())
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or(RequestAuthError::InvalidFormat)?;
+        let signature = hex::decode(signature.trim())
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or(RequestAuthError::InvalidFormat)?;
+        Ok(Self {
+            pubkey,
+            timestamp,
+            nonce,
+            signature,
+        })
+    }
+
+    /// Header values in the order of [`HEADER_PUBKEY`], [`HEADER_TIMESTAMP`],
+    /// [`HEADER_NONCE`] and [`HEADER_SIGNATURE`].
+    pub fn header_values(&self) -> [String; 4] {
+        [
+            hex::encode(self.pubkey),
+            self.timestamp.to_string(),
+            hex::encode(self.nonce),
+            hex::encode(self.signature),
+        ]
+    }
+
+    /// Check the timestamp against `now` (unix seconds) and the signature
+    /// against the request it claims to cover.
+    pub fn verify(
+        &self,
+        method: &str,
+        path: &str,
+        body: &[u8],
+        now: i64,
+    ) -> Result<(), RequestAuthError> {
+        if now.abs_diff(self.timestamp) > REQUEST_MAX_SKEW_SECS as u64 {
+            return Err(RequestAuthError::Stale);
+        }
+        let bytes = request_signing_bytes(method, path, body, self.timestamp, &self.nonce);
+        let signature = Signature::from_bytes(&self.signature);
+        verify_signature(&self.pubkey, &bytes, &signature)
+            .map_err(|_| RequestAuthError::InvalidSignature)
+    }
+}
+
+/// Signatures accepted while their timestamp is still within the skew
+/// window, so a captured request can't be replayed. Older ones are
+/// forgotten since [`RequestSignature::verify`] already refuses them.
+#[derive(Debug, Default)]
+pub struct SeenSignatures {
+    by_time: BTreeSet<(i64, [u8; 32], [u8; 64])>,
+    by_signer: HashMap<[u8; 32], BTreeSet<(i64, [u8; 64])>>,
+}
+
+impl SeenSignatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember a verified signature, refusing one already seen. Each
+    /// identity keeps at most [`MAX_SEEN_SIGNATURES_PER_SIGNER`] fresh
+    /// signatures; past that its oldest are forgotten, so a busy identity
+    /// only weakens replay protection for itself and never blocks others.
+    pub fn insert(&mut self, signed: &RequestSignature, now: i64) -> Result<(), RequestAuthError> {
+        let horizon = now.saturating_sub(REQUEST_MAX_SKEW_SECS);
+        while let Some(&(timestamp, pubkey, signature)) = self.by_time.first() {
+            if timestamp >= horizon {
+                break;
+            }
+            self.by_time.pop_first();
+            self.forget(&pubkey, timestamp, &signature);
+        }
+
+        let seen = self.by_signer.entry(signed.pubkey).or_default();
+        if !seen.insert((signed.timestamp, signed.signature)) {
+            return Err(RequestAuthError::Replayed);
+        }
+        self.by_time
+            .insert((signed.timestamp, signed.pubkey, signed.signature));
+        while seen.len() > MAX_SEEN_SIGNATURES_PER_SIGNER {
+            if let Some((timestamp, signature)) = seen.pop_first() {
+                self.by_time.remove(&(timestamp, signed.pubkey, signature));
+            }
+        }
+        Ok(())
+    }
+
+    fn forget(&mut self, pubkey: &[u8; 32], timestamp: i64, signature: &[u8; 64]) {
+        if let Some(seen) = self.by_signer.get_mut(pubkey) {
+            seen.remove(&(timestamp, *signature));
+            if seen.is_empty() {
+                self.by_signer.remove(pubkey);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_time.is_empty()
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum RequestAuthError {
+    #[error("Malformed request signature headers")]
+    InvalidFormat,
+
+    #[error("Request timestamp is too far from the server clock")]
+    Stale,
+
+    #[error("Invalid request signature")]
+    InvalidSignature,
+
+    #[error("Request was already received")]
+    Replayed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn test_request_signature_roundtrip() {
+        let identity = Identity::generate();
+        let signed = RequestSignature::sign(&identity, "post", "/blob/upload", b"data", NOW);
+
+        let [pubkey, timestamp, nonce, signature] = signed.header_values();
+        let parsed =
+            RequestSignature::from_headers(&pubkey, &timestamp, &nonce, &signature).unwrap();
+        assert_eq!(parsed, signed);
+        assert_eq!(
+            parsed.verify("POST", "/blob/upload", b"data", NOW + 10),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_request_signature_covers_request() {
+        let identity = Identity::generate();
+        let signed = RequestSignature::sign(&identity, "DELETE", "/blob/a", b"", NOW);
+
+        let err = Err(RequestAuthError::InvalidSignature);
+        assert_eq!(signed.verify("GET", "/blob/a", b"", NOW), err);
+        assert_eq!(signed.verify("DELETE", "/blob/b", b"", NOW), err);
+        assert_eq!(signed.verify("DELETE", "/blob/a", b"x", NOW), err);
+
+        let mut forged = signed.clone();
+        forged.timestamp += 1;
+        assert_eq!(forged.verify("DELETE", "/blob/a", b"", NOW), err);
+
+        let mut forged = signed.clone();
+        forged.nonce[0] ^= 1;
+        assert_eq!(forged.verify("DELETE", "/blob/a", b"", NOW), err);
+    }
+
+    #[test]
+    fn test_request_signature_stale() {
+        let identity = Identity::generate();
+        let signed = RequestSignature::sign(&identity, "DELETE", "/blob/a", b"", NOW);
+        assert_eq!(
+            signed.verify("DELETE", "/blob/a", b"", NOW + REQUEST_MAX_SKEW_SECS + 1),
+            Err(RequestAuthError::Stale)
+        );
+        assert!(RequestSignature::from_headers("zz", "1", "00", "00").is_err());
+
+        let mut far = signed.clone();
+        far.timestamp = i64::MIN;
+        assert_eq!(
+            far.verify("DELETE", "/blob/a", b"", NOW),
+            Err(RequestAuthError::Stale)
+        );
+    }
+
+    #[test]
+    fn test_seen_signatures_refuse_replay() {
+        let identity = Identity::generate();
+        let first = RequestSignature::sign(&identity, "GET", "/sfu", b"", NOW);
+        let second = RequestSignature::sign(&identity, "GET", "/sfu", b"", NOW + 1);
+
+        let mut seen = SeenSignatures::new();
+        assert_eq!(seen.insert(&first, NOW), Ok(()));
+        assert_eq!(seen.insert(&second, NOW), Ok(()));
+        assert_eq!(
+            seen.insert(&first, NOW + 10),
+            Err(RequestAuthError::Replayed)
+        );
+
+        // Forgotten once verify would call it stale anyway
+        let later = NOW + REQUEST_MAX_SKEW_SECS + 1;
+        let third = RequestSignature::sign(&identity, "GET", "/sfu", b"", later);
+        assert_eq!(seen.insert(&third, later), Ok(()));
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn test_identical_requests_in_the_same_second() {
+        let identity = Identity::generate();
+        let first = RequestSignature::sign(&identity, "HEAD", "/blob/quota", b"", NOW);
+        let retry = RequestSignature::sign(&identity, "HEAD", "/blob/quota", b"", NOW);
+        assert_ne!(first.signature, retry.signature);
+        assert_eq!(retry.verify("HEAD", "/blob/quota", b"", NOW), Ok(()));
+
+        let mut seen = SeenSignatures::new();
+        assert_eq!(seen.insert(&first, NOW), Ok(()));
+        assert_eq!(seen.insert(&retry, NOW), Ok(()));
+        assert_eq!(seen.insert(&retry, NOW), Err(RequestAuthError::Replayed));
+    }
+
+    #[test]
+    fn test_busy_signer_does_not_block_others() {
+        let busy = Identity::generate();
+        let other = Identity::generate();
+        let mut seen = SeenSignatures::new();
+
+        let first = RequestSignature::sign(&busy, "POST", "/blob/upload", b"", NOW - 10);
+        assert_eq!(seen.insert(&first, NOW), Ok(()));
+        for _ in 0..MAX_SEEN_SIGNATURES_PER_SIGNER {
+            let signed = RequestSignature::sign(&busy, "POST", "/blob/upload", b"", NOW);
+            assert_eq!(seen.insert(&signed, NOW), Ok(()));
+        }
+        assert_eq!(seen.len(), MAX_SEEN_SIGNATURES_PER_SIGNER);
+
+        let signed = RequestSignature::sign(&other, "GET", "/sfu", b"", NOW);
+        assert_eq!(seen.insert(&signed, NOW), Ok(()));
+        assert_eq!(seen.insert(&signed, NOW), Err(RequestAuthError::Replayed));
+        assert_eq!(seen.len(), MAX_SEEN_SIGNATURES_PER_SIGNER + 1);
+
+        // The busy identity lost its oldest signature, not the newest
+        let last = RequestSignature::sign(&busy, "POST", "/blob/upload", b"", NOW);
+        assert_eq!(seen.insert(&last, NOW), Ok(()));
+        assert_eq!(seen.insert(&last, NOW), Err(RequestAuthError::Replayed));
+        assert_eq!(seen.insert(&first, NOW), Ok(()));
+    }
+}
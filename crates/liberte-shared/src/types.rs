@@ -35,10 +35,6 @@ impl ChannelId {
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
-
-    pub fn to_topic(&self) -> String {
-        format!("channel:{}", self.0)
-    }
 }
 
 impl Default for ChannelId {
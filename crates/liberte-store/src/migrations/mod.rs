@@ -1,12 +1,14 @@
 pub mod v001_initial;
 pub mod v002_channel_keys;
 pub mod v003_reactions_profile;
+pub mod v004_peer_book;
+pub mod v005_peer_bans;
 
 use rusqlite::Connection;
 
 use crate::error::{Result, StoreError};
 
-const CURRENT_VERSION: u32 = 3;
+const CURRENT_VERSION: u32 = 5;
 
 pub fn run_migrations(conn: &Connection) -> Result<()> {
     let current: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
@@ -35,5 +37,17 @@ pub fn run_migrations(conn: &Connection) -> Result<()> {
         conn.pragma_update(None, "user_version", 3)?;
     }
 
+    if current < 4 {
+        tracing::info!("applying migration v004_peer_book");
+        v004_peer_book::up(conn).map_err(|e| StoreError::Migration(e.to_string()))?;
+        conn.pragma_update(None, "user_version", 4)?;
+    }
+
+    if current < 5 {
+        tracing::info!("applying migration v005_peer_bans");
+        v005_peer_bans::up(conn).map_err(|e| StoreError::Migration(e.to_string()))?;
+        conn.pragma_update(None, "user_version", 5)?;
+    }
+
     Ok(())
 }
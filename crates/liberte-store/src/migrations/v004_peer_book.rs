@@ -0,0 +1,27 @@
+use rusqlite::Connection;
+
+const UP_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS known_peers (
+    peer_id       TEXT PRIMARY KEY NOT NULL,  -- base58 libp2p PeerId
+    success_count INTEGER NOT NULL DEFAULT 0,
+    failure_count INTEGER NOT NULL DEFAULT 0,
+    last_success  TEXT,                       -- ISO-8601, nullable
+    last_failure  TEXT,                       -- ISO-8601, nullable
+    last_seen     TEXT NOT NULL               -- ISO-8601
+);
+
+CREATE INDEX IF NOT EXISTS idx_known_peers_last_seen ON known_peers(last_seen);
+
+CREATE TABLE IF NOT EXISTS peer_addresses (
+    peer_id   TEXT NOT NULL,                  -- FK -> known_peers(peer_id)
+    address   TEXT NOT NULL,                  -- multiaddr string
+    last_seen TEXT NOT NULL,                  -- ISO-8601
+
+    PRIMARY KEY (peer_id, address),
+    FOREIGN KEY (peer_id) REFERENCES known_peers(peer_id) ON DELETE CASCADE
+);
+"#;
+
+pub fn up(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(UP_SQL)
+}
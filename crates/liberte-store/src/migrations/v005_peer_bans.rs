@@ -0,0 +1,13 @@
+use rusqlite::Connection;
+
+const UP_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS banned_peers (
+    peer_id   TEXT PRIMARY KEY NOT NULL,      -- base58 libp2p PeerId
+    reason    TEXT,                           -- free-form, nullable
+    banned_at TEXT NOT NULL                   -- ISO-8601
+);
+"#;
+
+pub fn up(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(UP_SQL)
+}
@@ -57,3 +57,21 @@ pub struct Reaction {
     pub emoji: String,
     pub created_at: DateTime<Utc>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KnownPeer {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    pub success_count: i64,
+    pub failure_count: i64,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BannedPeer {
+    pub peer_id: String,
+    pub reason: Option<String>,
+    pub banned_at: DateTime<Utc>,
+}
@@ -0,0 +1,251 @@
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+
+use crate::database::Database;
+use crate::error::{Result, StoreError};
+use crate::models::{BannedPeer, KnownPeer};
+
+impl Database {
+    /// Record listen addresses learned for a peer (e.g. via identify).
+    pub fn upsert_peer_addresses(&self, peer_id: &str, addresses: &[String]) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+
+        self.conn().execute(
+            "INSERT INTO known_peers (peer_id, last_seen) VALUES (?1, ?2)
+             ON CONFLICT(peer_id) DO UPDATE SET last_seen = excluded.last_seen",
+            params![peer_id, now],
+        )?;
+
+        for address in addresses {
+            self.conn().execute(
+                "INSERT OR REPLACE INTO peer_addresses (peer_id, address, last_seen)
+                 VALUES (?1, ?2, ?3)",
+                params![peer_id, address, now],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn record_peer_success(&self, peer_id: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn().execute(
+            "INSERT INTO known_peers (peer_id, success_count, last_success, last_seen)
+             VALUES (?1, 1, ?2, ?2)
+             ON CONFLICT(peer_id) DO UPDATE SET
+                success_count = success_count + 1,
+                last_success  = excluded.last_success,
+                last_seen     = excluded.last_seen",
+            params![peer_id, now],
+        )?;
+        Ok(())
+    }
+
+    // Failures don't bump last_seen, so unreachable peers age out
+    pub fn record_peer_failure(&self, peer_id: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn().execute(
+            "INSERT INTO known_peers (peer_id, failure_count, last_failure, last_seen)
+             VALUES (?1, 1, ?2, ?2)
+             ON CONFLICT(peer_id) DO UPDATE SET
+                failure_count = failure_count + 1,
+                last_failure  = excluded.last_failure",
+            params![peer_id, now],
+        )?;
+        Ok(())
+    }
+
+    /// Known peers that have at least one address, most reliable first.
+    pub fn list_known_peers(&self, limit: u32) -> Result<Vec<KnownPeer>> {
+        let mut stmt = self.conn().prepare(
+            "SELECT peer_id, success_count, failure_count, last_success, last_failure, last_seen
+             FROM known_peers
+             WHERE EXISTS (SELECT 1 FROM peer_addresses a WHERE a.peer_id = known_peers.peer_id)
+             ORDER BY (success_count - failure_count) DESC, last_seen DESC
+             LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit], row_to_known_peer)?;
+
+        let mut peers = Vec::new();
+        for row in rows {
+            let mut peer = row?;
+            peer.addresses = self.get_peer_addresses(&peer.peer_id)?;
+            peers.push(peer);
+        }
+        Ok(peers)
+    }
+
+    pub fn get_peer_addresses(&self, peer_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn().prepare(
+            "SELECT address FROM peer_addresses WHERE peer_id = ?1 ORDER BY last_seen DESC",
+        )?;
+        let rows = stmt.query_map(params![peer_id], |row| row.get(0))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(StoreError::Sqlite)
+    }
+
+    /// Drop peers and addresses not seen since `cutoff`. Returns removed peer count.
+    pub fn prune_known_peers(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let cutoff = cutoff.to_rfc3339();
+        self.conn().execute(
+            "DELETE FROM peer_addresses WHERE last_seen < ?1",
+            params![cutoff],
+        )?;
+        let removed = self.conn().execute(
+            "DELETE FROM known_peers WHERE last_seen < ?1",
+            params![cutoff],
+        )?;
+        Ok(removed)
+    }
+
+    /// Ban a peer and drop it from the peer book so it is not re-seeded.
+    pub fn ban_peer(&self, peer_id: &str, reason: Option<&str>) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn().execute(
+            "INSERT OR REPLACE INTO banned_peers (peer_id, reason, banned_at)
+             VALUES (?1, ?2, ?3)",
+            params![peer_id, reason, now],
+        )?;
+        self.conn().execute(
+            "DELETE FROM peer_addresses WHERE peer_id = ?1",
+            params![peer_id],
+        )?;
+        self.conn().execute(
+            "DELETE FROM known_peers WHERE peer_id = ?1",
+            params![peer_id],
+        )?;
+        Ok(())
+    }
+
+    /// Returns false if the peer was not banned.
+    pub fn unban_peer(&self, peer_id: &str) -> Result<bool> {
+        let removed = self.conn().execute(
+            "DELETE FROM banned_peers WHERE peer_id = ?1",
+            params![peer_id],
+        )?;
+        Ok(removed > 0)
+    }
+
+    pub fn list_banned_peers(&self) -> Result<Vec<BannedPeer>> {
+        let mut stmt = self.conn().prepare(
+            "SELECT peer_id, reason, banned_at FROM banned_peers ORDER BY banned_at DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let banned_at: String = row.get(2)?;
+            Ok(BannedPeer {
+                peer_id: row.get(0)?,
+                reason: row.get(1)?,
+                banned_at: DateTime::parse_from_rfc3339(&banned_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(
+                            2,
+                            rusqlite::types::Type::Text,
+                            Box::new(e),
+                        )
+                    })?,
+            })
+        })?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(StoreError::Sqlite)
+    }
+}
+
+fn row_to_known_peer(row: &rusqlite::Row<'_>) -> rusqlite::Result<KnownPeer> {
+    let peer_id: String = row.get(0)?;
+    let success_count: i64 = row.get(1)?;
+    let failure_count: i64 = row.get(2)?;
+    let last_success: Option<String> = row.get(3)?;
+    let last_failure: Option<String> = row.get(4)?;
+    let last_seen_str: String = row.get(5)?;
+
+    let parse = |idx: usize, s: &str| {
+        DateTime::parse_from_rfc3339(s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    idx,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })
+    };
+
+    Ok(KnownPeer {
+        peer_id,
+        addresses: Vec::new(),
+        success_count,
+        failure_count,
+        last_success: last_success.map(|s| parse(3, &s)).transpose()?,
+        last_failure: last_failure.map(|s| parse(4, &s)).transpose()?,
+        last_seen: parse(5, &last_seen_str)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn test_db() -> (Database, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(&dir.path().join("test.db"), &[0u8; 32]).unwrap();
+        (db, dir)
+    }
+
+    #[test]
+    fn test_peers_ordered_by_reliability() {
+        let (db, _dir) = test_db();
+        let addr = vec!["/ip4/127.0.0.1/udp/4001/quic-v1".to_string()];
+
+        db.upsert_peer_addresses("good", &addr).unwrap();
+        db.upsert_peer_addresses("bad", &addr).unwrap();
+        db.upsert_peer_addresses("no-addr", &[]).unwrap();
+        db.record_peer_success("good").unwrap();
+        db.record_peer_failure("bad").unwrap();
+
+        let peers = db.list_known_peers(10).unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].peer_id, "good");
+        assert_eq!(peers[0].success_count, 1);
+        assert_eq!(peers[0].addresses, addr);
+        assert_eq!(peers[1].failure_count, 1);
+    }
+
+    #[test]
+    fn test_prune_removes_stale_peers() {
+        let (db, _dir) = test_db();
+        db.upsert_peer_addresses("peer", &["/ip4/10.0.0.1/udp/4001/quic-v1".to_string()])
+            .unwrap();
+
+        assert_eq!(
+            db.prune_known_peers(Utc::now() - Duration::days(1))
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            db.prune_known_peers(Utc::now() + Duration::seconds(1))
+                .unwrap(),
+            1
+        );
+        assert!(db.list_known_peers(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ban_removes_peer_from_book() {
+        let (db, _dir) = test_db();
+        db.upsert_peer_addresses("spammer", &["/ip4/10.0.0.2/udp/4001/quic-v1".to_string()])
+            .unwrap();
+
+        db.ban_peer("spammer", Some("invalid messages")).unwrap();
+        assert!(db.list_known_peers(10).unwrap().is_empty());
+
+        let bans = db.list_banned_peers().unwrap();
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].peer_id, "spammer");
+        assert_eq!(bans[0].reason.as_deref(), Some("invalid messages"));
+
+        assert!(db.unban_peer("spammer").unwrap());
+        assert!(!db.unban_peer("spammer").unwrap());
+        assert!(db.list_banned_peers().unwrap().is_empty());
+    }
+}
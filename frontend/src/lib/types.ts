@@ -71,6 +71,10 @@ export interface UserSettings {
   videoDevice?: string;
   notificationsEnabled: boolean;
   serverUrl: string;
+  useSystemDns?: boolean;
+  dnsUpstreams?: string[];
+  swarmKeyPath?: string | null;
+  rendezvousPoint?: string | null;
 }
 
 /** Public info returned by a Liberté server instance */
@@ -80,6 +84,8 @@ export interface ServerInfo {
   premiumRequired: boolean;
   registrationOpen: boolean;
   maxPeers: number;
+  peerId?: string | null;
+  multiaddrs?: string[];
 }
 
 /** Identity info for display */